@@ -0,0 +1,73 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/. */
+
+//! Feeds arbitrary request paths to `resolve_within` and checks that nothing
+//! ever resolves outside `serve_from`, in either mode.
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+
+use rocket_static_files::resolve_within;
+
+use std::ffi::OsStr;
+use std::fs;
+use std::os::unix::ffi::OsStrExt;
+use std::path::{Component, Path, PathBuf};
+
+/// A small `serve_from`, with a sibling directory that must stay out of
+/// reach.
+fn serve_from() -> PathBuf {
+    let base = std::env::temp_dir().join("rocket-static-files-fuzz");
+    let root = base.join("static");
+
+    fs::create_dir_all(root.join("css")).unwrap();
+    fs::create_dir_all(base.join("secret")).unwrap();
+    fs::write(root.join("index.html"), "").unwrap();
+    fs::write(root.join("css/app.css"), "").unwrap();
+    fs::write(base.join("secret/key"), "").unwrap();
+
+    root.canonicalize().unwrap()
+}
+
+fuzz_target!(|data: &[u8]| {
+    let root = serve_from();
+    let requested = Path::new(OsStr::from_bytes(data));
+
+    if let Ok(target) = resolve_within(&root, requested, false) {
+        assert!(
+            target.starts_with(&root),
+            "{:?} escaped to {:?}",
+            requested,
+            target
+        );
+
+        let canonical = target.canonicalize().unwrap();
+        assert!(
+            canonical.starts_with(&root),
+            "{:?} escaped to {:?}",
+            requested,
+            canonical
+        );
+    }
+
+    if let Ok(target) = resolve_within(&root, requested, true) {
+        assert!(
+            target.starts_with(&root),
+            "{:?} escaped to {:?}",
+            requested,
+            target
+        );
+
+        let relative = target.strip_prefix(&root).unwrap();
+        assert!(
+            relative
+                .components()
+                .all(|c| matches!(c, Component::Normal(_))),
+            "{:?} left {:?} unresolved",
+            requested,
+            relative,
+        );
+    }
+});
@@ -0,0 +1,72 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/. */
+
+use crate::error::{Error, Io};
+
+use snafu::ResultExt;
+
+use std::fmt;
+use std::fs::File;
+use std::io::{self, Read};
+use std::path::Path;
+use std::sync::Mutex;
+
+use zip::result::ZipError;
+use zip::ZipArchive;
+
+/// A zip file standing in for the `serve_from` directory.
+///
+/// Members are read whole into memory, one request at a time.
+pub(crate) struct Archive(Mutex<ZipArchive<File>>);
+
+impl fmt::Debug for Archive {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Archive")
+    }
+}
+
+impl Archive {
+    pub(crate) fn open(path: &Path) -> io::Result<Self> {
+        let file = File::open(path)?;
+        let zip = ZipArchive::new(file).map_err(to_io)?;
+        Ok(Archive(Mutex::new(zip)))
+    }
+
+    /// Whether `name` is a file (not a directory) in the archive.
+    pub(crate) fn is_file(&self, name: &str) -> bool {
+        let mut zip = self.0.lock().unwrap();
+        let member = zip.by_name(name);
+        matches!(member, Ok(m) if !m.name().ends_with('/'))
+    }
+
+    /// Whether `name` is a directory in the archive. The root always is;
+    /// anything else needs an explicit entry.
+    pub(crate) fn is_dir(&self, name: &str) -> bool {
+        if name.is_empty() {
+            return true;
+        }
+
+        let mut zip = self.0.lock().unwrap();
+        let member = zip.by_name(&format!("{}/", name));
+        member.is_ok()
+    }
+
+    pub(crate) fn read(&self, name: &str) -> Result<Vec<u8>, Error> {
+        let mut zip = self.0.lock().unwrap();
+        let mut member = zip.by_name(name).map_err(to_io).context(Io)?;
+
+        let mut contents = Vec::with_capacity(member.size() as usize);
+        member.read_to_end(&mut contents).context(Io)?;
+        Ok(contents)
+    }
+}
+
+/// Fold zip's errors into IO errors, keeping missing members as `NotFound`.
+pub(crate) fn to_io(error: ZipError) -> io::Error {
+    match error {
+        ZipError::Io(e) => e,
+        ZipError::FileNotFound => io::ErrorKind::NotFound.into(),
+        other => io::Error::new(io::ErrorKind::InvalidData, other),
+    }
+}
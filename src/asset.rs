@@ -0,0 +1,140 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/. */
+
+use mime_guess::Mime;
+
+use std::ffi::OsString;
+use std::hash::Hasher;
+use std::path::{Path, PathBuf};
+
+/// Build-time information about a single static file, as written out by
+/// `generate`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Asset {
+    /// Version token appended to links to this file.
+    pub version: &'static str,
+
+    /// Size in bytes of the precompressed `.gz` sibling (or of `embedded`,
+    /// when that's gzipped), if there is one.
+    pub gzip: Option<u64>,
+
+    /// Size in bytes of the precompressed `.br` sibling, if there is one.
+    pub brotli: Option<u64>,
+
+    /// Size in bytes of the precompressed `.zst` sibling, if there is one.
+    pub zstd: Option<u64>,
+
+    /// Subresource Integrity digest (`sha256-...`), if one was generated.
+    pub integrity: Option<&'static str>,
+
+    /// The file's contents, if the generator embedded them. Otherwise the
+    /// file is read from `serve_from`.
+    pub embedded: Option<&'static [u8]>,
+
+    /// Size in bytes of the file itself when `embedded` holds it gzipped
+    /// rather than as-is.
+    pub embedded_size: Option<u64>,
+}
+
+impl Asset {
+    pub(crate) fn has_variants(&self) -> bool {
+        self.gzip.is_some() || self.brotli.is_some() || self.zstd.is_some()
+    }
+
+    /// Whether the map lists a sibling in `encoding`. Dictionary-compressed
+    /// ones never are; they're looked for on disk when a client asks.
+    pub(crate) fn has(&self, encoding: Encoding) -> bool {
+        match encoding {
+            Encoding::Gzip => self.gzip.is_some(),
+            Encoding::Brotli => self.brotli.is_some(),
+            Encoding::Zstd => self.zstd.is_some(),
+            Encoding::DictionaryBrotli => false,
+        }
+    }
+}
+
+/// A single token for a whole set of `(path, version)` pairs, in any order:
+/// SipHash-2-4 (with zero keys) over the sorted pairs, in hex.
+///
+/// Computed the same way by the generator and at runtime, so the two agree.
+pub(crate) fn bundle_hash<'a, I>(entries: I) -> String
+where
+    I: IntoIterator<Item = (&'a str, &'a str)>,
+{
+    let mut entries: Vec<_> = entries.into_iter().collect();
+    entries.sort_unstable();
+
+    // `DefaultHasher` makes no promise to stay the same between releases.
+    #[allow(deprecated)]
+    let mut hasher = std::hash::SipHasher::new();
+
+    for (path, version) in entries {
+        hasher.write(path.as_bytes());
+        hasher.write_u8(0);
+        hasher.write(version.as_bytes());
+        hasher.write_u8(0);
+    }
+
+    format!("{:016x}", hasher.finish())
+}
+
+/// A content coding a precompressed sibling can be stored in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Encoding {
+    /// Brotli against a shared dictionary the client already has (`dcb`).
+    DictionaryBrotli,
+    Brotli,
+    Zstd,
+    Gzip,
+}
+
+impl Encoding {
+    /// Encodings in order of preference when the client accepts several.
+    pub(crate) const PREFERENCE: [Encoding; 4] = [
+        Encoding::DictionaryBrotli,
+        Encoding::Brotli,
+        Encoding::Zstd,
+        Encoding::Gzip,
+    ];
+
+    /// Token used in `Accept-Encoding` and `Content-Encoding`.
+    pub(crate) fn name(self) -> &'static str {
+        match self {
+            Encoding::DictionaryBrotli => "dcb",
+            Encoding::Brotli => "br",
+            Encoding::Zstd => "zstd",
+            Encoding::Gzip => "gzip",
+        }
+    }
+
+    /// Extension appended to the original file name for the sibling.
+    pub(crate) fn extension(self) -> &'static str {
+        match self {
+            Encoding::DictionaryBrotli => "dcb",
+            Encoding::Brotli => "br",
+            Encoding::Zstd => "zst",
+            Encoding::Gzip => "gz",
+        }
+    }
+
+    /// Path of the precompressed sibling of `path`.
+    pub(crate) fn sibling(self, path: &Path) -> PathBuf {
+        let mut name = OsString::from(path.as_os_str());
+        name.push(".");
+        name.push(self.extension());
+        PathBuf::from(name)
+    }
+}
+
+/// Whether `mime` is some flavour of text: `text/*`, scripts, JSON, XML, ...
+pub(crate) fn is_text(mime: &Mime) -> bool {
+    let suffix = mime.suffix().map(|s| s.as_str());
+
+    match (mime.type_().as_str(), mime.subtype().as_str()) {
+        ("text", _) => true,
+        ("application", "javascript") | ("application", "json") => true,
+        ("application", "xml") => true,
+        _ => suffix == Some("xml") || suffix == Some("json"),
+    }
+}
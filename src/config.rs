@@ -0,0 +1,1037 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/. */
+
+use crate::asset;
+use crate::glob::Glob;
+use crate::headers;
+use crate::lexical;
+
+use mime_guess::{mime, Mime};
+
+use rocket::http::hyper::header::{CacheControl, CacheDirective, Header};
+use rocket::http::uri::{Absolute, Uri};
+use rocket::http::RawStr;
+use rocket::Request;
+
+use serde::de::Error as _;
+use serde::{Deserialize, Deserializer, Serialize};
+
+use std::collections::HashMap;
+use std::ops::Deref;
+use std::path::{Path, PathBuf};
+
+/// The `static_files` section of `Rocket.toml`.
+#[derive(Debug, Serialize, Deserialize)]
+pub(crate) struct Config {
+    pub(crate) serve_from: ServeFrom,
+    pub(crate) path_prefix: String,
+
+    /// Add cache headers to responses under `path_prefix` from other routes.
+    #[serde(default)]
+    pub(crate) inject_headers: bool,
+
+    /// Seconds a stale, unversioned file may be served while revalidating.
+    #[serde(default)]
+    pub(crate) stale_while_revalidate: Option<u32>,
+
+    /// Seconds a stale, unversioned file may be served if revalidation fails.
+    #[serde(default)]
+    pub(crate) stale_if_error: Option<u32>,
+
+    /// Paths that always have a stable, unversioned URL.
+    #[serde(default)]
+    pub(crate) pin: Vec<String>,
+
+    /// Directories (relative to `serve_from`) whose files are linked without
+    /// a version token but still cached like versioned ones (and marked
+    /// `immutable`), for content-named files at fixed URLs such as fonts.
+    #[serde(default)]
+    pub(crate) no_token_dirs: Vec<String>,
+
+    /// Link files in the map as `<path_prefix>/<bundle hash>/<path>`, with a
+    /// single token for the whole set, instead of each with its own `?v=`.
+    #[serde(default)]
+    pub(crate) bundle_path: bool,
+
+    /// Content types (like `text/html`, or `text/*`) always sent with
+    /// `no-cache`, even at their current version.
+    #[serde(default)]
+    pub(crate) never_cache_types: Vec<String>,
+
+    /// File name suffixes (like `"@2x"`) by device pixel ratio (like `"2"`).
+    /// When set, a request for `img.png` is answered with the `img@2x.png`
+    /// (or other) variant matching the client's `Sec-CH-DPR` hint.
+    #[serde(default)]
+    pub(crate) dpr_variants: HashMap<String, String>,
+
+    /// Answer requests with query parameters other than `version_param`
+    /// with `400`, so random query strings can't be used to bypass caches.
+    #[serde(default)]
+    pub(crate) reject_unknown_query: bool,
+
+    /// Send `X-Cache-Status` (`immutable`, `revalidated`, `miss` or
+    /// `redirect`) on responses, for log pipelines to count.
+    #[serde(default)]
+    pub(crate) cache_status_header: bool,
+
+    /// `Timing-Allow-Origin` for every file (like `*`, or an origin), so
+    /// Resource Timing measurements from other origins see the details.
+    #[serde(default)]
+    pub(crate) timing_allow_origin: Option<String>,
+
+    /// Send an `Expires` header matching `max-age`, for HTTP/1.0 caches.
+    #[serde(default)]
+    pub(crate) expires: bool,
+
+    /// Check requested paths lexically instead of with `canonicalize`.
+    ///
+    /// **This follows symbolic links inside `serve_from` wherever they
+    /// point**, so only enable it when nothing untrusted can create links
+    /// there.
+    #[serde(default)]
+    pub(crate) skip_canonicalize: bool,
+
+    /// Path the whole application is deployed under, e.g. behind a reverse
+    /// proxy. Prepended to every generated URL, but not to the mount point.
+    #[serde(default)]
+    pub(crate) base_href: Option<String>,
+
+    /// Drop a leading UTF-8 byte order mark from text files when serving.
+    #[serde(default)]
+    pub(crate) strip_bom: bool,
+
+    /// Content type for files `mime_guess` can't place, instead of
+    /// `application/octet-stream`.
+    #[serde(default)]
+    pub(crate) default_content_type: Option<String>,
+
+    /// `charset` parameter to add to text content types (`text/*`, scripts,
+    /// JSON, XML) that don't come with one.
+    #[serde(default)]
+    pub(crate) default_charset: Option<String>,
+
+    /// `charset` parameters by content type (like `text/plain`, or
+    /// `text/*`), overriding `default_charset`.
+    #[serde(default)]
+    pub(crate) charset_by_type: HashMap<String, String>,
+
+    /// Content type for source maps (`.map`, including `.js.map` and
+    /// `.css.map`).
+    #[serde(default = "default_source_map_content_type")]
+    pub(crate) source_map_content_type: String,
+
+    /// Read extra response headers from a `_headers` file in `serve_from`.
+    #[serde(default)]
+    pub(crate) sidecar_headers: bool,
+
+    /// Name of the query parameter carrying the version token.
+    #[serde(default = "default_version_param")]
+    pub(crate) version_param: String,
+
+    /// Assets announced with `Link: rel=preload` on every HTML file served.
+    #[serde(default)]
+    pub(crate) preload: Vec<Preload>,
+
+    /// Match request paths against the map without regard to case, serving
+    /// whichever file the map lists.
+    #[serde(default)]
+    pub(crate) case_insensitive_lookup: bool,
+
+    /// Log a warning (with the `log` feature) whenever opening a file and
+    /// reading its first chunk takes at least this many milliseconds.
+    #[serde(default)]
+    pub(crate) slow_open_ms: Option<u64>,
+
+    /// Files served at the root of the site (e.g. `favicon.ico`), mapped to
+    /// where they live under `serve_from`.
+    #[serde(default)]
+    pub(crate) root_aliases: HashMap<String, String>,
+
+    /// Mount [`StaticFiles::routes`](crate::StaticFiles::routes) at
+    /// `path_prefix` on attach.
+    #[serde(default = "default_auto_mount")]
+    pub(crate) auto_mount: bool,
+
+    /// File served in place of anything missing, for single-page apps that
+    /// route on the client.
+    #[serde(default)]
+    pub(crate) spa_fallback: Option<String>,
+
+    /// Globs for paths that are real assets, and 404 when missing instead of
+    /// falling back to `spa_fallback`.
+    #[serde(default)]
+    pub(crate) spa_exclude: Vec<String>,
+
+    /// File (relative to `serve_from`, or absolute) sent as the body of
+    /// `500`s, instead of leaving them to Rocket's catcher.
+    #[serde(default)]
+    pub(crate) server_error_page: Option<PathBuf>,
+
+    /// Seconds clients may cache a redirect to a file's current version.
+    /// Kept short, since the redirect goes stale as soon as the file changes.
+    #[serde(default = "default_redirect_max_age")]
+    pub(crate) redirect_max_age: u32,
+
+    /// File name served for requests naming a directory, like `index.html`.
+    #[serde(default)]
+    pub(crate) index_file: Option<String>,
+
+    /// `Cache-Control` values for everything under a directory (relative to
+    /// `serve_from`), replacing the usual ones. The longest match wins.
+    #[serde(default)]
+    pub(crate) directory_cache: HashMap<String, String>,
+
+    /// Version links to files missing from the map with a `size-mtime` token
+    /// from a stat, so files added after the build still get cache-busted.
+    #[serde(default)]
+    pub(crate) stat_tokens: bool,
+
+    /// Answer other methods under `path_prefix` with `405 Method Not Allowed`
+    /// instead of letting them 404.
+    #[serde(default)]
+    pub(crate) method_not_allowed: bool,
+
+    /// Longest request path (in bytes, under `path_prefix`) looked up at all.
+    #[serde(default = "default_max_path_length")]
+    pub(crate) max_path_length: usize,
+
+    /// Most segments a request path may have before it's rejected.
+    #[serde(default = "default_max_path_segments")]
+    pub(crate) max_path_segments: usize,
+
+    /// Path (under `path_prefix`) of a JSON index of every asset, with its
+    /// version and URL, for tooling that needs them at runtime.
+    #[serde(default)]
+    pub(crate) manifest_path: Option<String>,
+
+    /// Headers left to another fairing (like `SpaceHelmet`), and dropped from
+    /// `_headers` rules and the like. Matched without regard to case.
+    #[serde(default)]
+    pub(crate) defer_headers: Vec<String>,
+
+    /// Redirect `HEAD` requests for stale versions like `GET`s. When off,
+    /// they're answered with the current file's headers, uncached.
+    #[serde(default = "default_redirect_head")]
+    pub(crate) redirect_head: bool,
+
+    /// Redirect requests with a missing or stale version token to the current
+    /// one. When off, the current file is served directly, uncached.
+    #[serde(default = "default_version_redirect")]
+    pub(crate) version_redirect: bool,
+
+    /// Serve versioned files requested without a token directly (and
+    /// uncached), only redirecting requests with a stale token.
+    #[serde(default)]
+    pub(crate) serve_unversioned: bool,
+
+    /// Full `Cache-Control` directive lists, replacing the built-in ones.
+    #[serde(default)]
+    pub(crate) cache_directives: CacheDirectives,
+
+    /// Globs for paths with a segment starting with `.` (like
+    /// `.well-known/security.txt`) that are served anyway. Every other dotfile
+    /// is hidden, and left out of the map unless the build script passes the
+    /// same globs to `Generator::dotfiles_allowlist`.
+    #[serde(default)]
+    pub(crate) serve_dotfiles_allowlist: Vec<String>,
+
+    /// Content types for exact paths (relative to `serve_from`), checked
+    /// before guessing from the extension.
+    #[serde(default)]
+    pub(crate) content_type_paths: HashMap<String, String>,
+
+    /// Scheme and host (like `https://example.com`) to make redirects to a
+    /// file's real URL absolute. Relative redirects are sent without it.
+    #[serde(default)]
+    pub(crate) redirect_base: Option<String>,
+
+    /// Refuse to launch if anything in the map is missing from `serve_from`,
+    /// logging what is with the `log` feature.
+    #[serde(default)]
+    pub(crate) strict: bool,
+
+    /// Answer `OPTIONS` under `path_prefix` with `204` and an `Allow` header.
+    #[serde(default)]
+    pub(crate) answer_options: bool,
+
+    /// Point links under `path_prefix` in HTML responses (from any route) at
+    /// their versioned URLs.
+    #[serde(default)]
+    pub(crate) rewrite_html: bool,
+
+    /// Largest HTML body `rewrite_html` reads in; bigger ones are left alone.
+    #[serde(default = "default_rewrite_html_max_bytes")]
+    pub(crate) rewrite_html_max_bytes: u64,
+
+    /// `Content-Language` for files under each directory (relative to
+    /// `serve_from`, like `"fr"`). The longest matching directory wins.
+    #[serde(default)]
+    pub(crate) content_language_map: HashMap<String, String>,
+
+    /// Files (globs relative to `serve_from`) for clients to store as
+    /// compression dictionaries, each with the URL pattern they apply to,
+    /// sent as `Use-As-Dictionary`.
+    #[serde(default)]
+    pub(crate) dictionaries: HashMap<String, String>,
+}
+
+/// The `cache_directives` table. Directives `hyper` doesn't know are passed
+/// through as-is.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub(crate) struct CacheDirectives {
+    /// For requests with the current version token.
+    #[serde(default)]
+    pub(crate) versioned: Option<Vec<String>>,
+
+    /// For everything else, instead of `stale_while_revalidate` and
+    /// `stale_if_error`.
+    #[serde(default)]
+    pub(crate) unversioned: Option<Vec<String>>,
+}
+
+impl CacheDirectives {
+    fn list(&self, cache: bool) -> Option<&Vec<String>> {
+        if cache {
+            self.versioned.as_ref()
+        } else {
+            self.unversioned.as_ref()
+        }
+    }
+
+    fn get(&self, cache: bool) -> Option<CacheControl> {
+        parse_cache_control(&self.list(cache)?.join(", "))
+    }
+
+    /// Whether every list given parses (which an empty one doesn't).
+    fn is_valid(&self) -> bool {
+        [true, false]
+            .iter()
+            .all(|&cache| self.list(cache).is_none() || self.get(cache).is_some())
+    }
+}
+
+/// `serve_from`: a directory, or a list of them searched in order, so that
+/// files in earlier ones (a theme, say) override those in later ones.
+///
+/// Derefs to the first directory, which is where anything that only has a
+/// single root (the `_headers` file, a zip archive) is looked for.
+#[derive(Debug, Clone, Serialize)]
+pub(crate) struct ServeFrom(Vec<PathBuf>);
+
+impl ServeFrom {
+    /// Every directory, in the order they're searched.
+    pub(crate) fn dirs(&self) -> &[PathBuf] {
+        &self.0
+    }
+
+    /// `path` relative to whichever directory it's under.
+    pub(crate) fn relative<'p>(&self, path: &'p Path) -> &'p Path {
+        self.0
+            .iter()
+            .find_map(|dir| path.strip_prefix(dir).ok())
+            .unwrap_or(path)
+    }
+
+    /// Where `path` (relative to `serve_from`) is found on disk: under the
+    /// first directory that has it, or the first directory if none do.
+    pub(crate) fn find(&self, path: &Path) -> PathBuf {
+        self.0
+            .iter()
+            .map(|dir| dir.join(path))
+            .find(|full| full.exists())
+            .unwrap_or_else(|| self.0[0].join(path))
+    }
+}
+
+impl From<Vec<PathBuf>> for ServeFrom {
+    /// `dirs` must not be empty.
+    fn from(dirs: Vec<PathBuf>) -> Self {
+        assert!(!dirs.is_empty(), "serve_from needs a directory");
+        ServeFrom(dirs)
+    }
+}
+
+impl Deref for ServeFrom {
+    type Target = Path;
+
+    fn deref(&self) -> &Path {
+        &self.0[0]
+    }
+}
+
+impl AsRef<Path> for ServeFrom {
+    fn as_ref(&self) -> &Path {
+        self
+    }
+}
+
+impl<'de> Deserialize<'de> for ServeFrom {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        #[derive(Deserialize)]
+        #[serde(untagged)]
+        enum Raw {
+            One(PathBuf),
+            Chain(Vec<PathBuf>),
+        }
+
+        match Raw::deserialize(deserializer)? {
+            Raw::One(dir) => Ok(ServeFrom(vec![dir])),
+            Raw::Chain(dirs) if !dirs.is_empty() => Ok(ServeFrom(dirs)),
+            Raw::Chain(_) => Err(D::Error::custom("serve_from needs a directory")),
+        }
+    }
+}
+
+/// The effective `static_files` settings, as resolved when the fairing was
+/// attached. Returned by [`StaticFiles::config`](crate::StaticFiles::config).
+#[derive(Debug, Clone)]
+pub struct ConfigView {
+    serve_from: PathBuf,
+    path_prefix: String,
+    url_prefix: String,
+    version_param: String,
+}
+
+impl ConfigView {
+    pub(crate) fn new(config: &Config) -> Self {
+        ConfigView {
+            serve_from: config.serve_from.to_path_buf(),
+            path_prefix: config.path_prefix.clone(),
+            url_prefix: config.url_prefix(),
+            version_param: config.version_param.clone(),
+        }
+    }
+
+    /// `serve_from` (the first directory, if it's a list), canonicalized, so
+    /// always absolute.
+    pub fn serve_from(&self) -> &Path {
+        &self.serve_from
+    }
+
+    /// Where the files are mounted, exactly as configured.
+    pub fn path_prefix(&self) -> &str {
+        &self.path_prefix
+    }
+
+    /// What links to files start with: `base_href` followed by `path_prefix`.
+    pub fn url_prefix(&self) -> &str {
+        &self.url_prefix
+    }
+
+    /// Name of the query parameter carrying the version token.
+    pub fn version_param(&self) -> &str {
+        &self.version_param
+    }
+}
+
+/// Most `preload` entries sent, however many are configured.
+pub(crate) const MAX_PRELOADS: usize = 8;
+
+/// An entry in `preload`.
+#[derive(Debug, Serialize, Deserialize)]
+pub(crate) struct Preload {
+    pub(crate) path: String,
+
+    /// The `as` destination: `style`, `script`, `font`, ...
+    #[serde(rename = "as")]
+    pub(crate) kind: String,
+}
+
+/// Whether `b` may appear anywhere in a URL without being percent-encoded.
+fn unreserved(b: u8) -> bool {
+    b.is_ascii_alphanumeric() || b"-._~".contains(&b)
+}
+
+fn parse_cache_control(value: &str) -> Option<CacheControl> {
+    CacheControl::parse_header(&[value.as_bytes().to_vec()]).ok()
+}
+
+fn default_version_param() -> String {
+    "v".into()
+}
+
+fn default_auto_mount() -> bool {
+    true
+}
+
+fn default_redirect_max_age() -> u32 {
+    300
+}
+
+fn default_redirect_head() -> bool {
+    true
+}
+
+fn default_source_map_content_type() -> String {
+    "application/json".into()
+}
+
+fn default_version_redirect() -> bool {
+    true
+}
+
+fn default_rewrite_html_max_bytes() -> u64 {
+    1024 * 1024
+}
+
+fn default_max_path_length() -> usize {
+    1024
+}
+
+fn default_max_path_segments() -> usize {
+    32
+}
+
+impl Config {
+    /// Split a request path into the part under `path_prefix`, without a
+    /// leading slash.
+    pub(crate) fn relative_path<'p>(&self, path: &'p str) -> Option<&'p str> {
+        let prefix = self.path_prefix.trim_end_matches('/');
+        let rest = path.strip_prefix(prefix)?;
+        rest.strip_prefix('/')
+    }
+
+    /// What links to files should start with: `base_href` and `path_prefix`.
+    pub(crate) fn url_prefix(&self) -> String {
+        let base = self.base_href.as_deref().unwrap_or_default();
+        format!("{}{}", base.trim_end_matches('/'), self.path_prefix)
+    }
+
+    /// Check the parts of the config that can't be validated by serde.
+    pub(crate) fn validate(&self) -> bool {
+        let index = match &self.index_file {
+            Some(index) => {
+                !index.is_empty() && !index.contains('/') && index != "." && index != ".."
+            }
+            None => true,
+        };
+
+        let content_type = match &self.default_content_type {
+            Some(default) => default.parse::<Mime>().is_ok(),
+            None => true,
+        };
+
+        let content_type_paths = self
+            .content_type_paths
+            .values()
+            .chain(Some(&self.source_map_content_type))
+            .all(|t| t.parse::<Mime>().is_ok());
+
+        // Only unreserved characters, so the name never needs escaping.
+        let param = !self.version_param.is_empty() && self.version_param.bytes().all(unreserved);
+
+        let preload = self
+            .preload
+            .iter()
+            .all(|p| !p.kind.is_empty() && p.kind.bytes().all(|b| b.is_ascii_alphabetic()));
+
+        let directory_cache = self
+            .directory_cache
+            .values()
+            .all(|v| parse_cache_control(v).is_some());
+
+        let cache_directives = self.cache_directives.is_valid();
+
+        let redirect_base = match &self.redirect_base {
+            Some(base) => Absolute::parse(base).is_ok(),
+            None => true,
+        };
+
+        let charset = |c: &String| {
+            !c.is_empty()
+                && c.bytes()
+                    .all(|b| b.is_ascii_alphanumeric() || b"-_.:".contains(&b))
+        };
+        let charsets =
+            self.default_charset.iter().all(charset) && self.charset_by_type.values().all(charset);
+
+        let dpr_variants = self.dpr_variants.keys().all(|ratio| {
+            ratio
+                .parse::<f64>()
+                .map(|r| r.is_finite() && r > 0.0)
+                .unwrap_or(false)
+        });
+
+        let content_language_map = self.content_language_map.values().all(|l| {
+            l.split('-')
+                .all(|part| !part.is_empty() && part.bytes().all(|b| b.is_ascii_alphanumeric()))
+        });
+
+        // Sent inside a quoted string.
+        let dictionaries = self.dictionaries.values().all(|m| {
+            !m.is_empty() && !m.contains(|c: char| c == '"' || c == '\\' || c.is_control())
+        });
+
+        let timing_allow_origin = self
+            .timing_allow_origin
+            .iter()
+            .all(|o| !o.is_empty() && !o.contains(|c: char| c.is_control()));
+
+        content_type
+            && content_type_paths
+            && param
+            && preload
+            && index
+            && directory_cache
+            && cache_directives
+            && redirect_base
+            && content_language_map
+            && dpr_variants
+            && charsets
+            && dictionaries
+            && timing_allow_origin
+    }
+
+    /// The version token `request` asked for, if any.
+    pub(crate) fn requested_version(&self, request: &Request) -> Option<String> {
+        request
+            .get_query_value::<String>(&self.version_param)
+            .and_then(Result::ok)
+    }
+
+    /// Whether `query` has any parameter besides `version_param`.
+    pub(crate) fn has_unknown_query(&self, query: Option<&str>) -> bool {
+        let query = match query {
+            Some(query) => query,
+            None => return false,
+        };
+
+        query.split('&').filter(|p| !p.is_empty()).any(|param| {
+            let name = param.split('=').next().unwrap_or_default();
+            let name = RawStr::from_str(name).percent_decode_lossy();
+            name != self.version_param.as_str()
+        })
+    }
+
+    /// Query string (including the `?`) selecting version `token`.
+    pub(crate) fn version_query(&self, token: &str) -> String {
+        let escaped: String = token
+            .bytes()
+            .map(|b| {
+                if unreserved(b) {
+                    (b as char).to_string()
+                } else {
+                    format!("%{:02X}", b)
+                }
+            })
+            .collect();
+
+        format!("?{}={}", self.version_param, escaped)
+    }
+
+    /// Full link to `path` (relative to `serve_from`), with each path
+    /// segment percent-encoded.
+    pub(crate) fn file_url(&self, path: &str) -> String {
+        let segments: Vec<_> = path.split('/').map(Uri::percent_encode).collect();
+        format!("{}/{}", self.url_prefix(), segments.join("/"))
+    }
+
+    /// Like [`Config::file_url`], under the bundle hash `bundle`.
+    pub(crate) fn bundled_url(&self, path: &str, bundle: &str) -> String {
+        let segments: Vec<_> = path.split('/').map(Uri::percent_encode).collect();
+        format!("{}/{}/{}", self.url_prefix(), bundle, segments.join("/"))
+    }
+
+    /// Like [`Config::file_url`], at version `token`.
+    pub(crate) fn versioned_url(&self, path: &str, token: &str) -> String {
+        format!("{}{}", self.file_url(path), self.version_query(token))
+    }
+
+    /// Where to redirect to for `url` (from [`Config::file_url`] and the
+    /// like), honouring `redirect_base`.
+    pub(crate) fn redirect_url(&self, url: String) -> String {
+        match &self.redirect_base {
+            Some(base) => format!("{}{}", base.trim_end_matches('/'), url),
+            None => url,
+        }
+    }
+
+    /// Guess the content type of the file at `path`.
+    pub(crate) fn mime(&self, path: &Path) -> Mime {
+        let exact = self
+            .serve_from
+            .relative(path)
+            .to_str()
+            .and_then(|p| self.content_type_paths.get(p))
+            .and_then(|t| t.parse().ok());
+
+        if let Some(exact) = exact {
+            return exact;
+        }
+
+        // `mime_guess` has nothing for source maps, but devtools want JSON.
+        if path.extension().map(|e| e == "map").unwrap_or(false) {
+            if let Ok(mime) = self.source_map_content_type.parse() {
+                return mime;
+            }
+        }
+
+        // `WebAssembly.instantiateStreaming` rejects anything else, and older
+        // `mime_guess` releases don't know the extension.
+        if path.extension().map(|e| e == "wasm").unwrap_or(false) {
+            return "application/wasm".parse().unwrap();
+        }
+
+        let guess = mime_guess::from_path(path).first();
+
+        let default = self
+            .default_content_type
+            .as_deref()
+            .and_then(|d| d.parse().ok());
+
+        match (guess, default) {
+            (Some(guess), Some(default)) if guess == mime::APPLICATION_OCTET_STREAM => default,
+            (Some(guess), _) => guess,
+            (None, Some(default)) => default,
+            (None, None) => mime::APPLICATION_OCTET_STREAM,
+        }
+    }
+
+    /// `Content-Language` of the file at `path`, from `content_language_map`.
+    pub(crate) fn content_language(&self, path: &Path) -> Option<&str> {
+        let path = self.serve_from.relative(path);
+
+        self.content_language_map
+            .iter()
+            .filter(|(dir, _)| path.starts_with(dir.trim_matches('/')))
+            .max_by_key(|(dir, _)| dir.trim_matches('/').len())
+            .map(|(_, language)| language.as_str())
+    }
+
+    /// The `Use-As-Dictionary` value for `path`, per `dictionaries`.
+    pub(crate) fn use_as_dictionary(&self, path: &str) -> Option<String> {
+        self.dictionaries
+            .iter()
+            .find(|(glob, _)| Glob::new(glob.trim_start_matches('/')).matches(path))
+            .map(|(_, pattern)| format!("match=\"{}\"", pattern))
+    }
+
+    /// The `charset` to add to `mime`, per `charset_by_type` and
+    /// `default_charset`. `None` if it already has one.
+    pub(crate) fn charset(&self, mime: &Mime) -> Option<&str> {
+        if mime.get_param(mime::CHARSET).is_some() {
+            return None;
+        }
+
+        let any_subtype = format!("{}/*", mime.type_());
+        let configured = self
+            .charset_by_type
+            .iter()
+            .find(|(t, _)| t.eq_ignore_ascii_case(mime.essence_str()))
+            .or_else(|| {
+                self.charset_by_type
+                    .iter()
+                    .find(|(t, _)| t.eq_ignore_ascii_case(&any_subtype))
+            });
+
+        match configured {
+            Some((_, charset)) => Some(charset),
+            None if asset::is_text(mime) => self.default_charset.as_deref(),
+            None => None,
+        }
+    }
+
+    /// Whether the file at `path` is served as one of `never_cache_types`.
+    pub(crate) fn never_caches(&self, path: &Path) -> bool {
+        if self.never_cache_types.is_empty() {
+            return false;
+        }
+
+        let mime = self.mime(path);
+        let any_subtype = format!("{}/*", mime.type_());
+
+        self.never_cache_types.iter().any(|t| {
+            t.eq_ignore_ascii_case(mime.essence_str()) || t.eq_ignore_ascii_case(&any_subtype)
+        })
+    }
+
+    /// Whether the file at `path` is served as WebAssembly.
+    pub(crate) fn is_wasm(&self, path: &Path) -> bool {
+        self.mime(path).essence_str() == "application/wasm"
+    }
+
+    /// Whether the file at `path` is served as HTML.
+    pub(crate) fn is_html(&self, path: &Path) -> bool {
+        let guess = self.mime(path);
+        guess.type_() == mime::TEXT && guess.subtype() == mime::HTML
+    }
+
+    /// The file to serve in place of `path` (relative to `serve_from`) if it
+    /// doesn't exist.
+    pub(crate) fn fallback_for(&self, path: &str) -> Option<&str> {
+        let index = self.spa_fallback.as_deref()?;
+        let name = path.rsplit('/').next().unwrap_or(path);
+
+        let excluded = if self.spa_exclude.is_empty() {
+            // Without a list, anything with an extension is taken for an asset.
+            name.contains('.')
+        } else {
+            // Like `.gitignore`, patterns without a `/` match the file name.
+            self.spa_exclude.iter().any(|p| {
+                let glob = Glob::new(p.trim_start_matches('/'));
+                glob.matches(path) || (!p.contains('/') && glob.matches(name))
+            })
+        };
+
+        if excluded {
+            None
+        } else {
+            Some(index.trim_start_matches('/'))
+        }
+    }
+
+    /// Whether `path` is short enough to be worth resolving.
+    pub(crate) fn within_limits(&self, path: &Path) -> bool {
+        path.as_os_str().len() <= self.max_path_length
+            && path.components().count() <= self.max_path_segments
+    }
+
+    /// Whether `path` (relative to `serve_from`) is where the manifest is
+    /// served.
+    pub(crate) fn is_manifest(&self, path: &str) -> bool {
+        self.manifest_path
+            .as_deref()
+            .map(|m| m.trim_start_matches('/'))
+            == Some(path)
+    }
+
+    /// The `Allow` header value for static files.
+    pub(crate) fn allowed_methods(&self) -> &'static str {
+        if self.answer_options {
+            "GET, HEAD, OPTIONS"
+        } else {
+            "GET, HEAD"
+        }
+    }
+
+    /// Whether the header `name` is listed in `defer_headers`.
+    pub(crate) fn defers(&self, name: &str) -> bool {
+        self.defer_headers
+            .iter()
+            .any(|d| d.eq_ignore_ascii_case(name))
+    }
+
+    /// Whether `path` (relative to `serve_from`) must never be served: the
+    /// `_headers` file, and dotfiles not in `serve_dotfiles_allowlist`.
+    pub(crate) fn is_hidden(&self, path: &str) -> bool {
+        let dotted = lexical::is_dotted(Path::new(path)) && !self.allows_dotfile(path);
+        dotted || (self.sidecar_headers && path == headers::FILE_NAME)
+    }
+
+    /// Whether the dotfile `path` (relative to `serve_from`) matches
+    /// `serve_dotfiles_allowlist`.
+    pub(crate) fn allows_dotfile(&self, path: &str) -> bool {
+        self.serve_dotfiles_allowlist
+            .iter()
+            .any(|p| Glob::new(p.trim_start_matches('/')).matches(path))
+    }
+
+    /// Whether `path` (relative to `serve_from`) is listed in `pin`.
+    pub(crate) fn is_pinned(&self, path: &str) -> bool {
+        self.pin.iter().any(|p| p.trim_start_matches('/') == path)
+    }
+
+    /// Whether `path` (relative to `serve_from`) is under one of
+    /// `no_token_dirs`.
+    pub(crate) fn is_tokenless(&self, path: &str) -> bool {
+        self.no_token_dirs.iter().any(|dir| {
+            let dir = dir.trim_matches('/');
+            dir.is_empty()
+                || path
+                    .strip_prefix(dir)
+                    .map(|rest| rest.starts_with('/'))
+                    .unwrap_or(false)
+        })
+    }
+
+    /// `Cache-Control` for the file at `path` (relative to `serve_from`),
+    /// honouring `directory_cache`.
+    pub(crate) fn cache_control_for(&self, path: &str, cache: bool) -> CacheControl {
+        let policy = self
+            .directory_cache
+            .iter()
+            .map(|(dir, value)| (dir.trim_matches('/'), value))
+            .filter(|(dir, _)| {
+                dir.is_empty()
+                    || path
+                        .strip_prefix(dir)
+                        .map(|rest| rest.starts_with('/'))
+                        .unwrap_or(false)
+            })
+            .max_by_key(|(dir, _)| dir.len())
+            .and_then(|(_, value)| parse_cache_control(value));
+
+        policy.unwrap_or_else(|| self.cache_control(cache))
+    }
+
+    /// `Cache-Control` for the file at `path` under `no_token_dirs`: cached
+    /// like a versioned file, and `immutable` too, since there's no token for
+    /// a reload to revalidate.
+    pub(crate) fn tokenless_cache_control(&self, path: &str) -> CacheControl {
+        let mut cache_control = self.cache_control_for(path, true);
+        let directives = &mut cache_control.0;
+
+        let long_lived = directives
+            .iter()
+            .any(|d| matches!(d, CacheDirective::MaxAge(_)));
+        let immutable = directives
+            .iter()
+            .any(|d| matches!(d, CacheDirective::Extension(name, None) if name == "immutable"));
+
+        if long_lived && !immutable {
+            directives.push(CacheDirective::Extension("immutable".into(), None));
+        }
+
+        cache_control
+    }
+
+    pub(crate) fn redirect_cache_control(&self) -> CacheControl {
+        CacheControl(vec![CacheDirective::MaxAge(self.redirect_max_age)])
+    }
+
+    pub(crate) fn cache_control(&self, cache: bool) -> CacheControl {
+        if let Some(configured) = self.cache_directives.get(cache) {
+            return configured;
+        }
+
+        if cache {
+            return CacheControl(vec![CacheDirective::MaxAge(31536000)]);
+        }
+
+        let mut directives = vec![];
+
+        if let Some(secs) = self.stale_while_revalidate {
+            directives.push(CacheDirective::Extension(
+                "stale-while-revalidate".into(),
+                Some(secs.to_string()),
+            ));
+        }
+
+        if let Some(secs) = self.stale_if_error {
+            directives.push(CacheDirective::Extension(
+                "stale-if-error".into(),
+                Some(secs.to_string()),
+            ));
+        }
+
+        // Without any stale allowance, revalidate every time rather than send
+        // an empty header and leave it to the cache's heuristics.
+        if directives.is_empty() {
+            directives.push(CacheDirective::NoCache);
+        }
+
+        CacheControl(directives)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::testing;
+
+    fn config(extra: &str) -> Config {
+        testing::config(Path::new("/srv/static"), extra)
+    }
+
+    #[test]
+    fn stale_directives_serialize_for_unversioned_files() {
+        let config = config("stale_while_revalidate = 60\nstale_if_error = 86400");
+
+        assert_eq!(
+            config.cache_control(false).to_string(),
+            "stale-while-revalidate=60, stale-if-error=86400"
+        );
+        assert_eq!(config.cache_control(true).to_string(), "max-age=31536000");
+    }
+
+    #[test]
+    fn base_href_prefixes_generated_urls() {
+        let config = config("base_href = \"/app/\"");
+
+        assert_eq!(
+            config.versioned_url("css/site.css", "t0k3n"),
+            "/app/static/css/site.css?v=t0k3n"
+        );
+        assert_eq!(config.file_url("a b.png"), "/app/static/a%20b.png");
+        assert_eq!(config.path_prefix, "/static");
+    }
+
+    #[test]
+    fn unknown_files_get_the_default_content_type() {
+        let config = config("default_content_type = \"text/plain\"");
+
+        assert_eq!(
+            config.mime(Path::new("/srv/static/LICENSE")),
+            mime::TEXT_PLAIN
+        );
+        assert_eq!(
+            config.mime(Path::new("/srv/static/site.css")),
+            mime::TEXT_CSS
+        );
+
+        let unset = self::config("");
+        assert_eq!(
+            unset.mime(Path::new("/srv/static/LICENSE")),
+            mime::APPLICATION_OCTET_STREAM
+        );
+    }
+
+    #[test]
+    fn the_longest_directory_cache_prefix_wins() {
+        let config = config(
+            "[directory_cache]\n\
+             vendor = \"max-age=86400\"\n\
+             \"vendor/beta/\" = \"no-store\"\n",
+        );
+
+        let policy = |path| config.cache_control_for(path, true).to_string();
+        assert_eq!(policy("vendor/lib.js"), "max-age=86400");
+        assert_eq!(policy("vendor/beta/lib.js"), "no-store");
+        assert_eq!(policy("vendor/betamax.js"), "max-age=86400");
+        assert_eq!(policy("vendored.js"), "max-age=31536000");
+    }
+
+    #[test]
+    fn content_language_follows_the_longest_directory() {
+        let languages = config(
+            "[content_language_map]\n\
+             de = \"de\"\n\
+             \"/de/at/\" = \"de-AT\"\n",
+        );
+
+        let language = |path: &str| languages.content_language(Path::new(path));
+        assert_eq!(language("de/index.html"), Some("de"));
+        assert_eq!(language("/srv/static/de/at/index.html"), Some("de-AT"));
+        assert_eq!(language("deutsch/index.html"), None);
+        assert_eq!(language("index.html"), None);
+        assert!(languages.validate());
+
+        let invalid = config("[content_language_map]\nfr = \"fr_FR\"\n");
+        assert!(!invalid.validate());
+    }
+
+    #[test]
+    fn charsets_follow_the_type_then_the_default() {
+        let charsets = config(
+            "default_charset = \"utf-8\"\n\
+             [charset_by_type]\n\
+             \"text/plain\" = \"iso-8859-1\"\n\
+             \"text/*\" = \"windows-1252\"\n",
+        );
+
+        let charset = |mime: &str| charsets.charset(&mime.parse().unwrap());
+        assert_eq!(charset("text/plain"), Some("iso-8859-1"));
+        assert_eq!(charset("text/css"), Some("windows-1252"));
+        assert_eq!(charset("application/javascript"), Some("utf-8"));
+        assert_eq!(charset("text/plain; charset=utf-16"), None);
+        assert_eq!(charset("image/png"), None);
+        assert!(charsets.validate());
+    }
+
+    #[test]
+    fn unversioned_files_revalidate_by_default() {
+        assert_eq!(config("").cache_control(false).to_string(), "no-cache");
+    }
+}
@@ -0,0 +1,71 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/. */
+
+use rocket::http::Status;
+use rocket::response::{Responder, Result as ResponseResult};
+use rocket::Request;
+
+use snafu::Snafu;
+
+use std::io;
+
+/// Why a static file couldn't be served.
+///
+/// Exported as `ServeError`, to keep it apart from the generator's `Error`.
+#[derive(Debug, Snafu)]
+#[snafu(visibility(pub(crate)))]
+#[non_exhaustive]
+pub enum Error {
+    /// Requested path not under `serve_from` path.
+    OutOfBounds,
+
+    /// Requested path not valid UTF-8.
+    Utf8,
+
+    /// Requested path is a directory or other non-file.
+    NotAFile,
+
+    /// Requested file was replaced while it was being opened.
+    Replaced,
+
+    /// Requested path exists but is never served.
+    Hidden,
+
+    /// Requested path is longer, or has more segments, than allowed.
+    TooLong,
+
+    /// Request has query parameters other than the version token, and
+    /// `reject_unknown_query` is set.
+    UnknownQuery,
+
+    /// Serving the file would redirect the client to the URL it asked for.
+    RedirectLoop,
+
+    /// An IO error occurred.
+    Io {
+        /// The underlying error; `NotFound` if the file doesn't exist.
+        source: std::io::Error,
+    },
+}
+
+impl Error {
+    /// The status the error is answered with.
+    pub fn status(&self) -> Status {
+        match self {
+            Error::Io { source } if source.kind() != io::ErrorKind::NotFound => {
+                Status::InternalServerError
+            }
+            Error::Utf8 | Error::UnknownQuery => Status::BadRequest,
+            Error::RedirectLoop => Status::InternalServerError,
+            Error::TooLong => Status::UriTooLong,
+            _ => Status::NotFound,
+        }
+    }
+}
+
+impl<'r> Responder<'r> for Error {
+    fn respond_to(self, _: &Request) -> ResponseResult<'r> {
+        Err(self.status())
+    }
+}
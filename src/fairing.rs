@@ -2,58 +2,320 @@
  * License, v. 2.0. If a copy of the MPL was not distributed with this
  * file, You can obtain one at https://mozilla.org/MPL/2.0/. */
 
+#[cfg(feature = "zip")]
+use crate::archive::Archive;
+use crate::asset::{self, Asset, Encoding};
+use crate::config::{Config, ConfigView, MAX_PRELOADS};
+#[cfg(feature = "log")]
+use crate::error::Io;
+use crate::error::{
+    Error, Hidden, NotAFile, OutOfBounds, RedirectLoop, TooLong, UnknownQuery, Utf8,
+};
+#[cfg(any(feature = "reload", feature = "verify"))]
+use crate::gen::Error as GenError;
+#[cfg(feature = "reload")]
+use crate::gen::{self, Filesystem, Generator};
+use crate::glob::Glob;
+use crate::headers::{self, HeaderRules};
+use crate::html;
+use crate::httpdate;
+#[cfg(feature = "zip")]
+use crate::lexical;
+use crate::manifest::Manifest;
+use crate::map::AssetMap;
+use crate::resolve::resolve_within;
+use crate::response::{
+    self, AcceptEncoding, Conditional, FileRedirect, FileResponse, MethodNotAllowed, NotModified,
+    OptionsResponse, Warm, WithCacheStatus,
+};
+#[cfg(feature = "verify")]
+use crate::verify::{self, Mismatch};
+
 use rocket::fairing::{Fairing, Info, Kind};
 use rocket::http::hyper::header::{CacheControl, CacheDirective};
-use rocket::http::{ContentType, Status};
+use rocket::http::uri::Segments;
+use rocket::http::{Header, Method, RawStr, Status};
 use rocket::request::{FromRequest, Outcome};
-use rocket::response::{Redirect, Responder, Result as ResponseResult};
-use rocket::{Request, Rocket, State};
+use rocket::response::{status, Body, Redirect, Responder, Result as ResponseResult};
+use rocket::{Request, Response, Rocket, Route, State};
 
-use serde::{Deserialize, Serialize};
+#[cfg(feature = "log")]
+use snafu::ResultExt;
+use snafu::{ensure, OptionExt};
 
-use snafu::{ensure, OptionExt, ResultExt, Snafu};
+#[cfg(feature = "reload")]
+use arc_swap::ArcSwapOption;
 
-use std::fmt::Display;
-use std::fs::File;
+use std::borrow::Cow;
+#[cfg(feature = "reload")]
+use std::collections::HashSet;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::{self, Display};
 use std::io;
 use std::path::{Path, PathBuf};
-use std::str::FromStr;
-use std::sync::Arc;
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::{Duration, Instant, SystemTime};
 
-#[derive(Debug, Snafu)]
-#[non_exhaustive]
-enum Error {
-    /// Requested path not under `serve_from` path.
-    OutOfBounds,
+/// Environment variable that, when set, replaces `serve_from` at launch.
+const SERVE_FROM_VAR: &str = "STATIC_FILES_DIR";
+
+/// How long a token from `stat_tokens` is reused before the file is statted
+/// again.
+const STAT_TOKEN_TTL: Duration = Duration::from_secs(2);
+
+/// Most paths `stat_tokens` remembers at once; the cache is emptied when full.
+const STAT_TOKEN_CACHE_SIZE: usize = 256;
+
+/// Most `dcb` siblings whose dictionary is remembered at once; the cache is
+/// emptied when full.
+#[cfg(feature = "dictionary")]
+const DICTIONARY_CACHE_SIZE: usize = 256;
+
+#[derive(Debug)]
+struct Inner {
+    config: Config,
+    /// The public face of `config`.
+    view: ConfigView,
+    /// Consulted in order; empty when created with [`StaticFiles::etag_only`].
+    hashes: Vec<&'static dyn AssetMap>,
+    header_rules: HeaderRules,
+
+    /// Lowercased path to stored path, when `case_insensitive_lookup` is set.
+    folded: HashMap<String, String>,
+
+    /// Set when `serve_from` is a zip file rather than a directory.
+    #[cfg(feature = "zip")]
+    archive: Option<Arc<Archive>>,
+
+    /// Replaces `hashes` (and `folded`) entirely once [`StaticFiles::reload`]
+    /// is called.
+    #[cfg(feature = "reload")]
+    reloaded: ArcSwapOption<Reloaded>,
+
+    /// Every token handed out by a reload, so unchanged files don't leak a new
+    /// copy each time.
+    #[cfg(feature = "reload")]
+    tokens: Mutex<HashSet<&'static str>>,
+
+    /// [`asset::bundle_hash`] over `hashes`.
+    bundle_hash: &'static str,
+
+    on_access: Option<AccessHook>,
+
+    /// Where `Expires` is counted from; the system clock when unset.
+    clock: Option<Clock>,
 
-    /// Requested path not valid UTF-8.
-    Utf8,
+    /// What [`StaticFiles::reload`] and [`StaticFiles::verify`] hash with.
+    #[cfg(any(feature = "reload", feature = "verify"))]
+    sip_keys: (u64, u64),
 
-    /// An IO error occurred.
-    Io { source: std::io::Error },
+    /// Files read in by [`StaticFiles::warm`], by full path (including
+    /// siblings).
+    warm: RwLock<HashMap<PathBuf, Warm>>,
+
+    /// Recent `stat_tokens` results, with when each was taken.
+    stat_tokens: Mutex<HashMap<String, (Instant, Option<String>)>>,
+
+    /// The dictionary each `dcb` sibling was compressed against, by full
+    /// path, with the size and modification time it had when read.
+    #[cfg(feature = "dictionary")]
+    dictionaries: Mutex<HashMap<PathBuf, (FileStamp, Option<[u8; 32]>)>>,
 }
 
-impl<'r> Responder<'r> for Error {
-    fn respond_to(self, _: &Request) -> ResponseResult<'r> {
-        match self {
-            Error::Io { source } if source.kind() != io::ErrorKind::NotFound => {
-                Err(Status::InternalServerError)
+/// A file's size and modification time, which change whenever it does.
+#[cfg(feature = "dictionary")]
+type FileStamp = (u64, Option<SystemTime>);
+
+#[cfg(feature = "reload")]
+#[derive(Debug)]
+struct Reloaded {
+    assets: HashMap<String, Asset>,
+    folded: HashMap<String, String>,
+    bundle_hash: &'static str,
+}
+
+impl Inner {
+    /// The path `path` is stored (and found on disk) under, which only
+    /// differs when `case_insensitive_lookup` is set.
+    fn key<'p>(&self, path: &'p str) -> Cow<'p, str> {
+        if !self.config.case_insensitive_lookup {
+            return Cow::Borrowed(path);
+        }
+
+        #[cfg(feature = "reload")]
+        {
+            if let Some(reloaded) = &*self.reloaded.load() {
+                return folded_key(&reloaded.folded, path);
+            }
+        }
+
+        folded_key(&self.folded, path)
+    }
+
+    fn asset(&self, path: &str) -> Option<Asset> {
+        #[cfg(feature = "reload")]
+        {
+            // Folding and lookup both use this one snapshot, so a reload
+            // landing in between can't pair one map's keys with another's.
+            if let Some(reloaded) = &*self.reloaded.load() {
+                let key = if self.config.case_insensitive_lookup {
+                    folded_key(&reloaded.folded, path)
+                } else {
+                    Cow::Borrowed(path)
+                };
+
+                return reloaded.assets.get(&*key).copied();
+            }
+        }
+
+        let key = self.key(path);
+        self.hashes.iter().find_map(|h| h.get(&*key)).copied()
+    }
+
+    /// Every asset, by path. Of several maps listing a path, the earlier wins.
+    fn assets(&self) -> BTreeMap<String, Asset> {
+        #[cfg(feature = "reload")]
+        {
+            if let Some(reloaded) = &*self.reloaded.load() {
+                return reloaded
+                    .assets
+                    .iter()
+                    .map(|(path, asset)| (path.clone(), *asset))
+                    .collect();
+            }
+        }
+
+        merge(&self.hashes)
+    }
+
+    /// `target`, in `encoding`, if [`StaticFiles::warm`] read it in.
+    fn warmed(&self, target: &Target, encoding: Option<Encoding>) -> Option<Warm> {
+        let path = match target {
+            Target::Disk(path) => path,
+            _ => return None,
+        };
+
+        let warm = self.warm.read().unwrap();
+        match encoding {
+            Some(encoding) => warm.get(&encoding.sibling(path)).cloned(),
+            None => warm.get(path).cloned(),
+        }
+    }
+
+    /// The current time, per [`StaticFilesFairing::clock`].
+    fn now(&self) -> SystemTime {
+        match &self.clock {
+            Some(clock) => (clock.0)(),
+            None => SystemTime::now(),
+        }
+    }
+
+    /// Paths in the map with no file behind them.
+    fn missing(&self) -> Vec<String> {
+        self.assets()
+            .into_iter()
+            .map(|(path, _)| path)
+            .filter(|path| {
+                let target = Target::resolve(self, Path::new(path));
+                !target.map(|t| t.is_file()).unwrap_or(false)
+            })
+            .collect()
+    }
+
+    fn bundle_hash(&self) -> &'static str {
+        #[cfg(feature = "reload")]
+        {
+            if let Some(reloaded) = &*self.reloaded.load() {
+                return reloaded.bundle_hash;
+            }
+        }
+
+        self.bundle_hash
+    }
+
+    /// `size-mtime` token for the untracked file at `path`, reusing one taken
+    /// in the last [`STAT_TOKEN_TTL`].
+    fn stat_token(&self, path: &str) -> Option<String> {
+        let now = Instant::now();
+
+        if let Some((taken, token)) = self.stat_tokens.lock().unwrap().get(path) {
+            if now.duration_since(*taken) < STAT_TOKEN_TTL {
+                return token.clone();
+            }
+        }
+
+        let token = Target::resolve(self, Path::new(path))
+            .ok()
+            .filter(Target::is_file)
+            .and_then(|target| target.stat_token());
+
+        let mut cache = self.stat_tokens.lock().unwrap();
+        if cache.len() >= STAT_TOKEN_CACHE_SIZE {
+            cache.clear();
+        }
+
+        cache.insert(path.to_owned(), (now, token.clone()));
+        token
+    }
+
+    /// [`response::dictionary_hash`] for the `dcb` file at `path`, read again
+    /// only once its size or modification time changes.
+    #[cfg(feature = "dictionary")]
+    fn dictionary_hash(&self, path: &Path) -> Option<[u8; 32]> {
+        let metadata = std::fs::metadata(path).ok()?;
+        let stamp = (metadata.len(), metadata.modified().ok());
+
+        if let Some((seen, hash)) = self.dictionaries.lock().unwrap().get(path) {
+            if *seen == stamp {
+                return *hash;
             }
-            _ => Err(Status::NotFound),
+        }
+
+        let hash = response::dictionary_hash(path);
+
+        let mut cache = self.dictionaries.lock().unwrap();
+        if cache.len() >= DICTIONARY_CACHE_SIZE {
+            cache.clear();
+        }
+
+        cache.insert(path.to_owned(), (stamp, hash));
+        hash
+    }
+}
+
+/// Everything in `maps`, by path. Of several maps listing a path, the earlier
+/// wins.
+fn merge(maps: &[&'static dyn AssetMap]) -> BTreeMap<String, Asset> {
+    let mut assets = BTreeMap::new();
+    for map in maps {
+        for (path, asset) in map.entries() {
+            assets.entry(path.to_owned()).or_insert(*asset);
         }
     }
+
+    assets
 }
 
-#[derive(Debug, Serialize, Deserialize)]
-struct Config {
-    serve_from: PathBuf,
-    path_prefix: String,
+/// A [`asset::bundle_hash`] that lives as long as the process.
+fn leak_bundle_hash(assets: &BTreeMap<String, Asset>) -> &'static str {
+    let hash = asset::bundle_hash(assets.iter().map(|(k, v)| (k.as_str(), v.version)));
+    Box::leak(hash.into_boxed_str())
 }
 
-#[derive(Debug)]
-struct Inner {
-    config: Config,
-    hashes: &'static phf::Map<&'static str, &'static str>,
+/// Index `keys` by their lowercased form. Of several keys differing only by
+/// case, the one that sorts first wins.
+fn fold<'k, I: IntoIterator<Item = &'k str>>(keys: I) -> HashMap<String, String> {
+    let mut keys: Vec<_> = keys.into_iter().collect();
+    keys.sort();
+
+    let mut folded = HashMap::new();
+    for key in keys {
+        folded
+            .entry(key.to_lowercase())
+            .or_insert_with(|| key.to_owned());
+    }
+
+    folded
 }
 
 /// Entry point for all of the functionality for `rocket-static-files`.
@@ -79,23 +341,394 @@ impl StaticFiles {
     /// }
     ///
     /// ```
-    pub fn fairing(hashes: &'static phf::Map<&'static str, &'static str>) -> impl Fairing {
-        StaticFilesFairing { hashes }
+    pub fn fairing(hashes: &'static dyn AssetMap) -> StaticFilesFairing {
+        StaticFilesFairing::new(vec![hashes])
+    }
+
+    /// Like [`StaticFiles::fairing`], but for several maps (e.g. one per
+    /// crate that ships assets) served from the same `serve_from`.
+    ///
+    /// A path is looked up in each map in turn, so when two maps list the
+    /// same path, the earlier one wins.
+    pub fn fairing_merged(maps: &[&'static dyn AssetMap]) -> StaticFilesFairing {
+        StaticFilesFairing::new(maps.to_vec())
+    }
+
+    /// Create a fairing that serves `serve_from` without a generated map, for
+    /// when a build script is more trouble than it's worth.
+    ///
+    /// Nothing gets a version token, so there are no redirects and no
+    /// far-future caching; clients revalidate every file using an `ETag`
+    /// derived from its size and modification time.
+    pub fn etag_only() -> StaticFilesFairing {
+        StaticFilesFairing::new(Vec::new())
+    }
+
+    /// The route serving files, for mounting by hand (at `path_prefix`) when
+    /// `auto_mount` is off, e.g. to wrap it with other guards.
+    pub fn routes() -> Vec<Route> {
+        routes![
+            serve_static,
+            serve_prefix,
+            reject_post,
+            reject_put,
+            reject_delete,
+            reject_patch,
+            answer_options
+        ]
     }
 
     /// Compute the full path, including version hash if one exists.
+    ///
+    /// Paths listed in the `pin` config option, or under one of
+    /// `no_token_dirs`, never get a version hash.
+    /// With `stat_tokens`, files missing from the map get one from their size
+    /// and modification time instead. With `bundle_path`, files in the map
+    /// are linked under the bundle hash rather than with their own token.
+    ///
+    /// `path` may be given with or without its leading slash.
     pub fn to<D: Display>(&self, path: D) -> String {
+        let path = rooted(path);
+        let key = &path[1..];
+        let config = &self.0.config;
+
+        let token = if config.is_pinned(key) || config.is_tokenless(key) {
+            None
+        } else {
+            match self.0.asset(key) {
+                Some(asset) => Some(Cow::Borrowed(asset.version)),
+                None if config.stat_tokens => self.0.stat_token(key).map(Cow::Owned),
+                None => None,
+            }
+        };
+
+        let hash = match token {
+            Some(Cow::Borrowed(_)) if config.bundle_path => {
+                return config.bundled_url(key, self.bundle_hash());
+            }
+            Some(token) => config.version_query(&token),
+            None => String::new(),
+        };
+
+        format!("{}{}{}", config.url_prefix(), path, hash)
+    }
+
+    /// Like [`StaticFiles::to`], but absolute, under `base_url` (such as
+    /// `https://example.com`), for emails, OpenGraph tags and the like.
+    ///
+    /// Links that `to` already makes absolute (with a scheme-qualified
+    /// `base_href`) are left alone, and protocol-relative ones get the scheme
+    /// of `base_url`.
+    pub fn to_absolute<D: Display>(&self, base_url: &str, path: D) -> String {
+        let url = self.to(path);
+
+        if url.contains("://") {
+            return url;
+        }
+
+        if url.starts_with("//") {
+            let scheme = base_url.find("://").map(|i| &base_url[..i]);
+            return format!("{}:{}", scheme.unwrap_or("https"), url);
+        }
+
+        format!("{}{}", base_url.trim_end_matches('/'), url)
+    }
+
+    /// Serve the file at `path` (relative to `serve_from`, like `"app.js"`)
+    /// from a handler of your own, with the same content type, encoding and
+    /// revalidation handling as under `path_prefix`.
+    ///
+    /// The handler's URL carries no version token, so the response is cached
+    /// like an unversioned file and never redirected.
+    pub fn respond<D: Display>(&self, path: D) -> Result<AssetResponse, Error> {
+        let path = path.to_string();
+        let path = path.trim_start_matches('/');
+
+        let key = self.0.key(path);
+        ensure!(!self.0.config.is_hidden(&key), Hidden);
+        ensure!(
+            Target::resolve(&self.0, Path::new(&*key))?.is_file(),
+            NotAFile
+        );
+
+        Ok(AssetResponse {
+            static_files: self.clone(),
+            path: path.to_owned(),
+        })
+    }
+
+    /// Read the files at `paths` (relative to `serve_from`, like `"app.js"`),
+    /// and their precompressed siblings, into memory, so that even the first
+    /// request for them doesn't touch the disk. Call it from a launch fairing
+    /// for a few critical assets.
+    ///
+    /// Warmed files are kept as they were read until the process exits. Only
+    /// files on disk are read in; embedded ones are in memory already.
+    pub fn warm(&self, paths: &[&str]) -> Result<(), Error> {
+        let mut loaded = Vec::new();
+
+        for path in paths {
+            let key = self.0.key(path.trim_start_matches('/'));
+            let full = match Target::resolve(&self.0, Path::new(&*key))? {
+                Target::Disk(full) => full,
+                _ => continue,
+            };
+
+            if let Some(asset) = self.0.asset(&key) {
+                for encoding in Encoding::PREFERENCE.iter().filter(|e| asset.has(**e)) {
+                    let sibling = encoding.sibling(&full);
+                    loaded.push((sibling.clone(), Warm::load(&sibling)?));
+                }
+            }
+
+            loaded.push((full.clone(), Warm::load(&full)?));
+        }
+
+        self.0.warm.write().unwrap().extend(loaded);
+        Ok(())
+    }
+
+    /// A token that changes whenever any asset does, like a version for the
+    /// whole set (say, to name a service worker's cache).
+    ///
+    /// Matches `STATIC_FILE_BUNDLE_HASH` in the generated file, unless several
+    /// maps are merged or the assets were reloaded.
+    pub fn bundle_hash(&self) -> &'static str {
+        self.0.bundle_hash()
+    }
+
+    /// The settings this instance was attached with, e.g. for diagnostics.
+    pub fn config(&self) -> &ConfigView {
+        &self.0.view
+    }
+
+    /// Subresource Integrity digest for `path` (with or without its leading
+    /// slash), if one was generated.
+    pub fn integrity<D: Display>(&self, path: D) -> Option<&'static str> {
+        let path = rooted(path);
+        self.0.asset(&path[1..]).and_then(|a| a.integrity)
+    }
+
+    /// Every tracked path (relative to `serve_from`) matching `pattern`, like
+    /// `fonts/*.woff2`, with its version token, sorted by path. Uses the same
+    /// glob syntax as the config options. Paths that are never served, like
+    /// dotfiles outside `serve_dotfiles_allowlist`, are left out.
+    pub fn glob(&self, pattern: &str) -> Vec<(String, &'static str)> {
+        let glob = Glob::new(pattern.trim_start_matches('/'));
+
+        self.0
+            .assets()
+            .into_iter()
+            .filter(|(path, _)| glob.matches(path) && !self.0.config.is_hidden(path))
+            .map(|(path, asset)| (path, asset.version))
+            .collect()
+    }
+
+    /// Render a `<script>` tag linking to `path`, with an `integrity`
+    /// attribute when a digest is available.
+    pub fn script_tag<D: Display>(&self, path: D) -> String {
+        let path = path.to_string();
+
+        format!(
+            "<script src=\"{}\"{}></script>",
+            html::escape_attr(&self.to(&path)),
+            html::integrity_attrs(self.integrity(&path)),
+        )
+    }
+
+    /// Render a stylesheet `<link>` tag for `path`, with an `integrity`
+    /// attribute when a digest is available.
+    pub fn link_tag<D: Display>(&self, path: D) -> String {
         let path = path.to_string();
 
-        let hash = self
+        format!(
+            "<link rel=\"stylesheet\" href=\"{}\"{}>",
+            html::escape_attr(&self.to(&path)),
+            html::integrity_attrs(self.integrity(&path)),
+        )
+    }
+
+    /// Render a `Link` header value preloading each of `assets` from its
+    /// versioned URL, for responses that aren't static files:
+    ///
+    /// ```nocompile
+    /// let links = static_files.preload_links(&[("/app.css", As::Style), ("/app.js", As::Script)]);
+    /// response.set_raw_header("Link", links);
+    /// ```
+    pub fn preload_links<D: Display>(&self, assets: &[(D, As)]) -> String {
+        let links: Vec<_> = assets
+            .iter()
+            .map(|(path, kind)| self.preload_link(path, kind.as_str()))
+            .collect();
+
+        links.join(", ")
+    }
+
+    fn preload_link<D: Display>(&self, path: D, kind: &str) -> String {
+        format!(
+            "<{}>; rel=preload; as={}",
+            link_target(&self.to(path)),
+            kind
+        )
+    }
+
+    /// `response`, labelled for `cache_status_header` if it's set.
+    fn with_cache_status(&self, response: StaticResponse) -> WithCacheStatus<StaticResponse> {
+        let cache_status = if self.0.config.cache_status_header {
+            response.cache_status()
+        } else {
+            None
+        };
+
+        WithCacheStatus(response, cache_status)
+    }
+
+    /// Answer a `500` for `error` with `server_error_page`, if set. When the
+    /// page can't be served either, `error` is passed on as usual rather than
+    /// trying again.
+    fn server_error_page(&self, error: Error) -> Result<StaticResponse, Error> {
+        let config = &self.0.config;
+
+        let page = match &config.server_error_page {
+            Some(page) if error.status() == Status::InternalServerError => {
+                config.serve_from.join(page)
+            }
+            _ => return Err(error),
+        };
+
+        let no_store = CacheControl(vec![CacheDirective::NoStore]);
+        let mut file = match FileResponse::new(config, page, no_store, None, None) {
+            Ok(file) => file,
+            Err(_) => return Err(error),
+        };
+
+        #[cfg(feature = "log")]
+        log::error!("serving static file failed: {:?}", error);
+
+        file.etag = None;
+        Ok(StaticResponse::ServerError(status::Custom(
+            Status::InternalServerError,
+            file,
+        )))
+    }
+
+    /// Point links in an HTML `response` at their versioned URLs, for
+    /// `rewrite_html`.
+    fn rewrite_html(&self, response: &mut Response) {
+        let config = &self.0.config;
+
+        let is_html = response
+            .content_type()
+            .map(|t| t.is_html())
+            .unwrap_or(false);
+
+        if !is_html || response.headers().contains("Content-Encoding") {
+            return;
+        }
+
+        // Only bodies of a known, bounded size are read in.
+        match response.body() {
+            Some(Body::Sized(_, size)) if size <= config.rewrite_html_max_bytes => (),
+            _ => return,
+        }
+
+        let bytes = match response.body_bytes() {
+            Some(bytes) => bytes,
+            None => return,
+        };
+
+        let rewritten = match std::str::from_utf8(&bytes) {
+            Ok(text) => html::rewrite_links(text, &config.url_prefix(), |path| self.to(path)),
+            Err(_) => {
+                response.set_sized_body(io::Cursor::new(bytes));
+                return;
+            }
+        };
+
+        response.set_sized_body(io::Cursor::new(rewritten));
+    }
+
+    /// A `Link` header preloading everything listed in `preload`.
+    fn preload_header(&self) -> Option<Header<'static>> {
+        let links: Vec<_> = self
             .0
-            .hashes
-            .get(&path[1..])
-            .map(|x| format!("?v={}", x))
-            .unwrap_or_default();
+            .config
+            .preload
+            .iter()
+            .take(MAX_PRELOADS)
+            .map(|p| self.preload_link(rooted(&p.path), &p.kind))
+            .collect();
+
+        if links.is_empty() {
+            None
+        } else {
+            Some(Header::new("Link", links.join(", ")))
+        }
+    }
+}
+
+/// What a preloaded asset will be used as: the `as` attribute of a preload
+/// link.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum As {
+    /// `as=audio`
+    Audio,
+    /// `as=document`
+    Document,
+    /// `as=fetch`
+    Fetch,
+    /// `as=font`
+    Font,
+    /// `as=image`
+    Image,
+    /// `as=script`
+    Script,
+    /// `as=style`
+    Style,
+    /// `as=track`
+    Track,
+    /// `as=video`
+    Video,
+    /// `as=worker`
+    Worker,
+}
+
+impl As {
+    fn as_str(self) -> &'static str {
+        match self {
+            As::Audio => "audio",
+            As::Document => "document",
+            As::Fetch => "fetch",
+            As::Font => "font",
+            As::Image => "image",
+            As::Script => "script",
+            As::Style => "style",
+            As::Track => "track",
+            As::Video => "video",
+            As::Worker => "worker",
+        }
+    }
+}
+
+/// `path` with exactly one leading slash, however many it was given with.
+fn rooted<D: Display>(path: D) -> String {
+    format!("/{}", path.to_string().trim_start_matches('/'))
+}
 
-        format!("{}{}{}", self.0.config.path_prefix, path, hash)
+/// Percent-encode whatever can't appear between the `<>` of a `Link` header.
+fn link_target(url: &str) -> String {
+    let mut escaped = String::with_capacity(url.len());
+
+    for b in url.bytes() {
+        if b <= b' ' || b >= 0x7f || b"\"<>\\^`{|}".contains(&b) {
+            escaped.push_str(&format!("%{:02X}", b));
+        } else {
+            escaped.push(b as char);
+        }
     }
+
+    escaped
 }
 
 impl<'a, 'r> FromRequest<'a, 'r> for StaticFiles {
@@ -108,131 +741,1374 @@ impl<'a, 'r> FromRequest<'a, 'r> for StaticFiles {
     }
 }
 
-struct StaticFilesFairing {
-    hashes: &'static phf::Map<&'static str, &'static str>,
-}
+#[cfg(feature = "reload")]
+impl StaticFiles {
+    /// Rescan `static_root` (normally the same directory as `serve_from`) and
+    /// atomically switch every lookup over to the new versions, without
+    /// restarting.
+    ///
+    /// Requests keep being answered from the previous map while the rescan
+    /// runs, and if it fails. The new one replaces it in a single swap, once
+    /// complete, so no request sees half of each. Concurrent reloads take
+    /// turns, so the last to start is the one left in place.
+    ///
+    /// Once called, the map passed to [`StaticFiles::fairing`] is no longer
+    /// consulted. Each distinct token is kept for the life of the process, so
+    /// reloads are meant to be occasional, not continuous.
+    pub fn reload<P: AsRef<Path>>(&self, static_root: P) -> Result<(), GenError> {
+        // Held throughout, so an older scan can't be stored over a newer one.
+        let mut tokens = self.0.tokens.lock().unwrap();
+        let (k0, k1) = self.0.sip_keys;
+        let (mut files, _) = Generator::with_source(Filesystem::quiet(static_root))
+            .sip_keys(k0, k1)
+            .dotfiles_allowlist(&self.0.config.serve_dotfiles_allowlist)
+            .scan()?;
+        gen::drop_built_siblings(&mut files, |path| self.0.asset(path).is_some());
 
-impl Fairing for StaticFilesFairing {
-    fn info(&self) -> Info {
-        Info {
-            name: "Static Files",
-            kind: Kind::Attach,
-        }
-    }
+        let assets: HashMap<_, _> = files
+            .into_iter()
+            .map(|(path, entry)| {
+                let asset = Asset {
+                    version: intern(&mut tokens, entry.token),
+                    gzip: entry.gzip,
+                    brotli: entry.brotli,
+                    zstd: entry.zstd,
+                    integrity: None,
+                    embedded: None,
+                    embedded_size: None,
+                };
 
-    fn on_attach(&self, mut rocket: Rocket) -> Result<Rocket, Rocket> {
-        let orig_config = match rocket.config().get_extra("static_files") {
-            Ok(c) => c.clone(),
-            Err(_) => return Err(rocket),
-        };
+                (path, asset)
+            })
+            .collect();
 
-        let orig_config: Config = match orig_config.try_into() {
-            Ok(c) => c,
-            Err(_) => return Err(rocket),
+        let folded = if self.0.config.case_insensitive_lookup {
+            fold(assets.keys().map(String::as_str))
+        } else {
+            HashMap::new()
         };
 
-        let canon = rocket
-            .config()
-            .root_relative(orig_config.serve_from)
-            .canonicalize();
-
-        let serve_from = match canon {
-            Ok(s) => s,
-            Err(_) => return Err(rocket),
-        };
+        let bundle = asset::bundle_hash(assets.iter().map(|(k, v)| (k.as_str(), v.version)));
+        let bundle_hash = intern(&mut tokens, bundle);
 
-        rocket = rocket.mount(&orig_config.path_prefix, routes![serve_static]);
+        self.0.reloaded.store(Some(Arc::new(Reloaded {
+            assets,
+            folded,
+            bundle_hash,
+        })));
+        Ok(())
+    }
+}
 
-        Ok(rocket.manage(StaticFiles(Arc::new(Inner {
-            hashes: self.hashes,
-            config: Config {
-                path_prefix: orig_config.path_prefix,
-                serve_from,
-            },
-        }))))
+/// The stored path `path` folds to, per `folded`, or `path` itself.
+fn folded_key<'p>(folded: &HashMap<String, String>, path: &'p str) -> Cow<'p, str> {
+    match folded.get(&path.to_lowercase()) {
+        Some(key) => Cow::Owned(key.clone()),
+        None => Cow::Borrowed(path),
     }
 }
 
-#[derive(Debug, Responder)]
-struct FileResponse {
-    file: File,
-    content_type: ContentType,
-    cache_control: CacheControl,
+#[cfg(feature = "reload")]
+fn intern(tokens: &mut HashSet<&'static str>, token: String) -> &'static str {
+    if let Some(existing) = tokens.get(token.as_str()) {
+        return *existing;
+    }
+
+    let leaked: &'static str = Box::leak(token.into_boxed_str());
+    tokens.insert(leaked);
+    leaked
 }
 
-impl FileResponse {
-    pub fn new<P: AsRef<Path>>(path: P, cache: bool) -> Result<Self, Error> {
-        Self::new_path(path.as_ref(), cache)
+#[cfg(feature = "verify")]
+impl StaticFiles {
+    /// Rehash every file in `serve_from` and compare against the map compiled
+    /// into the binary, to catch assets that weren't deployed along with it.
+    ///
+    /// Meant to be run once at launch; an empty list means everything agrees.
+    pub fn verify(&self) -> Result<Vec<Mismatch>, GenError> {
+        verify::verify(
+            self.0.config.serve_from.dirs(),
+            &self.0.hashes,
+            self.0.sip_keys,
+            &self.0.config.serve_dotfiles_allowlist,
+        )
     }
+}
 
-    fn cache_control(cache: bool) -> CacheControl {
-        if cache {
-            CacheControl(vec![CacheDirective::MaxAge(31536000)])
-        } else {
-            CacheControl(vec![])
+/// The fairing returned by [`StaticFiles::fairing`] and friends, to attach to
+/// your rocket.
+#[derive(Debug)]
+pub struct StaticFilesFairing {
+    hashes: Vec<&'static dyn AssetMap>,
+    on_access: Option<AccessHook>,
+    clock: Option<Clock>,
+    #[cfg(any(feature = "reload", feature = "verify"))]
+    sip_keys: (u64, u64),
+}
+
+impl StaticFilesFairing {
+    fn new(hashes: Vec<&'static dyn AssetMap>) -> Self {
+        StaticFilesFairing {
+            hashes,
+            on_access: None,
+            clock: None,
+            #[cfg(any(feature = "reload", feature = "verify"))]
+            sip_keys: (0, 0),
         }
     }
 
-    fn new_path(path: &Path, cache: bool) -> Result<Self, Error> {
-        let file = File::open(path).context(Io)?;
-        let mime = mime_guess::from_path(path).first_or_octet_stream();
+    /// The SipHash keys the map was generated with, for
+    /// [`StaticFiles::reload`] and [`StaticFiles::verify`] to hash files the
+    /// same way. Pass the `STATIC_FILE_SIP_KEYS` from the generated file:
+    ///
+    /// ```nocompile
+    /// StaticFiles::fairing(&STATIC_FILE_HASHES).sip_keys(STATIC_FILE_SIP_KEYS)
+    /// ```
+    ///
+    /// Only needed when the map was generated with
+    /// [`Generator::sip_keys`](crate::Generator::sip_keys); the default keys
+    /// are both zero.
+    #[cfg(any(feature = "reload", feature = "verify"))]
+    pub fn sip_keys(mut self, keys: (u64, u64)) -> Self {
+        self.sip_keys = keys;
+        self
+    }
 
-        // TODO: Probably a better way to do this conversion
-        let content_type = ContentType::from_str(&mime.to_string()).unwrap();
+    /// Call `hook` with the path (relative to `serve_from`) of every file
+    /// served, including `304`s, e.g. to find out which assets are worth
+    /// warming a cache with. It runs on the worker thread, so keep it quick.
+    pub fn on_access<F>(mut self, hook: F) -> Self
+    where
+        F: Fn(&str) + Send + Sync + 'static,
+    {
+        self.on_access = Some(AccessHook(Arc::new(hook)));
+        self
+    }
 
-        Ok(FileResponse {
-            file,
-            content_type,
-            cache_control: Self::cache_control(cache),
-        })
+    /// Take the current time from `clock` rather than the system clock when
+    /// computing `Expires` headers, e.g. to test them with a fixed time.
+    pub fn clock<F>(mut self, clock: F) -> Self
+    where
+        F: Fn() -> SystemTime + Send + Sync + 'static,
+    {
+        self.clock = Some(Clock(Arc::new(clock)));
+        self
     }
 }
 
-#[derive(Debug, Responder)]
-enum RedirectOrFile {
-    Redirect(Redirect),
-    File(FileResponse),
-}
+/// See [`StaticFilesFairing::on_access`].
+#[derive(Clone)]
+struct AccessHook(Arc<dyn Fn(&str) + Send + Sync>);
 
-#[get("/<path..>?<v>")]
-fn serve_static(
-    path: PathBuf,
-    v: Option<String>,
-    static_files: StaticFiles,
-) -> Result<RedirectOrFile, Error> {
-    let expected_revision = v.as_deref();
+impl fmt::Debug for AccessHook {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("AccessHook")
+    }
+}
 
-    let text = path.to_str().context(Utf8)?;
-    let target = static_files
-        .0
-        .config
-        .serve_from
-        .join(&path)
-        .canonicalize()
-        .context(Io)?;
-
-    ensure!(
-        target.starts_with(&static_files.0.config.serve_from),
-        OutOfBounds,
-    );
+/// See [`StaticFilesFairing::clock`].
+#[derive(Clone)]
+struct Clock(Arc<dyn Fn() -> SystemTime + Send + Sync>);
 
-    let current_revision = static_files.0.hashes.get(text).copied();
+impl fmt::Debug for Clock {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("Clock")
+    }
+}
 
-    let resp = match (expected_revision, current_revision) {
-        (Some(expected), Some(current)) if expected == current => {
-            RedirectOrFile::File(FileResponse::new(target, true)?)
+impl Fairing for StaticFilesFairing {
+    fn info(&self) -> Info {
+        Info {
+            name: "Static Files",
+            kind: Kind::Attach,
         }
-        (_, Some(current)) => {
-            let url = format!(
-                "{}{}",
-                static_files.0.config.path_prefix,
-                uri!(serve_static: path, current)
-            );
-            let redir = Redirect::to(url);
-            RedirectOrFile::Redirect(redir)
-        }
-        (_, None) => RedirectOrFile::File(FileResponse::new(target, false)?),
-    };
+    }
+
+    fn on_attach(&self, mut rocket: Rocket) -> Result<Rocket, Rocket> {
+        let orig_config = match rocket.config().get_extra("static_files") {
+            Ok(c) => c.clone(),
+            Err(_) => return Err(rocket),
+        };
+
+        let mut config: Config = match orig_config.try_into() {
+            Ok(c) => c,
+            Err(_) => return Err(rocket),
+        };
+
+        if !config.validate() {
+            return Err(rocket);
+        }
+
+        // Deploys can move the files without rebuilding with a new config.
+        if let Some(dir) = std::env::var_os(SERVE_FROM_VAR) {
+            config.serve_from = vec![PathBuf::from(dir)].into();
+        }
+
+        let canon: Result<Vec<_>, _> = config
+            .serve_from
+            .dirs()
+            .iter()
+            .map(|dir| rocket.config().root_relative(dir).canonicalize())
+            .collect();
+
+        config.serve_from = match canon {
+            Ok(dirs) => dirs.into(),
+            Err(_) => return Err(rocket),
+        };
+
+        #[cfg(feature = "zip")]
+        let archive = if config.serve_from.is_file() {
+            match Archive::open(&config.serve_from) {
+                Ok(a) => Some(Arc::new(a)),
+                Err(_) => return Err(rocket),
+            }
+        } else {
+            None
+        };
+
+        let header_rules = if config.sidecar_headers {
+            let parsed = read_sidecar(
+                &config,
+                #[cfg(feature = "zip")]
+                archive.as_deref(),
+            )
+            .and_then(|text| HeaderRules::parse(&text));
+
+            match parsed {
+                Ok(rules) => rules,
+                Err(_) => return Err(rocket),
+            }
+        } else {
+            HeaderRules::default()
+        };
+
+        let folded = if config.case_insensitive_lookup {
+            fold(self.hashes.iter().flat_map(|h| h.entries().map(|(k, _)| k)))
+        } else {
+            HashMap::new()
+        };
+
+        if config.auto_mount {
+            rocket = rocket.mount(&config.path_prefix, StaticFiles::routes());
+        }
+
+        if !config.root_aliases.is_empty() {
+            rocket = rocket.mount("/", routes![serve_root_alias]);
+        }
+
+        let inner = Arc::new(Inner {
+            hashes: self.hashes.clone(),
+            view: ConfigView::new(&config),
+            bundle_hash: leak_bundle_hash(&merge(&self.hashes)),
+            on_access: self.on_access.clone(),
+            clock: self.clock.clone(),
+            config,
+            header_rules,
+            folded,
+            #[cfg(feature = "zip")]
+            archive,
+            #[cfg(feature = "reload")]
+            reloaded: ArcSwapOption::empty(),
+            #[cfg(feature = "reload")]
+            tokens: Mutex::default(),
+            #[cfg(any(feature = "reload", feature = "verify"))]
+            sip_keys: self.sip_keys,
+            warm: RwLock::default(),
+            stat_tokens: Mutex::default(),
+            #[cfg(feature = "dictionary")]
+            dictionaries: Mutex::default(),
+        });
+
+        if inner.config.strict {
+            let missing = inner.missing();
+
+            if !missing.is_empty() {
+                #[cfg(feature = "log")]
+                log::error!(
+                    "static files missing from serve_from: {}",
+                    missing.join(", ")
+                );
+
+                return Err(rocket);
+            }
+        }
+
+        // Only then is every response in the app worth looking at.
+        if inner.config.inject_headers || inner.config.rewrite_html {
+            rocket = rocket.attach(ResponseHeaders);
+        }
+
+        Ok(rocket.manage(StaticFiles(inner)))
+    }
+}
+
+/// Attached by [`StaticFilesFairing`] for `inject_headers` and
+/// `rewrite_html`, which work on responses from any handler.
+struct ResponseHeaders;
+
+impl Fairing for ResponseHeaders {
+    fn info(&self) -> Info {
+        Info {
+            name: "Static Files (responses)",
+            kind: Kind::Response,
+        }
+    }
+
+    fn on_response(&self, request: &Request, response: &mut Response) {
+        let static_files = match request.guard::<State<StaticFiles>>().succeeded() {
+            Some(s) => s,
+            None => return,
+        };
+
+        let inner = &static_files.0;
+
+        if inner.config.rewrite_html && response.status() == Status::Ok {
+            static_files.rewrite_html(response);
+        }
+
+        if !inner.config.inject_headers || response.status() != Status::Ok {
+            return;
+        }
+
+        // Anything `serve_static` (or the handler itself) already decided on
+        // wins.
+        if response.headers().contains("Cache-Control") {
+            return;
+        }
+
+        let path = match inner.config.relative_path(request.uri().path()) {
+            Some(p) => p,
+            None => return,
+        };
+
+        let decoded = RawStr::from_str(path).percent_decode_lossy();
+        let current = inner.asset(&decoded).map(|a| a.version);
+        let expected = inner.config.requested_version(request);
+
+        let cache = match (expected, current) {
+            (Some(expected), Some(current)) => expected == current,
+            _ => false,
+        };
+
+        response.set_header(inner.config.cache_control_for(&decoded, cache));
+    }
+}
+
+/// The version token in the request's query string, under whatever name
+/// `version_param` configures, and whether `reject_unknown_query` should turn
+/// the request away for carrying anything else.
+struct RequestedVersion(Option<String>, bool);
+
+impl<'a, 'r> FromRequest<'a, 'r> for RequestedVersion {
+    type Error = ();
+
+    fn from_request(request: &'a Request<'r>) -> Outcome<Self, Self::Error> {
+        request.guard::<StaticFiles>().map(|s| {
+            let config = &s.0.config;
+            let unexpected =
+                config.reject_unknown_query && config.has_unknown_query(request.uri().query());
+
+            RequestedVersion(config.requested_version(request), unexpected)
+        })
+    }
+}
+
+/// The request URI as sent, and whether its path ends in a `/`, which the
+/// path segments alone don't say.
+struct RequestUri {
+    uri: String,
+    trailing_slash: bool,
+}
+
+impl<'a, 'r> FromRequest<'a, 'r> for RequestUri {
+    type Error = ();
+
+    fn from_request(request: &'a Request<'r>) -> Outcome<Self, Self::Error> {
+        Outcome::Success(RequestUri {
+            uri: request.uri().to_string(),
+            trailing_slash: request.uri().path().ends_with('/'),
+        })
+    }
+}
+
+fn read_sidecar(
+    config: &Config,
+    #[cfg(feature = "zip")] archive: Option<&Archive>,
+) -> Result<String, String> {
+    #[cfg(feature = "zip")]
+    {
+        if let Some(archive) = archive {
+            let contents = archive
+                .read(headers::FILE_NAME)
+                .map_err(|e| e.to_string())?;
+            return String::from_utf8(contents).map_err(|e| e.to_string());
+        }
+    }
+
+    std::fs::read_to_string(config.serve_from.join(headers::FILE_NAME)).map_err(|e| e.to_string())
+}
+
+#[derive(Debug, Responder)]
+enum StaticResponse {
+    Redirect(FileRedirect),
+    NotModified(NotModified),
+    File(FileResponse),
+    Manifest(Manifest),
+    ServerError(status::Custom<FileResponse>),
+}
+
+impl StaticResponse {
+    /// The `X-Cache-Status` for this response, if it has one.
+    fn cache_status(&self) -> Option<&'static str> {
+        match self {
+            StaticResponse::Redirect(_) => Some("redirect"),
+            StaticResponse::NotModified(_) => Some("revalidated"),
+            StaticResponse::File(file) if file.versioned => Some("immutable"),
+            StaticResponse::File(_) | StaticResponse::Manifest(_) => Some("miss"),
+            StaticResponse::ServerError(_) => None,
+        }
+    }
+}
+
+/// A single file, sent from any handler. See [`StaticFiles::respond`].
+#[derive(Debug)]
+pub struct AssetResponse {
+    static_files: StaticFiles,
+    path: String,
+}
+
+impl<'r> Responder<'r> for AssetResponse {
+    fn respond_to(self, request: &Request) -> ResponseResult<'r> {
+        let accept = request.guard::<AcceptEncoding>().succeeded();
+        let conditional = request.guard::<Conditional>().succeeded();
+
+        let options = Options {
+            pinned: true,
+            head: request.method() == Method::Head,
+            ..Options::default()
+        };
+
+        serve(
+            &self.static_files,
+            &self.path,
+            options,
+            accept.unwrap_or_default(),
+            &conditional.unwrap_or_default(),
+        )
+        .map(|response| self.static_files.with_cache_status(response))
+        .respond_to(request)
+    }
+}
+
+/// The file a request for `/<name>` stands in for, per `root_aliases`.
+/// Forwards when `name` isn't configured.
+struct RootAlias(String);
+
+impl<'a, 'r> FromRequest<'a, 'r> for RootAlias {
+    type Error = ();
+
+    fn from_request(request: &'a Request<'r>) -> Outcome<Self, Self::Error> {
+        let static_files = match request.guard::<StaticFiles>().succeeded() {
+            Some(s) => s,
+            None => return Outcome::Forward(()),
+        };
+
+        let name = request.uri().path().trim_start_matches('/');
+
+        match static_files.0.config.root_aliases.get(name) {
+            Some(target) => Outcome::Success(RootAlias(target.trim_start_matches('/').into())),
+            None => Outcome::Forward(()),
+        }
+    }
+}
+
+/// The `Allow` header value, when `method_not_allowed` is set.
+struct RejectMethods(&'static str);
+
+impl<'a, 'r> FromRequest<'a, 'r> for RejectMethods {
+    type Error = ();
+
+    fn from_request(request: &'a Request<'r>) -> Outcome<Self, Self::Error> {
+        match request.guard::<StaticFiles>().succeeded() {
+            Some(s) if s.0.config.method_not_allowed => {
+                Outcome::Success(RejectMethods(s.0.config.allowed_methods()))
+            }
+            _ => Outcome::Forward(()),
+        }
+    }
+}
+
+/// The path of an `OPTIONS` request, when `answer_options` is set.
+struct AnswerOptions(StaticFiles);
+
+impl<'a, 'r> FromRequest<'a, 'r> for AnswerOptions {
+    type Error = ();
+
+    fn from_request(request: &'a Request<'r>) -> Outcome<Self, Self::Error> {
+        match request.guard::<StaticFiles>().succeeded() {
+            Some(s) if s.0.config.answer_options => Outcome::Success(AnswerOptions(s)),
+            _ => Outcome::Forward(()),
+        }
+    }
+}
+
+/// `204` with the methods static files answer to, plus any CORS headers
+/// `_headers` gives the path.
+#[options("/<path..>", rank = 20)]
+fn answer_options(path: Segments, answer: AnswerOptions) -> OptionsResponse {
+    let inner = &(answer.0).0;
+
+    let path = dotted_path(path).ok();
 
-    Ok(resp)
+    let cors = path
+        .as_deref()
+        .and_then(Path::to_str)
+        .map(|text| {
+            inner
+                .header_rules
+                .matching(text)
+                .filter(|h| h.name().to_ascii_lowercase().starts_with("access-control-"))
+                .collect()
+        })
+        .unwrap_or_default();
+
+    OptionsResponse {
+        allow: inner.config.allowed_methods(),
+        headers: cors,
+    }
+}
+
+// Only the methods Rocket routes; anything else never reaches a handler.
+
+#[post("/<_path..>", rank = 20)]
+fn reject_post(_path: Segments, reject: RejectMethods) -> MethodNotAllowed {
+    MethodNotAllowed(reject.0)
+}
+
+#[put("/<_path..>", rank = 20)]
+fn reject_put(_path: Segments, reject: RejectMethods) -> MethodNotAllowed {
+    MethodNotAllowed(reject.0)
+}
+
+#[delete("/<_path..>", rank = 20)]
+fn reject_delete(_path: Segments, reject: RejectMethods) -> MethodNotAllowed {
+    MethodNotAllowed(reject.0)
+}
+
+#[patch("/<_path..>", rank = 20)]
+fn reject_patch(_path: Segments, reject: RejectMethods) -> MethodNotAllowed {
+    MethodNotAllowed(reject.0)
 }
+
+/// Takes the raw segments rather than a `PathBuf`, which Rocket would forward
+/// on (to a `404`) for bad UTF-8 and for every dotfile.
+#[get("/<segments..>")]
+#[allow(clippy::too_many_arguments)]
+fn serve_static(
+    segments: Segments,
+    method: Method,
+    requested: RequestedVersion,
+    uri: RequestUri,
+    accept: AcceptEncoding,
+    conditional: Conditional,
+    dpr: ClientDpr,
+    static_files: StaticFiles,
+) -> Result<WithCacheStatus<StaticResponse>, Error> {
+    #[cfg(feature = "tracing")]
+    let span = tracing::info_span!(
+        "serve_static",
+        path = %segments.0,
+        outcome = tracing::field::Empty,
+        status = tracing::field::Empty,
+        bytes = tracing::field::Empty,
+    );
+    #[cfg(feature = "tracing")]
+    let _entered = span.enter();
+
+    let response = serve_request(
+        segments,
+        method,
+        requested,
+        uri,
+        accept,
+        conditional,
+        dpr,
+        static_files.clone(),
+    );
+
+    #[cfg(feature = "tracing")]
+    record_outcome(&span, &response);
+
+    let response = response.or_else(|error| static_files.server_error_page(error))?;
+    Ok(static_files.with_cache_status(response))
+}
+
+/// Fill in the `outcome`, `status` and `bytes` of `serve_static`'s span, and
+/// note whether the client's cached copy was still good.
+#[cfg(feature = "tracing")]
+fn record_outcome(span: &tracing::Span, response: &Result<StaticResponse, Error>) {
+    let (outcome, status) = match response {
+        Ok(StaticResponse::File(file)) => {
+            span.record("bytes", &file.body_len());
+            tracing::debug!("cache miss");
+            ("file", Some(Status::Ok))
+        }
+        Ok(StaticResponse::NotModified(_)) => {
+            tracing::debug!("cache hit");
+            ("not_modified", Some(Status::NotModified))
+        }
+        // `301` or `303`, which `Redirect` doesn't say.
+        Ok(StaticResponse::Redirect(_)) => ("redirect", None),
+        Ok(StaticResponse::Manifest(_)) => ("manifest", Some(Status::Ok)),
+        Ok(StaticResponse::ServerError(_)) => ("error", Some(Status::InternalServerError)),
+        Err(e) => ("error", Some(e.status())),
+    };
+
+    span.record("outcome", &outcome);
+    if let Some(status) = status {
+        span.record("status", &status.code);
+    }
+}
+
+/// Everything [`serve_static`] does, apart from tracing.
+#[allow(clippy::too_many_arguments)]
+fn serve_request(
+    segments: Segments,
+    method: Method,
+    requested: RequestedVersion,
+    uri: RequestUri,
+    accept: AcceptEncoding,
+    conditional: Conditional,
+    dpr: ClientDpr,
+    static_files: StaticFiles,
+) -> Result<StaticResponse, Error> {
+    let inner = &static_files.0;
+
+    // Before anything touches the filesystem.
+    let path = request_path(&inner.config, segments)?;
+    ensure!(inner.config.within_limits(&path), TooLong);
+    ensure!(!requested.1, UnknownQuery);
+
+    let text = path.to_str().context(Utf8)?;
+
+    if inner.config.is_manifest(text) {
+        let manifest = Manifest::render(&inner.config, &inner.assets());
+        return Ok(StaticResponse::Manifest(manifest));
+    }
+
+    let (bundle, text) = match split_bundle(text) {
+        Some((bundle, rest)) if inner.config.bundle_path => (Some(bundle), rest),
+        _ => (None, text),
+    };
+
+    let mut options = Options {
+        bundle,
+        expected_revision: requested.0.as_deref(),
+        trailing_slash: uri.trailing_slash,
+        request_uri: Some(&uri.uri),
+        head: method == Method::Head,
+        ..Options::default()
+    };
+
+    // The variant's URL is shared by every ratio, so it can't be versioned.
+    let variant = dpr_variant(&static_files, text, dpr.0);
+    let text = match &variant {
+        Some(variant) => {
+            options.pinned = true;
+            variant.as_str()
+        }
+        None => text,
+    };
+
+    let mut response = match serve(&static_files, text, options, accept, &conditional) {
+        Err(Error::Io { source }) if source.kind() == io::ErrorKind::NotFound => {
+            match static_files.0.config.fallback_for(text) {
+                Some(index) => {
+                    let options = Options {
+                        pinned: true,
+                        ..Options::default()
+                    };
+
+                    serve(&static_files, index, options, accept, &conditional)
+                }
+                None => Err(Error::Io { source }),
+            }
+        }
+        other => other,
+    };
+
+    if variant.is_some() {
+        // Both, since either hint picks the variant.
+        let vary = Header::new("Vary", "Sec-CH-DPR, DPR");
+
+        match &mut response {
+            Ok(StaticResponse::File(file)) => file.headers.push(vary),
+            Ok(StaticResponse::NotModified(not_modified)) => not_modified.headers.push(vary),
+            _ => (),
+        }
+    }
+
+    if let Some(hook) = &static_files.0.on_access {
+        let served = matches!(
+            response,
+            Ok(StaticResponse::File(_)) | Ok(StaticResponse::NotModified(_))
+        );
+
+        if served {
+            (hook.0)(text);
+        }
+    }
+
+    response
+}
+
+/// `Expires` for the `max-age` in `cache_control`, counted from `now`.
+fn expires_header(cache_control: &CacheControl, now: SystemTime) -> Option<Header<'static>> {
+    let max_age = cache_control.0.iter().find_map(|d| match d {
+        CacheDirective::MaxAge(secs) => Some(*secs),
+        _ => None,
+    })?;
+
+    let at = httpdate::unix_secs(now) + u64::from(max_age);
+    Some(Header::new("Expires", httpdate::format(at)))
+}
+
+/// The `dpr_variants` file to serve for `path` at device pixel ratio `dpr`
+/// (1 if the client didn't say): the one for the highest configured ratio up
+/// to `dpr`, falling back to the lowest. `None` if neither exists.
+fn dpr_variant(static_files: &StaticFiles, path: &str, dpr: Option<f64>) -> Option<String> {
+    let config = &static_files.0.config;
+    if config.dpr_variants.is_empty() {
+        return None;
+    }
+
+    let mut ratios: Vec<(f64, &str)> = config
+        .dpr_variants
+        .iter()
+        .filter_map(|(ratio, suffix)| Some((ratio.parse().ok()?, suffix.as_str())))
+        .collect();
+    ratios.sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap_or(std::cmp::Ordering::Equal));
+
+    let dpr = dpr.unwrap_or(1.0);
+    let chosen = ratios.iter().rev().find(|(ratio, _)| *ratio <= dpr);
+
+    let exists = |variant: &String| {
+        let key = static_files.0.key(variant);
+        Target::resolve(&static_files.0, Path::new(&*key))
+            .map(|t| t.is_file())
+            .unwrap_or(false)
+    };
+
+    chosen
+        .into_iter()
+        .chain(ratios.first())
+        .filter_map(|(_, suffix)| with_suffix(path, suffix))
+        .find(exists)
+}
+
+/// `path` with `suffix` inserted before the file name's extension, as in
+/// `img.png` to `img@2x.png`.
+fn with_suffix(path: &str, suffix: &str) -> Option<String> {
+    let name = path.rfind('/').map(|i| i + 1).unwrap_or(0);
+    let dot = name + path[name..].rfind('.').filter(|&i| i > 0)?;
+
+    Some(format!("{}{}{}", &path[..dot], suffix, &path[dot..]))
+}
+
+/// The client's device pixel ratio, from the `Sec-CH-DPR` (or older `DPR`)
+/// client hint.
+struct ClientDpr(Option<f64>);
+
+impl<'a, 'r> FromRequest<'a, 'r> for ClientDpr {
+    type Error = ();
+
+    fn from_request(request: &'a Request<'r>) -> Outcome<Self, Self::Error> {
+        let headers = request.headers();
+        let dpr = headers
+            .get_one("Sec-CH-DPR")
+            .or_else(|| headers.get_one("DPR"))
+            .and_then(|v| v.trim().parse::<f64>().ok())
+            .filter(|v| v.is_finite() && *v > 0.0);
+
+        Outcome::Success(ClientDpr(dpr))
+    }
+}
+
+/// Split a leading `<bundle hash>/` segment off `path`, current or not.
+fn split_bundle(path: &str) -> Option<(&str, &str)> {
+    let slash = path.find('/')?;
+    let (first, rest) = (&path[..slash], &path[slash + 1..]);
+    let is_hash = first.len() == 16 && first.bytes().all(|b| b.is_ascii_hexdigit());
+
+    if is_hash && !rest.is_empty() {
+        Some((first, rest))
+    } else {
+        None
+    }
+}
+
+/// `path_prefix` itself, with or without its trailing slash, which
+/// `<segments..>` doesn't match.
+#[get("/")]
+fn serve_prefix(
+    method: Method,
+    requested: RequestedVersion,
+    uri: RequestUri,
+    accept: AcceptEncoding,
+    conditional: Conditional,
+    dpr: ClientDpr,
+    static_files: StaticFiles,
+) -> Result<WithCacheStatus<StaticResponse>, Error> {
+    serve_static(
+        Segments(""),
+        method,
+        requested,
+        uri,
+        accept,
+        conditional,
+        dpr,
+        static_files,
+    )
+}
+
+/// Aliases get the same headers as any other unversioned file, and are never
+/// redirected to a versioned URL.
+#[get("/<_name>", rank = 20)]
+fn serve_root_alias(
+    _name: String,
+    alias: RootAlias,
+    accept: AcceptEncoding,
+    conditional: Conditional,
+    static_files: StaticFiles,
+) -> Result<WithCacheStatus<StaticResponse>, Error> {
+    let options = Options {
+        pinned: true,
+        ..Options::default()
+    };
+
+    let response = serve(&static_files, &alias.0, options, accept, &conditional)?;
+    Ok(static_files.with_cache_status(response))
+}
+
+/// How [`serve`] treats a request, beyond its path.
+#[derive(Debug, Default, Clone, Copy)]
+struct Options<'a> {
+    /// The version token the request asked for.
+    expected_revision: Option<&'a str>,
+
+    /// Serve like a `pin`ned file: no redirect, no long cache.
+    pinned: bool,
+
+    /// The request path ended in a `/`.
+    trailing_slash: bool,
+
+    /// The request is a `HEAD`.
+    head: bool,
+
+    /// The `<bundle hash>/` segment split off the path, with `bundle_path`.
+    bundle: Option<&'a str>,
+
+    /// The URI the client asked for, to catch redirects back to it.
+    request_uri: Option<&'a str>,
+}
+
+/// Serve `requested` (relative to `serve_from`).
+fn serve(
+    static_files: &StaticFiles,
+    requested: &str,
+    options: Options,
+    accept: AcceptEncoding,
+    conditional: &Conditional,
+) -> Result<StaticResponse, Error> {
+    let Options {
+        expected_revision,
+        pinned,
+        trailing_slash,
+        head,
+        bundle,
+        request_uri,
+    } = options;
+
+    let config = &static_files.0.config;
+    let text = static_files.0.key(requested);
+    let text = &*text;
+    let path = Path::new(text);
+
+    let target = Target::resolve(&static_files.0, path)?;
+
+    // Directories, including `path_prefix` itself, are answered with their
+    // index file at the directory's own URL.
+    if let Some(index) = &config.index_file {
+        if target.is_dir() {
+            let joined = match text.trim_end_matches('/') {
+                "" => index.clone(),
+                dir => format!("{}/{}", dir, index),
+            };
+
+            let options = Options {
+                pinned: true,
+                trailing_slash: false,
+                ..options
+            };
+
+            return serve(static_files, &joined, options, accept, conditional);
+        }
+    }
+
+    ensure!(!config.is_hidden(text), Hidden);
+
+    let asset = static_files.0.asset(text);
+
+    // Pinned files are served like untracked ones: no redirect, no long cache.
+    // Files in `no_token_dirs` aren't redirected either, and are immutable.
+    let tokenless = !pinned && config.is_tokenless(text);
+    let current_revision = asset
+        .filter(|_| !pinned && !config.is_pinned(text) && !tokenless)
+        .map(|a| a.version);
+
+    // With `bundle_path`, the one bundle hash stands in for every token.
+    let (expected_revision, current_revision) = if config.bundle_path {
+        (bundle, current_revision.map(|_| static_files.bundle_hash()))
+    } else {
+        (expected_revision, current_revision)
+    };
+
+    let versioned_url = |current: &str| {
+        if config.bundle_path {
+            config.bundled_url(text, current)
+        } else {
+            config.versioned_url(text, current)
+        }
+    };
+
+    // `app.js/` names a file, not a directory; send clients to the real URL.
+    // That never ends in a slash, so this can't loop.
+    if trailing_slash && target.is_file() {
+        let url = match current_revision {
+            Some(current) => versioned_url(current),
+            None => config.file_url(text),
+        };
+
+        return Ok(StaticResponse::Redirect(FileRedirect {
+            redirect: Redirect::moved(config.redirect_url(url)),
+            cache_control: config.redirect_cache_control(),
+        }));
+    }
+
+    let cache = match (expected_revision, current_revision) {
+        (Some(expected), Some(current)) if expected == current => true,
+        (_, Some(_)) if !config.version_redirect => false,
+        (_, Some(_)) if head && !config.redirect_head => false,
+        (None, Some(_)) if config.serve_unversioned => false,
+        (_, Some(current)) => {
+            let url = versioned_url(current);
+
+            // Only a misconfiguration (of `base_href`, say) could get here, but
+            // the client would follow it forever.
+            let absolute = config.redirect_url(url.clone());
+            let looped = request_uri.map(|r| r == url || r == absolute);
+            ensure!(!looped.unwrap_or(false), RedirectLoop);
+
+            let redir = Redirect::to(absolute);
+            return Ok(StaticResponse::Redirect(FileRedirect {
+                redirect: redir,
+                cache_control: config.redirect_cache_control(),
+            }));
+        }
+        (_, None) => tokenless,
+    };
+
+    let never_cache = config.never_caches(target.path());
+    let cache_control = if never_cache {
+        CacheControl(vec![CacheDirective::NoCache])
+    } else if tokenless {
+        config.tokenless_cache_control(text)
+    } else {
+        config.cache_control_for(text, cache)
+    };
+
+    // Rewritten pages change whenever any asset does, so their validators
+    // have to as well, and they can't be sent precompressed.
+    let rewrite = config.rewrite_html && config.is_html(target.path());
+
+    // Streaming compilation only gets a `.wasm` body the browser decoded
+    // first, so it's sent in a coding the browser named, not one `*` allows.
+    let named_only = config.is_wasm(target.path());
+    let encoding = asset
+        .as_ref()
+        .and_then(|a| accept.choose(a, named_only))
+        .filter(|_| !rewrite);
+
+    #[cfg(feature = "dictionary")]
+    let (encoding, dictionary_vary) = dictionary_encoding(
+        &static_files.0,
+        &target,
+        &accept,
+        asset.as_ref(),
+        rewrite,
+        encoding,
+    );
+    #[cfg(not(feature = "dictionary"))]
+    let dictionary_vary: Option<Header<'static>> = None;
+
+    let mut etag = match asset {
+        Some(asset) => Some(response::etag(asset.version, encoding)),
+        None => target.weak_etag(),
+    };
+
+    if rewrite {
+        etag = etag.map(|e| response::etag_suffixed(&e, static_files.bundle_hash()));
+    }
+
+    // Revalidations are answered from the token (or a stat) alone, without
+    // opening the file.
+    let fresh = conditional.is_fresh(etag.as_deref(), || {
+        target.modified(encoding).filter(|_| !rewrite)
+    });
+
+    let expires = if config.expires {
+        expires_header(&cache_control, static_files.0.now())
+    } else {
+        None
+    };
+
+    if fresh {
+        let headers = static_files
+            .0
+            .header_rules
+            .matching(text)
+            .chain(expires.clone())
+            .chain(dictionary_vary.clone());
+
+        return Ok(StaticResponse::NotModified(NotModified {
+            etag,
+            cache_control,
+            vary: response::vary(asset.as_ref()),
+            headers: headers.filter(|h| !config.defers(h.name())).collect(),
+        }));
+    }
+
+    let html = config.is_html(target.path());
+
+    #[cfg(feature = "log")]
+    let started = Instant::now();
+
+    let mut file = match static_files.0.warmed(&target, encoding) {
+        Some(warm) => FileResponse::from_warm(
+            config,
+            target.path(),
+            &warm,
+            cache_control,
+            asset.as_ref(),
+            encoding,
+        )?,
+        None => target.open(config, cache_control, asset.as_ref(), encoding)?,
+    };
+
+    // A full response carries the very tag the client's `If-None-Match` was
+    // just compared against, so a stale one is always replaced. The file's
+    // own (say, when warmed, or statted again on opening) could differ.
+    file.etag = etag;
+    file.versioned = cache && !never_cache;
+
+    #[cfg(feature = "log")]
+    {
+        if let Some(slow) = config.slow_open_ms {
+            warn_if_slow(text, started, Duration::from_millis(slow), file.body_mut())?;
+        }
+    }
+
+    if html {
+        file.headers.extend(static_files.preload_header());
+    }
+
+    file.headers
+        .extend(static_files.0.header_rules.matching(text));
+    file.headers.extend(expires);
+    file.headers.extend(dictionary_vary);
+    file.headers.extend(
+        config
+            .use_as_dictionary(text)
+            .map(|m| Header::new("Use-As-Dictionary", m)),
+    );
+
+    // Whatever sets these later only fills in headers that are missing, so
+    // they have to be left out entirely here.
+    file.headers.retain(|h| !config.defers(h.name()));
+
+    Ok(StaticResponse::File(file))
+}
+
+/// Read the first chunk of `body`, and warn if getting that far (opening the
+/// file, since `started`, included) took `slow` or longer. A file can open
+/// quickly and still stall on its first read, from a cold disk or a network
+/// mount, say.
+#[cfg(feature = "log")]
+fn warn_if_slow<R: io::BufRead>(
+    text: &str,
+    started: Instant,
+    slow: Duration,
+    body: &mut R,
+) -> Result<(), Error> {
+    body.fill_buf().context(Io)?;
+
+    let elapsed = started.elapsed();
+    if elapsed >= slow {
+        log::warn!(
+            "static file {} took {:?} to open and start reading",
+            text,
+            elapsed
+        );
+    }
+
+    Ok(())
+}
+
+/// `encoding`, or `dcb` if the client has the dictionary the file's `dcb`
+/// sibling was compressed against; plus the `Vary` that choice calls for.
+///
+/// Untracked files are left alone, since their `ETag` wouldn't tell the two
+/// apart.
+#[cfg(feature = "dictionary")]
+fn dictionary_encoding(
+    inner: &Inner,
+    target: &Target,
+    accept: &AcceptEncoding,
+    asset: Option<&Asset>,
+    rewrite: bool,
+    encoding: Option<Encoding>,
+) -> (Option<Encoding>, Option<Header<'static>>) {
+    let dictionary = match accept.dictionary() {
+        Some(dictionary) if asset.is_some() && !rewrite => dictionary,
+        _ => return (encoding, None),
+    };
+
+    // Whichever is sent, it depended on the dictionary the client has.
+    let vary = Header::new("Vary", "Accept-Encoding, Available-Dictionary");
+
+    match target {
+        Target::Disk(path)
+            if inner.dictionary_hash(&Encoding::DictionaryBrotli.sibling(path))
+                == Some(dictionary) =>
+        {
+            (Some(Encoding::DictionaryBrotli), Some(vary))
+        }
+        _ => (encoding, Some(vary)),
+    }
+}
+
+/// The path `serve_static` was asked for, relative to `serve_from`: a `400`
+/// if it isn't UTF-8, and hidden (before anything looks for it) if it's a
+/// dotfile that `serve_dotfiles_allowlist` doesn't let through.
+fn request_path(config: &Config, segments: Segments) -> Result<PathBuf, Error> {
+    let path = dotted_path(segments)?;
+    let text = path.to_str().context(Utf8)?;
+
+    ensure!(!config.is_hidden(text), Hidden);
+    Ok(path)
+}
+
+/// Decode `segments` like Rocket does into a `PathBuf`, except that segments
+/// may start with a `.` (but not be `.` or `..`).
+fn dotted_path(segments: Segments) -> Result<PathBuf, Error> {
+    let mut path = PathBuf::new();
+
+    for segment in segments {
+        let decoded = RawStr::from_str(segment)
+            .percent_decode()
+            .ok()
+            .context(Utf8)?;
+
+        let bad = decoded == "." || decoded == "..";
+        let bad = bad || decoded.contains(|c: char| c == '/' || c == '\\' || c == '\0');
+        ensure!(!bad, OutOfBounds);
+
+        path.push(&*decoded);
+    }
+
+    Ok(path)
+}
+
+/// Where a requested file lives.
+enum Target {
+    /// On disk, under `serve_from`.
+    Disk(PathBuf),
+
+    /// In the archive `serve_from` points at, named with `/` separators.
+    #[cfg(feature = "zip")]
+    Archived(Arc<Archive>, String),
+
+    /// Embedded in the map, under this path.
+    Embedded(&'static [u8], String),
+}
+
+impl Target {
+    fn resolve(inner: &Inner, path: &Path) -> Result<Self, Error> {
+        let config = &inner.config;
+
+        if let Some(name) = path.to_str() {
+            if let Some(bytes) = inner.asset(name).and_then(|a| a.embedded) {
+                return Ok(Target::Embedded(bytes, name.to_owned()));
+            }
+        }
+
+        #[cfg(feature = "zip")]
+        {
+            if let Some(archive) = &inner.archive {
+                let relative = lexical::normalize(path).context(OutOfBounds)?;
+                let name: Vec<_> = relative
+                    .iter()
+                    .map(|c| c.to_str().context(Utf8))
+                    .collect::<Result<_, _>>()?;
+
+                return Ok(Target::Archived(archive.clone(), name.join("/")));
+            }
+        }
+
+        // Earlier directories override later ones. A file that's in none of
+        // them is reported missing from the first.
+        let resolve = |dir: &Path| resolve_within(dir, path, config.skip_canonicalize);
+
+        let found = config
+            .serve_from
+            .dirs()
+            .iter()
+            .find_map(|dir| resolve(dir).ok().filter(|t| t.exists()));
+
+        match found {
+            Some(target) => Ok(Target::Disk(target)),
+            None => Ok(Target::Disk(resolve(&config.serve_from)?)),
+        }
+    }
+
+    /// Path to guess the content type from.
+    fn path(&self) -> &Path {
+        match self {
+            Target::Disk(path) => path,
+            #[cfg(feature = "zip")]
+            Target::Archived(_, name) => Path::new(name),
+            Target::Embedded(_, name) => Path::new(name),
+        }
+    }
+
+    fn is_dir(&self) -> bool {
+        match self {
+            Target::Disk(path) => path.is_dir(),
+            #[cfg(feature = "zip")]
+            Target::Archived(archive, name) => archive.is_dir(name),
+            Target::Embedded(..) => false,
+        }
+    }
+
+    fn is_file(&self) -> bool {
+        match self {
+            Target::Disk(path) => path.is_file(),
+            #[cfg(feature = "zip")]
+            Target::Archived(archive, name) => archive.is_file(name),
+            Target::Embedded(..) => true,
+        }
+    }
+
+    /// Modification time of the file (or its sibling, for `encoding`).
+    /// Archive members and embedded files don't have a usable one.
+    fn modified(&self, encoding: Option<Encoding>) -> Option<u64> {
+        match self {
+            Target::Disk(path) => {
+                let served = match encoding {
+                    Some(encoding) => encoding.sibling(path),
+                    None => path.clone(),
+                };
+
+                let modified = std::fs::metadata(served).ok()?.modified().ok()?;
+                Some(httpdate::unix_secs(modified))
+            }
+            #[cfg(feature = "zip")]
+            Target::Archived(..) => None,
+            Target::Embedded(..) => None,
+        }
+    }
+
+    /// An `ETag` for when there's no version token to build one from.
+    fn weak_etag(&self) -> Option<String> {
+        match self {
+            Target::Disk(path) => response::weak_etag(&std::fs::metadata(path).ok()?),
+            #[cfg(feature = "zip")]
+            Target::Archived(..) => None,
+            Target::Embedded(..) => None,
+        }
+    }
+
+    /// A `size-mtime` version token, for files missing from the map.
+    fn stat_token(&self) -> Option<String> {
+        match self {
+            Target::Disk(path) => response::stat_token(&std::fs::metadata(path).ok()?),
+            #[cfg(feature = "zip")]
+            Target::Archived(..) => None,
+            Target::Embedded(..) => None,
+        }
+    }
+
+    fn open(
+        self,
+        config: &Config,
+        cache_control: CacheControl,
+        asset: Option<&Asset>,
+        encoding: Option<Encoding>,
+    ) -> Result<FileResponse, Error> {
+        match self {
+            Target::Disk(path) => FileResponse::new(config, path, cache_control, asset, encoding),
+            #[cfg(feature = "zip")]
+            Target::Archived(archive, name) => {
+                let contents = match encoding {
+                    Some(encoding) => {
+                        archive.read(&format!("{}.{}", name, encoding.extension()))?
+                    }
+                    None => archive.read(&name)?,
+                };
+
+                FileResponse::from_memory(
+                    config,
+                    Path::new(&name),
+                    contents,
+                    cache_control,
+                    asset,
+                    encoding,
+                )
+            }
+            Target::Embedded(bytes, name) => {
+                let gzipped = asset.and_then(|a| a.embedded_size);
+
+                match (encoding, gzipped) {
+                    (None, None) | (Some(Encoding::Gzip), Some(_)) => FileResponse::from_static(
+                        config,
+                        Path::new(&name),
+                        bytes,
+                        cache_control,
+                        asset,
+                        encoding,
+                    ),
+                    (None, Some(size)) => FileResponse::gunzip(
+                        config,
+                        Path::new(&name),
+                        bytes,
+                        size,
+                        cache_control,
+                        asset,
+                    ),
+                    // A precompressed sibling that wasn't embedded. Virtual
+                    // files never have one, so this is always a file on disk.
+                    _ => FileResponse::new(
+                        config,
+                        config.serve_from.find(Path::new(&name)),
+                        cache_control,
+                        asset,
+                        encoding,
+                    ),
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,3099 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/. */
+
+use super::*;
+
+use crate::map::SortedMap;
+use crate::testing::{self, TempDir, ASSET};
+
+use rocket::http::{Header, Status};
+use rocket::local::Client;
+use rocket::response::content;
+
+#[test]
+fn serves_precompressed_siblings_to_clients_accepting_them() {
+    static MAP: SortedMap = SortedMap::new(&[(
+        "site.css",
+        Asset {
+            version: "v1",
+            gzip: Some(13),
+            ..ASSET
+        },
+    )]);
+
+    let (_dir, client) = testing::serve(
+        &[("site.css", "body {}"), ("site.css.gz", "gzipped bytes")],
+        StaticFiles::fairing(&MAP),
+        "",
+    );
+
+    let mut response = client
+        .get("/static/site.css?v=v1")
+        .header(Header::new("Accept-Encoding", "gzip, deflate"))
+        .dispatch();
+    assert_eq!(response.status(), Status::Ok);
+    assert_eq!(response.headers().get_one("Content-Encoding"), Some("gzip"));
+    assert_eq!(response.headers().get_one("Vary"), Some("Accept-Encoding"));
+    assert_eq!(response.body_string().as_deref(), Some("gzipped bytes"));
+
+    let mut response = client.get("/static/site.css?v=v1").dispatch();
+    assert_eq!(response.headers().get_one("Content-Encoding"), None);
+    assert_eq!(response.body_string().as_deref(), Some("body {}"));
+}
+
+#[get("/generated/<name>")]
+fn generated(name: String) -> String {
+    name
+}
+
+#[get("/elsewhere")]
+fn elsewhere() -> &'static str {
+    "elsewhere"
+}
+
+const PAGE: &str = "<script src=\"/static/app.js\"></script>\n\
+                    <link href='/static/other.css'>\n\
+                    <a href=\"/static/app.js#top\">/static/app.js</a>";
+
+#[get("/page")]
+fn page() -> content::Html<&'static str> {
+    content::Html(PAGE)
+}
+
+#[get("/text")]
+fn text() -> &'static str {
+    PAGE
+}
+
+#[get("/download/latest")]
+fn latest(static_files: State<StaticFiles>) -> Option<AssetResponse> {
+    static_files.respond("releases/app-1.2.zip").ok()
+}
+
+#[test]
+fn injects_cache_headers_into_other_routes_under_the_prefix() {
+    static MAP: SortedMap = SortedMap::new(&[(
+        "generated/report.txt",
+        Asset {
+            version: "v1",
+            ..ASSET
+        },
+    )]);
+
+    let dir = TempDir::new();
+    let rocket = testing::rocket(dir.path(), "inject_headers = true")
+        .attach(StaticFiles::fairing(&MAP))
+        .mount("/static", routes![generated])
+        .mount("/", routes![elsewhere]);
+    let client = Client::new(rocket).unwrap();
+
+    let mut response = client.get("/static/generated/report.txt?v=v1").dispatch();
+    assert_eq!(response.body_string().as_deref(), Some("report.txt"));
+    let cache_control = response.headers().get_one("Cache-Control").unwrap();
+    assert!(cache_control.starts_with("max-age=31536000"));
+
+    let response = client.get("/elsewhere").dispatch();
+    assert_eq!(response.headers().get_one("Cache-Control"), None);
+
+    // Left alone unless asked for.
+    let rocket = testing::rocket(dir.path(), "")
+        .attach(StaticFiles::fairing(&MAP))
+        .mount("/static", routes![generated]);
+    let client = Client::new(rocket).unwrap();
+    let response = client.get("/static/generated/report.txt?v=v1").dispatch();
+    assert_eq!(response.headers().get_one("Cache-Control"), None);
+}
+
+#[test]
+fn pinned_files_keep_a_stable_url_and_revalidate() {
+    static MAP: SortedMap = SortedMap::new(&[
+        (
+            "app.js",
+            Asset {
+                version: "v1",
+                ..ASSET
+            },
+        ),
+        (
+            "favicon.ico",
+            Asset {
+                version: "v2",
+                ..ASSET
+            },
+        ),
+    ]);
+
+    let (_dir, client) = testing::serve(
+        &[("app.js", "app"), ("favicon.ico", "icon")],
+        StaticFiles::fairing(&MAP),
+        "pin = [\"/favicon.ico\"]",
+    );
+
+    let static_files = client.rocket().state::<StaticFiles>().unwrap();
+    assert_eq!(static_files.to("favicon.ico"), "/static/favicon.ico");
+    assert_eq!(static_files.to("app.js"), "/static/app.js?v=v1");
+
+    let response = client.get("/static/favicon.ico").dispatch();
+    assert_eq!(response.status(), Status::Ok);
+    assert_eq!(
+        response.headers().get_one("Cache-Control"),
+        Some("no-cache")
+    );
+}
+
+#[test]
+fn renders_script_and_link_tags() {
+    static MAP: SortedMap = SortedMap::new(&[
+        (
+            "app.js",
+            Asset {
+                version: "v1",
+                integrity: Some("sha256-abc"),
+                ..ASSET
+            },
+        ),
+        (
+            "site.css",
+            Asset {
+                version: "v2",
+                ..ASSET
+            },
+        ),
+    ]);
+
+    let (_dir, client) = testing::serve(&[], StaticFiles::fairing(&MAP), "");
+    let static_files = client.rocket().state::<StaticFiles>().unwrap();
+
+    assert_eq!(
+        static_files.script_tag("app.js"),
+        "<script src=\"/static/app.js?v=v1\" integrity=\"sha256-abc\" \
+         crossorigin=\"anonymous\"></script>"
+    );
+    assert_eq!(
+        static_files.link_tag("site.css"),
+        "<link rel=\"stylesheet\" href=\"/static/site.css?v=v2\">"
+    );
+}
+
+static EMPTY: SortedMap = SortedMap::new(&[]);
+
+#[cfg(feature = "reload")]
+#[test]
+fn reload_picks_up_new_files() {
+    let (dir, client) = testing::serve(&[("app.js", "app")], StaticFiles::fairing(&EMPTY), "");
+    let static_files = client.rocket().state::<StaticFiles>().unwrap();
+
+    assert_eq!(static_files.to("new.js"), "/static/new.js");
+
+    dir.write("new.js", "new");
+    static_files.reload(dir.path()).unwrap();
+
+    let url = static_files.to("new.js");
+    assert!(url.starts_with("/static/new.js?v="), "{}", url);
+    assert!(static_files.to("app.js").starts_with("/static/app.js?v="));
+
+    let response = client.get("/static/new.js").dispatch();
+    assert_eq!(response.status(), Status::SeeOther);
+    assert_eq!(response.headers().get_one("Location"), Some(&url[..]));
+
+    let mut response = client.get(url).dispatch();
+    assert_eq!(response.status(), Status::Ok);
+    assert_eq!(response.body_string().as_deref(), Some("new"));
+}
+
+#[test]
+fn strip_bom_drops_a_leading_byte_order_mark() {
+    static MAP: SortedMap = SortedMap::new(&[
+        (
+            "binary.bin",
+            Asset {
+                version: "v1",
+                ..ASSET
+            },
+        ),
+        (
+            "notes.txt",
+            Asset {
+                version: "v1",
+                ..ASSET
+            },
+        ),
+    ]);
+
+    let (_dir, client) = testing::serve(
+        &[
+            ("notes.txt", "\u{feff}hello"),
+            ("binary.bin", "\u{feff}bytes"),
+        ],
+        StaticFiles::fairing(&MAP),
+        "strip_bom = true",
+    );
+
+    let mut response = client.get("/static/notes.txt?v=v1").dispatch();
+    assert_eq!(response.body_string().as_deref(), Some("hello"));
+
+    // Only text is touched.
+    let mut response = client.get("/static/binary.bin?v=v1").dispatch();
+    assert_eq!(response.body_string().as_deref(), Some("\u{feff}bytes"));
+}
+
+#[test]
+fn applies_sidecar_header_rules() {
+    static MAP: SortedMap = SortedMap::new(&[
+        (
+            "_headers",
+            Asset {
+                version: "v0",
+                ..ASSET
+            },
+        ),
+        (
+            "fonts/icons.woff2",
+            Asset {
+                version: "v1",
+                ..ASSET
+            },
+        ),
+    ]);
+
+    let (_dir, client) = testing::serve(
+        &[
+            ("_headers", "/fonts/*\n  Access-Control-Allow-Origin: *\n"),
+            ("fonts/icons.woff2", "font"),
+        ],
+        StaticFiles::fairing(&MAP),
+        "sidecar_headers = true",
+    );
+
+    let response = client.get("/static/fonts/icons.woff2?v=v1").dispatch();
+    assert_eq!(response.status(), Status::Ok);
+    assert_eq!(
+        response.headers().get_one("Access-Control-Allow-Origin"),
+        Some("*")
+    );
+
+    // The rules themselves aren't served.
+    let response = client.get("/static/_headers?v=v0").dispatch();
+    assert_eq!(response.status(), Status::NotFound);
+}
+
+#[test]
+fn answers_revalidation_without_opening_the_file() {
+    static MAP: SortedMap = SortedMap::new(&[(
+        "app.js",
+        Asset {
+            version: "v1",
+            ..ASSET
+        },
+    )]);
+
+    // Couldn't be opened as a file, so only a response that never tries can
+    // succeed.
+    let dir = TempDir::new();
+    std::fs::create_dir(dir.path().join("app.js")).unwrap();
+    let client = testing::client(StaticFiles::fairing(&MAP), dir.path(), "");
+
+    let response = client
+        .get("/static/app.js?v=v1")
+        .header(Header::new("If-None-Match", "\"v1\""))
+        .dispatch();
+    assert_eq!(response.status(), Status::NotModified);
+    assert_eq!(response.headers().get_one("ETag"), Some("\"v1\""));
+
+    let response = client.get("/static/app.js?v=v1").dispatch();
+    assert_eq!(response.status(), Status::NotFound);
+}
+
+#[test]
+fn custom_version_param_round_trips() {
+    static MAP: SortedMap = SortedMap::new(&[(
+        "app.js",
+        Asset {
+            version: "v1",
+            ..ASSET
+        },
+    )]);
+
+    let (_dir, client) = testing::serve(
+        &[("app.js", "app")],
+        StaticFiles::fairing(&MAP),
+        "version_param = \"rev\"",
+    );
+    let static_files = client.rocket().state::<StaticFiles>().unwrap();
+
+    let url = static_files.to("app.js");
+    assert_eq!(url, "/static/app.js?rev=v1");
+
+    let response = client.get(url).dispatch();
+    assert_eq!(response.status(), Status::Ok);
+    let cache_control = response.headers().get_one("Cache-Control").unwrap();
+    assert!(cache_control.starts_with("max-age=31536000"));
+
+    let response = client.get("/static/app.js?v=v1").dispatch();
+    assert_eq!(response.status(), Status::SeeOther);
+    assert_eq!(
+        response.headers().get_one("Location"),
+        Some("/static/app.js?rev=v1")
+    );
+}
+
+#[test]
+fn rejects_paths_that_are_not_utf8_with_400() {
+    let (_dir, client) = testing::serve(
+        &[("café.txt", "coffee"), (".env", "SECRET=1")],
+        StaticFiles::etag_only(),
+        "",
+    );
+
+    let response = client.get("/static/%FF.txt").dispatch();
+    assert_eq!(response.status(), Status::BadRequest);
+
+    let mut response = client.get("/static/caf%C3%A9.txt").dispatch();
+    assert_eq!(response.status(), Status::Ok);
+    assert_eq!(response.body_string().as_deref(), Some("coffee"));
+
+    let response = client.get("/static/%2e%2e/secret").dispatch();
+    assert_eq!(response.status(), Status::NotFound);
+
+    let response = client.get("/static/.env").dispatch();
+    assert_eq!(response.status(), Status::NotFound);
+}
+
+#[test]
+fn leaves_a_served_file_marker_for_later_fairings() {
+    use crate::response::ServedFile;
+
+    use rocket::fairing::AdHoc;
+
+    static MAP: SortedMap = SortedMap::new(&[(
+        "site.css",
+        Asset {
+            version: "v1",
+            gzip: Some(13),
+            ..ASSET
+        },
+    )]);
+
+    let dir = TempDir::new();
+    dir.write("site.css", "body {}");
+    dir.write("site.css.gz", "gzipped bytes");
+
+    let report = AdHoc::on_response("Report", |request, response| {
+        let served = match request.local_cache(|| None::<ServedFile>) {
+            Some(ServedFile {
+                encoding: Some(encoding),
+            }) => encoding,
+            Some(_) => "identity",
+            None => "none",
+        };
+
+        response.set_raw_header("X-Served", served);
+    });
+
+    let rocket = testing::rocket(dir.path(), "")
+        .attach(StaticFiles::fairing(&MAP))
+        .attach(report);
+    let client = Client::new(rocket).unwrap();
+
+    let response = client
+        .get("/static/site.css?v=v1")
+        .header(Header::new("Accept-Encoding", "gzip"))
+        .dispatch();
+    assert_eq!(response.headers().get_one("X-Served"), Some("gzip"));
+
+    let response = client.get("/static/site.css?v=v1").dispatch();
+    assert_eq!(response.headers().get_one("X-Served"), Some("identity"));
+
+    let response = client.get("/static/site.css").dispatch();
+    assert_eq!(response.headers().get_one("X-Served"), Some("none"));
+}
+
+#[test]
+fn html_files_announce_preloads() {
+    static MAP: SortedMap = SortedMap::new(&[
+        (
+            "app.css",
+            Asset {
+                version: "v1",
+                ..ASSET
+            },
+        ),
+        (
+            "index.html",
+            Asset {
+                version: "v2",
+                ..ASSET
+            },
+        ),
+    ]);
+
+    let (_dir, client) = testing::serve(
+        &[("app.css", "body {}"), ("index.html", "<!doctype html>")],
+        StaticFiles::fairing(&MAP),
+        "preload = [{ path = \"/app.css\", as = \"style\" }]",
+    );
+
+    let response = client.get("/static/index.html?v=v2").dispatch();
+    assert_eq!(
+        response.headers().get_one("Link"),
+        Some("</static/app.css?v=v1>; rel=preload; as=style")
+    );
+
+    let response = client.get("/static/app.css?v=v1").dispatch();
+    assert_eq!(response.headers().get_one("Link"), None);
+}
+
+#[test]
+fn case_insensitive_lookup_finds_files_whatever_the_case() {
+    static MAP: SortedMap = SortedMap::new(&[(
+        "css/app.css",
+        Asset {
+            version: "v1",
+            ..ASSET
+        },
+    )]);
+
+    let (_dir, client) = testing::serve(
+        &[("css/app.css", "body {}")],
+        StaticFiles::fairing(&MAP),
+        "case_insensitive_lookup = true",
+    );
+
+    let mut response = client.get("/static/CSS/App.css?v=v1").dispatch();
+    assert_eq!(response.status(), Status::Ok);
+    assert_eq!(response.body_string().as_deref(), Some("body {}"));
+
+    let response = client.get("/static/CSS/App.css").dispatch();
+    assert_eq!(response.status(), Status::SeeOther);
+    assert_eq!(
+        response.headers().get_one("Location"),
+        Some("/static/css/app.css?v=v1")
+    );
+}
+
+#[test]
+fn etag_only_revalidates_with_weak_etags() {
+    let (_dir, client) = testing::serve(&[("app.js", "app")], StaticFiles::etag_only(), "");
+
+    let response = client.get("/static/app.js").dispatch();
+    assert_eq!(response.status(), Status::Ok);
+    assert_eq!(
+        response.headers().get_one("Cache-Control"),
+        Some("no-cache")
+    );
+    let etag = response.headers().get_one("ETag").unwrap().to_owned();
+    assert!(etag.starts_with("W/\""), "{}", etag);
+
+    let response = client
+        .get("/static/app.js")
+        .header(Header::new("If-None-Match", etag.clone()))
+        .dispatch();
+    assert_eq!(response.status(), Status::NotModified);
+    assert_eq!(response.headers().get_one("ETag"), Some(&etag[..]));
+}
+
+#[test]
+fn merged_maps_are_searched_in_order() {
+    static APP: SortedMap = SortedMap::new(&[(
+        "app.js",
+        Asset {
+            version: "app",
+            ..ASSET
+        },
+    )]);
+    static WIDGETS: SortedMap = SortedMap::new(&[
+        (
+            "app.js",
+            Asset {
+                version: "shadowed",
+                ..ASSET
+            },
+        ),
+        (
+            "widgets/chart.js",
+            Asset {
+                version: "chart",
+                ..ASSET
+            },
+        ),
+    ]);
+
+    let dir = TempDir::new();
+    let fairing = StaticFiles::fairing_merged(&[&APP, &WIDGETS]);
+    let client = testing::client(fairing, dir.path(), "");
+    let static_files = client.rocket().state::<StaticFiles>().unwrap();
+
+    assert_eq!(static_files.to("app.js"), "/static/app.js?v=app");
+    assert_eq!(
+        static_files.to("widgets/chart.js"),
+        "/static/widgets/chart.js?v=chart"
+    );
+    assert_eq!(static_files.to("other.js"), "/static/other.js");
+}
+
+/// Takes `delay` over every read.
+#[cfg(feature = "log")]
+struct SlowReader(Duration);
+
+#[cfg(feature = "log")]
+impl io::Read for SlowReader {
+    fn read(&mut self, _: &mut [u8]) -> io::Result<usize> {
+        std::thread::sleep(self.0);
+        Ok(0)
+    }
+}
+
+#[cfg(feature = "log")]
+#[test]
+fn warns_about_files_slow_to_start_reading() {
+    testing::take_logs();
+
+    let mut slow = io::BufReader::new(SlowReader(Duration::from_millis(20)));
+    warn_if_slow(
+        "slow.bin",
+        Instant::now(),
+        Duration::from_millis(10),
+        &mut slow,
+    )
+    .unwrap();
+
+    let mut fast = &b"fast"[..];
+    warn_if_slow(
+        "fast.bin",
+        Instant::now(),
+        Duration::from_secs(60),
+        &mut fast,
+    )
+    .unwrap();
+
+    let logs = testing::take_logs();
+    assert_eq!(logs.len(), 1, "{:?}", logs);
+    assert!(
+        logs[0].starts_with("static file slow.bin took "),
+        "{}",
+        logs[0]
+    );
+}
+
+#[cfg(feature = "log")]
+#[test]
+fn times_requests_through_the_first_read() {
+    let (_dir, client) = testing::serve(
+        &[("app.js", "app")],
+        StaticFiles::etag_only(),
+        "slow_open_ms = 0",
+    );
+
+    testing::take_logs();
+    let mut response = client.get("/static/app.js").dispatch();
+    assert_eq!(response.body_string().as_deref(), Some("app"));
+
+    let logs = testing::take_logs();
+    assert!(
+        logs.iter()
+            .any(|l| l.starts_with("static file app.js took ")),
+        "{:?}",
+        logs
+    );
+}
+
+#[test]
+fn serves_root_aliases() {
+    static MAP: SortedMap = SortedMap::new(&[(
+        "icons/favicon.ico",
+        Asset {
+            version: "v1",
+            ..ASSET
+        },
+    )]);
+
+    let (_dir, client) = testing::serve(
+        &[("icons/favicon.ico", "icon")],
+        StaticFiles::fairing(&MAP),
+        "root_aliases = { \"favicon.ico\" = \"icons/favicon.ico\" }",
+    );
+
+    let mut response = client.get("/favicon.ico").dispatch();
+    assert_eq!(response.status(), Status::Ok);
+    assert_eq!(
+        response.headers().get_one("Cache-Control"),
+        Some("no-cache")
+    );
+    assert_eq!(response.body_string().as_deref(), Some("icon"));
+
+    let response = client.get("/robots.txt").dispatch();
+    assert_eq!(response.status(), Status::NotFound);
+}
+
+#[cfg(feature = "reload")]
+#[test]
+fn reload_hashes_with_the_configured_sip_keys() {
+    let dir = TempDir::new();
+    dir.write("app.js", "app");
+    let fairing = StaticFiles::fairing(&EMPTY).sip_keys((3, 5));
+    let client = testing::client(fairing, dir.path(), "");
+    let static_files = client.rocket().state::<StaticFiles>().unwrap();
+
+    static_files.reload(dir.path()).unwrap();
+
+    let (files, _) = Generator::with_source(Filesystem::quiet(dir.path()))
+        .sip_keys(3, 5)
+        .scan()
+        .unwrap();
+    assert_eq!(
+        static_files.to("app.js"),
+        format!("/static/app.js?v={}", files["app.js"].token)
+    );
+}
+
+#[test]
+fn serve_errors_say_what_went_wrong() {
+    use crate::ServeError;
+
+    let dir = TempDir::new();
+    dir.write("public/app.js", "app");
+    dir.write("public/css/site.css", "body {}");
+    dir.write("secret.txt", "secret");
+    let public = dir.path().join("public");
+    let client = testing::client(StaticFiles::etag_only(), &public, "");
+    let static_files = client.rocket().state::<StaticFiles>().unwrap();
+
+    assert!(static_files.respond("app.js").is_ok());
+
+    match static_files.respond("missing.js") {
+        Err(error @ ServeError::Io { .. }) => assert_eq!(error.status(), Status::NotFound),
+        other => panic!("expected an IO error, got {:?}", other),
+    }
+
+    match static_files.respond("css") {
+        Err(error @ ServeError::NotAFile) => assert_eq!(error.status(), Status::NotFound),
+        other => panic!("expected NotAFile, got {:?}", other),
+    }
+
+    match static_files.respond("../secret.txt") {
+        Err(error @ ServeError::OutOfBounds) => assert_eq!(error.status(), Status::NotFound),
+        other => panic!("expected OutOfBounds, got {:?}", other),
+    }
+
+    assert_eq!(ServeError::Utf8.status(), Status::BadRequest);
+    assert_eq!(ServeError::TooLong.status(), Status::UriTooLong);
+
+    let denied = ServeError::Io {
+        source: io::ErrorKind::PermissionDenied.into(),
+    };
+    assert_eq!(denied.status(), Status::InternalServerError);
+}
+
+#[test]
+fn routes_can_be_mounted_by_hand() {
+    let dir = TempDir::new();
+    dir.write("app.js", "app");
+
+    let unmounted = testing::client(StaticFiles::etag_only(), dir.path(), "auto_mount = false");
+    let response = unmounted.get("/static/app.js").dispatch();
+    assert_eq!(response.status(), Status::NotFound);
+
+    let rocket = testing::rocket(dir.path(), "auto_mount = false")
+        .attach(StaticFiles::etag_only())
+        .mount("/static", StaticFiles::routes());
+    let client = Client::new(rocket).unwrap();
+
+    let mut response = client.get("/static/app.js?v=v1").dispatch();
+    assert_eq!(response.status(), Status::Ok);
+    assert_eq!(response.body_string().as_deref(), Some("app"));
+}
+
+#[test]
+fn redirects_files_requested_with_a_trailing_slash() {
+    static MAP: SortedMap = SortedMap::new(&[(
+        "app.js",
+        Asset {
+            version: "v1",
+            ..ASSET
+        },
+    )]);
+
+    let (_dir, client) = testing::serve(
+        &[("app.js", "app"), ("css/site.css", "body {}")],
+        StaticFiles::fairing(&MAP),
+        "",
+    );
+
+    let response = client.get("/static/app.js/?v=v1").dispatch();
+    assert_eq!(response.status(), Status::MovedPermanently);
+    assert_eq!(
+        response.headers().get_one("Location"),
+        Some("/static/app.js?v=v1")
+    );
+
+    // Following it lands on the file, not another redirect.
+    let response = client.get("/static/app.js?v=v1").dispatch();
+    assert_eq!(response.status(), Status::Ok);
+
+    // Directories keep their slash.
+    let response = client.get("/static/css/").dispatch();
+    assert_eq!(response.status(), Status::NotFound);
+}
+
+#[cfg(all(feature = "gen", feature = "zip"))]
+#[test]
+fn serves_members_of_a_zip_archive() {
+    use crate::gen::{Generator, ZipSource};
+
+    use std::io::Write;
+
+    let dir = TempDir::new();
+    let path = dir.path().join("site.zip");
+    let mut zip = zip::ZipWriter::new(std::fs::File::create(&path).unwrap());
+    let options = zip::write::FileOptions::default();
+    zip.start_file("app.js", options).unwrap();
+    zip.write_all(b"app").unwrap();
+    zip.add_directory("css/", options).unwrap();
+    zip.start_file("css/site.css", options).unwrap();
+    zip.write_all(b"body {}").unwrap();
+    zip.finish().unwrap();
+
+    let (files, _) = Generator::with_source(ZipSource::open(&path).unwrap())
+        .scan()
+        .unwrap();
+    let map = testing::leak_map(
+        files
+            .iter()
+            .map(|(path, entry)| {
+                let asset = Asset {
+                    version: testing::leak(&entry.token),
+                    ..ASSET
+                };
+
+                (testing::leak(path), asset)
+            })
+            .collect(),
+    );
+
+    let client = testing::client(StaticFiles::fairing(map), &path, "");
+    let static_files = client.rocket().state::<StaticFiles>().unwrap();
+
+    let url = static_files.to("css/site.css");
+    assert_eq!(
+        url,
+        format!("/static/css/site.css?v={}", files["css/site.css"].token)
+    );
+
+    let mut response = client.get(url).dispatch();
+    assert_eq!(response.status(), Status::Ok);
+    assert_eq!(response.headers().get_one("Content-Type"), Some("text/css"));
+    assert_eq!(response.body_string().as_deref(), Some("body {}"));
+
+    let response = client.get("/static/app.js").dispatch();
+    assert_eq!(response.status(), Status::SeeOther);
+
+    let response = client.get("/static/missing.js").dispatch();
+    assert_eq!(response.status(), Status::NotFound);
+}
+
+#[test]
+fn spa_exclude_decides_what_falls_back_to_the_index() {
+    let (_dir, client) = testing::serve(
+        &[("index.html", "<!doctype html>")],
+        StaticFiles::etag_only(),
+        "spa_fallback = \"index.html\"\nspa_exclude = [\"*.png\", \"/assets/**\"]",
+    );
+
+    let mut response = client.get("/static/user/john.doe").dispatch();
+    assert_eq!(response.status(), Status::Ok);
+    assert_eq!(response.body_string().as_deref(), Some("<!doctype html>"));
+
+    let response = client.get("/static/missing.png").dispatch();
+    assert_eq!(response.status(), Status::NotFound);
+
+    let response = client.get("/static/assets/app").dispatch();
+    assert_eq!(response.status(), Status::NotFound);
+}
+
+#[test]
+fn version_redirects_are_cached_briefly() {
+    static MAP: SortedMap = SortedMap::new(&[(
+        "app.js",
+        Asset {
+            version: "v2",
+            ..ASSET
+        },
+    )]);
+
+    let dir = TempDir::new();
+    dir.write("app.js", "app");
+
+    let client = testing::client(StaticFiles::fairing(&MAP), dir.path(), "");
+    let response = client.get("/static/app.js?v=v1").dispatch();
+    assert_eq!(response.status(), Status::SeeOther);
+    assert_eq!(
+        response.headers().get_one("Cache-Control"),
+        Some("max-age=300")
+    );
+
+    let client = testing::client(
+        StaticFiles::fairing(&MAP),
+        dir.path(),
+        "redirect_max_age = 30",
+    );
+    let response = client.get("/static/app.js?v=v1").dispatch();
+    assert_eq!(
+        response.headers().get_one("Cache-Control"),
+        Some("max-age=30")
+    );
+}
+
+#[test]
+fn serves_the_root_index_at_the_bare_prefix() {
+    let (dir, client) = testing::serve(
+        &[("index.html", "root"), ("docs/index.html", "docs")],
+        StaticFiles::etag_only(),
+        "index_file = \"index.html\"",
+    );
+
+    for url in &["/static", "/static/"] {
+        let mut response = client.get(*url).dispatch();
+        assert_eq!(response.status(), Status::Ok, "{}", url);
+        assert_eq!(response.body_string().as_deref(), Some("root"));
+    }
+
+    let mut response = client.get("/static/docs/").dispatch();
+    assert_eq!(response.status(), Status::Ok);
+    assert_eq!(response.body_string().as_deref(), Some("docs"));
+
+    // Without an index, there's nothing to serve there.
+    let client = testing::client(StaticFiles::etag_only(), dir.path(), "");
+    let response = client.get("/static").dispatch();
+    assert_eq!(response.status(), Status::NotFound);
+}
+
+#[test]
+fn stat_tokens_version_untracked_files() {
+    let dir = TempDir::new();
+    let path = dir.write("uploads/new.js", "new");
+    let metadata = std::fs::metadata(&path).unwrap();
+    let token = response::stat_token(&metadata).unwrap();
+    assert!(token.starts_with("3-"), "{}", token);
+
+    let client = testing::client(StaticFiles::etag_only(), dir.path(), "stat_tokens = true");
+    let static_files = client.rocket().state::<StaticFiles>().unwrap();
+    assert_eq!(
+        static_files.to("uploads/new.js"),
+        format!("/static/uploads/new.js?v={}", token)
+    );
+    assert_eq!(static_files.to("missing.js"), "/static/missing.js");
+
+    let client = testing::client(StaticFiles::etag_only(), dir.path(), "");
+    let static_files = client.rocket().state::<StaticFiles>().unwrap();
+    assert_eq!(static_files.to("uploads/new.js"), "/static/uploads/new.js");
+}
+
+#[test]
+fn exposes_the_effective_config() {
+    let dir = TempDir::new();
+    dir.write("app.js", "app");
+
+    // Canonicalized on attach.
+    std::fs::create_dir(dir.path().join("css")).unwrap();
+    let client = testing::client(
+        StaticFiles::etag_only(),
+        &dir.path().join("css/.."),
+        "base_href = \"/app\"\nversion_param = \"rev\"",
+    );
+    let static_files = client.rocket().state::<StaticFiles>().unwrap();
+
+    let view = static_files.config();
+    assert_eq!(view.serve_from(), dir.path());
+    assert_eq!(view.path_prefix(), "/static");
+    assert_eq!(view.url_prefix(), "/app/static");
+    assert_eq!(view.version_param(), "rev");
+}
+
+#[test]
+fn other_methods_get_405_with_allow() {
+    let dir = TempDir::new();
+    dir.write("app.js", "app");
+
+    let client = testing::client(
+        StaticFiles::etag_only(),
+        dir.path(),
+        "method_not_allowed = true",
+    );
+    let response = client.post("/static/app.js").dispatch();
+    assert_eq!(response.status(), Status::MethodNotAllowed);
+    assert_eq!(response.headers().get_one("Allow"), Some("GET, HEAD"));
+
+    let response = client.delete("/static/missing.js").dispatch();
+    assert_eq!(response.status(), Status::MethodNotAllowed);
+
+    let client = testing::client(StaticFiles::etag_only(), dir.path(), "");
+    let response = client.post("/static/app.js").dispatch();
+    assert_eq!(response.status(), Status::NotFound);
+}
+
+#[test]
+fn rejects_absurd_paths_before_looking_them_up() {
+    let (_dir, client) = testing::serve(
+        &[("app.js", "app")],
+        StaticFiles::etag_only(),
+        "max_path_segments = 4\nmax_path_length = 64",
+    );
+
+    let deep = format!("/static/{}app.js", "a/".repeat(5000));
+    let response = client.get(deep).dispatch();
+    assert_eq!(response.status(), Status::UriTooLong);
+
+    let long = format!("/static/{}.js", "a".repeat(65));
+    let response = client.get(long).dispatch();
+    assert_eq!(response.status(), Status::UriTooLong);
+
+    let response = client.get("/static/app.js").dispatch();
+    assert_eq!(response.status(), Status::Ok);
+}
+
+#[test]
+fn serves_the_manifest_as_json() {
+    static MAP: SortedMap = SortedMap::new(&[
+        (
+            "app.js",
+            Asset {
+                version: "v1",
+                integrity: Some("sha256-abc"),
+                ..ASSET
+            },
+        ),
+        (
+            "favicon.ico",
+            Asset {
+                version: "v2",
+                ..ASSET
+            },
+        ),
+    ]);
+
+    let (_dir, client) = testing::serve(
+        &[],
+        StaticFiles::fairing(&MAP),
+        "manifest_path = \"/assets.json\"\npin = [\"favicon.ico\"]",
+    );
+
+    let mut response = client.get("/static/assets.json").dispatch();
+    assert_eq!(response.status(), Status::Ok);
+    assert_eq!(
+        response.headers().get_one("Content-Type"),
+        Some("application/json")
+    );
+    assert_eq!(
+        response.headers().get_one("Cache-Control"),
+        Some("max-age=60")
+    );
+    assert_eq!(
+        response.body_string().as_deref(),
+        Some(
+            "{\"app.js\":{\"version\":\"v1\",\"url\":\"/static/app.js?v=v1\",\
+             \"integrity\":\"sha256-abc\"},\
+             \"favicon.ico\":{\"version\":\"v2\",\"url\":\"/static/favicon.ico\",\
+             \"integrity\":null}}"
+        )
+    );
+}
+
+#[test]
+fn deferred_headers_are_left_to_other_fairings() {
+    use rocket::fairing::AdHoc;
+
+    let dir = TempDir::new();
+    dir.write("_headers", "/*\n  X-Frame-Options: SAMEORIGIN\n");
+    dir.write("app.js", "app");
+
+    let frame_options = |extra: &str| {
+        // Like `SpaceHelmet`, only fills in what's missing.
+        let helmet = AdHoc::on_response("Helmet", |_, response| {
+            if !response.headers().contains("X-Frame-Options") {
+                response.set_raw_header("X-Frame-Options", "DENY");
+            }
+        });
+
+        let toml = format!("sidecar_headers = true\n{}", extra);
+        let rocket = testing::rocket(dir.path(), &toml)
+            .attach(StaticFiles::etag_only())
+            .attach(helmet);
+        let client = Client::new(rocket).unwrap();
+
+        let response = client.get("/static/app.js").dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        let values: Vec<String> = response
+            .headers()
+            .get("X-Frame-Options")
+            .map(String::from)
+            .collect();
+        values
+    };
+
+    assert_eq!(frame_options(""), ["SAMEORIGIN"]);
+    assert_eq!(
+        frame_options("defer_headers = [\"x-frame-options\"]"),
+        ["DENY"]
+    );
+}
+
+/// Resident set size of this process, in bytes.
+#[cfg(target_os = "linux")]
+fn rss() -> u64 {
+    let status = std::fs::read_to_string("/proc/self/status").unwrap();
+    let line = status.lines().find(|l| l.starts_with("VmRSS:")).unwrap();
+    let kib: u64 = line.split_whitespace().nth(1).unwrap().parse().unwrap();
+    kib * 1024
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn streams_large_files_without_reading_them_in() {
+    use std::io::Read;
+
+    const SIZE: u64 = 128 * 1024 * 1024;
+
+    // Sparse, so writing it out doesn't take long either.
+    let dir = TempDir::new();
+    let file = std::fs::File::create(dir.path().join("large.bin")).unwrap();
+    file.set_len(SIZE).unwrap();
+
+    let client = testing::client(StaticFiles::etag_only(), dir.path(), "");
+    let before = rss();
+
+    let mut response = client.get("/static/large.bin").dispatch();
+    assert_eq!(response.status(), Status::Ok);
+
+    // Read like a slow client would: a chunk at a time, checking in on the
+    // way.
+    let body = response.body().unwrap().into_inner();
+    let mut chunk = vec![0; 64 * 1024];
+    let (mut total, mut peak) = (0, before);
+    for reads in 0.. {
+        let read = body.read(&mut chunk).unwrap();
+        if read == 0 {
+            break;
+        }
+
+        total += read as u64;
+        if reads % 256 == 0 {
+            peak = peak.max(rss());
+        }
+    }
+
+    assert_eq!(total, SIZE);
+    let grown = peak.saturating_sub(before);
+    assert!(grown < SIZE / 4, "grew by {} bytes", grown);
+}
+
+#[test]
+fn head_requests_for_stale_versions() {
+    static MAP: SortedMap = SortedMap::new(&[(
+        "app.js",
+        Asset {
+            version: "v2",
+            ..ASSET
+        },
+    )]);
+
+    let dir = TempDir::new();
+    dir.write("app.js", "app");
+
+    let client = testing::client(StaticFiles::fairing(&MAP), dir.path(), "");
+    let response = client.head("/static/app.js?v=v1").dispatch();
+    assert_eq!(response.status(), Status::SeeOther);
+    assert_eq!(
+        response.headers().get_one("Location"),
+        Some("/static/app.js?v=v2")
+    );
+
+    let client = testing::client(
+        StaticFiles::fairing(&MAP),
+        dir.path(),
+        "redirect_head = false",
+    );
+    let mut response = client.head("/static/app.js?v=v1").dispatch();
+    assert_eq!(response.status(), Status::Ok);
+    assert_eq!(
+        response.headers().get_one("Cache-Control"),
+        Some("no-cache")
+    );
+    assert_eq!(response.body_string().unwrap_or_default(), "");
+
+    let response = client.head("/static/app.js?v=v2").dispatch();
+    assert_eq!(response.status(), Status::Ok);
+    let cache_control = response.headers().get_one("Cache-Control").unwrap();
+    assert!(cache_control.starts_with("max-age=31536000"));
+
+    // `GET`s are still redirected.
+    let response = client.get("/static/app.js?v=v1").dispatch();
+    assert_eq!(response.status(), Status::SeeOther);
+}
+
+#[test]
+fn renders_preload_links_for_other_responses() {
+    static MAP: SortedMap = SortedMap::new(&[
+        (
+            "app.css",
+            Asset {
+                version: "v1",
+                ..ASSET
+            },
+        ),
+        (
+            "fonts/Open Sans.woff2",
+            Asset {
+                version: "v2",
+                ..ASSET
+            },
+        ),
+    ]);
+
+    let (_dir, client) = testing::serve(&[], StaticFiles::fairing(&MAP), "");
+    let static_files = client.rocket().state::<StaticFiles>().unwrap();
+
+    assert_eq!(
+        static_files.preload_links(&[("/app.css", As::Style), ("fonts/Open Sans.woff2", As::Font)]),
+        "</static/app.css?v=v1>; rel=preload; as=style, \
+         </static/fonts/Open%20Sans.woff2?v=v2>; rel=preload; as=font"
+    );
+
+    let none: &[(&str, As)] = &[];
+    assert_eq!(static_files.preload_links(none), "");
+}
+
+#[test]
+fn serve_unversioned_answers_bare_links_directly() {
+    static MAP: SortedMap = SortedMap::new(&[(
+        "report.pdf",
+        Asset {
+            version: "v2",
+            ..ASSET
+        },
+    )]);
+
+    let (_dir, client) = testing::serve(
+        &[("report.pdf", "pdf")],
+        StaticFiles::fairing(&MAP),
+        "serve_unversioned = true",
+    );
+
+    let mut response = client.get("/static/report.pdf").dispatch();
+    assert_eq!(response.status(), Status::Ok);
+    assert_eq!(
+        response.headers().get_one("Cache-Control"),
+        Some("no-cache")
+    );
+    assert_eq!(response.headers().get_one("ETag"), Some("\"v2\""));
+    assert_eq!(response.body_string().as_deref(), Some("pdf"));
+
+    // A stale token is still corrected.
+    let response = client.get("/static/report.pdf?v=v1").dispatch();
+    assert_eq!(response.status(), Status::SeeOther);
+}
+
+#[test]
+fn custom_cache_directives_reach_the_response() {
+    static MAP: SortedMap = SortedMap::new(&[(
+        "app.js",
+        Asset {
+            version: "v1",
+            ..ASSET
+        },
+    )]);
+
+    let (dir, client) = testing::serve(
+        &[("app.js", "app")],
+        StaticFiles::fairing(&MAP),
+        "[cache_directives]\n\
+         versioned = [\"public\", \"max-age=3600\", \"immutable\"]\n\
+         unversioned = [\"private\", \"no-cache\", \"x-custom=1\"]\n",
+    );
+
+    let response = client.get("/static/app.js?v=v1").dispatch();
+    assert_eq!(
+        response.headers().get_one("Cache-Control"),
+        Some("public, max-age=3600, immutable")
+    );
+
+    dir.write("untracked.js", "untracked");
+    let response = client.get("/static/untracked.js").dispatch();
+    assert_eq!(
+        response.headers().get_one("Cache-Control"),
+        Some("private, no-cache, x-custom=1")
+    );
+
+    // Lists that don't parse are refused at launch.
+    let rocket = testing::rocket(dir.path(), "[cache_directives]\nversioned = []\n")
+        .attach(StaticFiles::fairing(&MAP));
+    assert!(testing::fails_to_launch(rocket));
+}
+
+#[test]
+fn serves_allowlisted_dotfiles_only() {
+    static MAP: SortedMap = SortedMap::new(&[
+        (
+            ".env",
+            Asset {
+                version: "v0",
+                ..ASSET
+            },
+        ),
+        (
+            ".well-known/security.txt",
+            Asset {
+                version: "v1",
+                ..ASSET
+            },
+        ),
+    ]);
+
+    let (_dir, client) = testing::serve(
+        &[
+            (".env", "SECRET=1"),
+            (".well-known/security.txt", "Contact: me"),
+        ],
+        StaticFiles::fairing(&MAP),
+        "serve_dotfiles_allowlist = [\".well-known/**\"]\nmanifest_path = \"assets.json\"",
+    );
+
+    let mut response = client
+        .get("/static/.well-known/security.txt?v=v1")
+        .dispatch();
+    assert_eq!(response.status(), Status::Ok);
+    assert_eq!(response.body_string().as_deref(), Some("Contact: me"));
+
+    let response = client.get("/static/.env?v=v0").dispatch();
+    assert_eq!(response.status(), Status::NotFound);
+
+    // Even a map that lists it doesn't give it away.
+    let static_files = client.rocket().state::<StaticFiles>().unwrap();
+    assert_eq!(
+        static_files.glob("**"),
+        [(".well-known/security.txt".to_owned(), "v1")]
+    );
+
+    let mut response = client.get("/static/assets.json").dispatch();
+    let manifest = response.body_string().unwrap();
+    assert!(!manifest.contains(".env"), "{}", manifest);
+    assert!(manifest.contains("security.txt"), "{}", manifest);
+}
+
+#[test]
+fn content_type_paths_override_guessing() {
+    let (_dir, client) = testing::serve(
+        &[("healthz", "{\"ok\":true}"), ("status/healthz", "ok")],
+        StaticFiles::etag_only(),
+        "content_type_paths = { \"healthz\" = \"application/json\" }",
+    );
+
+    let response = client.get("/static/healthz").dispatch();
+    assert_eq!(
+        response.headers().get_one("Content-Type"),
+        Some("application/json")
+    );
+
+    // Only that exact path.
+    let response = client.get("/static/status/healthz").dispatch();
+    assert_eq!(
+        response.headers().get_one("Content-Type"),
+        Some("application/octet-stream")
+    );
+}
+
+#[test]
+fn serves_embedded_files_from_memory() {
+    static MAP: SortedMap = SortedMap::new(&[
+        (
+            "app.js",
+            Asset {
+                version: "v1",
+                embedded: Some(b"embedded"),
+                ..ASSET
+            },
+        ),
+        (
+            "big.js",
+            Asset {
+                version: "v1",
+                ..ASSET
+            },
+        ),
+    ]);
+
+    // Neither file is on disk.
+    let (_dir, client) = testing::serve(&[], StaticFiles::fairing(&MAP), "");
+
+    let mut response = client.get("/static/app.js?v=v1").dispatch();
+    assert_eq!(response.status(), Status::Ok);
+    assert_eq!(
+        response.headers().get_one("Cache-Control"),
+        Some("max-age=31536000")
+    );
+    assert_eq!(response.body_string().as_deref(), Some("embedded"));
+
+    let response = client.get("/static/big.js?v=v1").dispatch();
+    assert_eq!(response.status(), Status::NotFound);
+}
+
+#[test]
+fn gzipped_embeds_are_decompressed_for_clients_without_gzip() {
+    use std::io::Write;
+
+    let original = "body { color: red; }\n".repeat(100);
+    let mut encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::best());
+    encoder.write_all(original.as_bytes()).unwrap();
+    let compressed: &'static [u8] = Box::leak(encoder.finish().unwrap().into_boxed_slice());
+
+    let map = testing::leak_map(vec![(
+        "site.css",
+        Asset {
+            version: "v1",
+            gzip: Some(compressed.len() as u64),
+            embedded: Some(compressed),
+            embedded_size: Some(original.len() as u64),
+            ..ASSET
+        },
+    )]);
+
+    let (_dir, client) = testing::serve(&[], StaticFiles::fairing(map), "");
+
+    let mut response = client
+        .get("/static/site.css?v=v1")
+        .header(Header::new("Accept-Encoding", "gzip"))
+        .dispatch();
+    assert_eq!(response.status(), Status::Ok);
+    assert_eq!(response.headers().get_one("Content-Encoding"), Some("gzip"));
+    assert_eq!(response.body_bytes().as_deref(), Some(compressed));
+
+    let mut response = client.get("/static/site.css?v=v1").dispatch();
+    assert_eq!(response.status(), Status::Ok);
+    assert_eq!(response.headers().get_one("Content-Encoding"), None);
+    assert_eq!(response.body_string(), Some(original));
+}
+
+#[test]
+fn on_access_hears_of_every_file_served() {
+    use std::sync::{Arc, Mutex};
+
+    static MAP: SortedMap = SortedMap::new(&[(
+        "app.js",
+        Asset {
+            version: "v1",
+            ..ASSET
+        },
+    )]);
+
+    let dir = TempDir::new();
+    dir.write("app.js", "app");
+
+    let seen = Arc::new(Mutex::new(Vec::new()));
+    let hook = {
+        let seen = seen.clone();
+        move |path: &str| seen.lock().unwrap().push(path.to_owned())
+    };
+    let fairing = StaticFiles::fairing(&MAP).on_access(hook);
+    let client = testing::client(fairing, dir.path(), "");
+
+    let response = client.get("/static/app.js?v=v1").dispatch();
+    assert_eq!(response.status(), Status::Ok);
+
+    let response = client
+        .get("/static/app.js?v=v1")
+        .header(Header::new("If-None-Match", "\"v1\""))
+        .dispatch();
+    assert_eq!(response.status(), Status::NotModified);
+
+    // Neither redirects nor misses count.
+    let response = client.get("/static/app.js?v=v0").dispatch();
+    assert_eq!(response.status(), Status::SeeOther);
+    let response = client.get("/static/missing.js").dispatch();
+    assert_eq!(response.status(), Status::NotFound);
+
+    assert_eq!(*seen.lock().unwrap(), ["app.js", "app.js"]);
+}
+
+#[test]
+fn redirect_base_makes_version_redirects_absolute() {
+    static MAP: SortedMap = SortedMap::new(&[(
+        "app.js",
+        Asset {
+            version: "v1",
+            ..ASSET
+        },
+    )]);
+
+    let dir = TempDir::new();
+    dir.write("app.js", "app");
+
+    let client = testing::client(StaticFiles::fairing(&MAP), dir.path(), "");
+    let response = client.get("/static/app.js?v=v0").dispatch();
+    assert_eq!(response.status(), Status::SeeOther);
+    assert_eq!(
+        response.headers().get_one("Location"),
+        Some("/static/app.js?v=v1")
+    );
+
+    let client = testing::client(
+        StaticFiles::fairing(&MAP),
+        dir.path(),
+        "redirect_base = \"https://example.com/\"",
+    );
+    let response = client.get("/static/app.js?v=v0").dispatch();
+    assert_eq!(response.status(), Status::SeeOther);
+    assert_eq!(
+        response.headers().get_one("Location"),
+        Some("https://example.com/static/app.js?v=v1")
+    );
+
+    let rocket = testing::rocket(dir.path(), "redirect_base = \"not a url\"")
+        .attach(StaticFiles::fairing(&MAP));
+    assert!(testing::fails_to_launch(rocket));
+}
+
+#[test]
+fn static_files_dir_overrides_serve_from() {
+    // Every launch in the process reads the variable, so it's only set for a
+    // child running the test below on its own.
+    let dir = TempDir::new();
+    dir.write("app.js", "from the environment");
+
+    let status = std::process::Command::new(std::env::current_exe().unwrap())
+        .args(&["--exact", "fairing::tests::serves_from_static_files_dir"])
+        .args(&["--ignored", "--quiet", "--test-threads=1"])
+        .env(SERVE_FROM_VAR, dir.path())
+        .status()
+        .unwrap();
+    assert!(status.success());
+}
+
+/// Run by `static_files_dir_overrides_serve_from`.
+#[test]
+#[ignore]
+fn serves_from_static_files_dir() {
+    if std::env::var_os(SERVE_FROM_VAR).is_none() {
+        return;
+    }
+
+    // Configured with somewhere empty.
+    let (_dir, client) = testing::serve(&[], StaticFiles::etag_only(), "");
+
+    let mut response = client.get("/static/app.js").dispatch();
+    assert_eq!(response.status(), Status::Ok);
+    assert_eq!(
+        response.body_string().as_deref(),
+        Some("from the environment")
+    );
+}
+
+#[test]
+fn strict_mode_refuses_to_launch_with_files_missing() {
+    static MAP: SortedMap = SortedMap::new(&[
+        (
+            "app.js",
+            Asset {
+                version: "v1",
+                ..ASSET
+            },
+        ),
+        (
+            "gone.css",
+            Asset {
+                version: "v1",
+                ..ASSET
+            },
+        ),
+    ]);
+
+    let dir = TempDir::new();
+    dir.write("app.js", "app");
+
+    #[cfg(feature = "log")]
+    testing::take_logs();
+
+    let rocket = testing::rocket(dir.path(), "strict = true").attach(StaticFiles::fairing(&MAP));
+    assert!(testing::fails_to_launch(rocket));
+
+    #[cfg(feature = "log")]
+    assert_eq!(
+        testing::take_logs(),
+        ["static files missing from serve_from: gone.css"]
+    );
+
+    dir.write("gone.css", "back");
+    let rocket = testing::rocket(dir.path(), "strict = true").attach(StaticFiles::fairing(&MAP));
+    assert!(!testing::fails_to_launch(rocket));
+}
+
+#[test]
+fn answers_options_with_allow_and_cors_headers() {
+    let dir = TempDir::new();
+    dir.write(
+        "_headers",
+        "/fonts/*\n  Access-Control-Allow-Origin: *\n  X-Other: 1\n",
+    );
+    dir.write("fonts/icons.woff2", "font");
+
+    let client = testing::client(
+        StaticFiles::etag_only(),
+        dir.path(),
+        "answer_options = true\nsidecar_headers = true",
+    );
+
+    let mut response = client.options("/static/fonts/icons.woff2").dispatch();
+    assert_eq!(response.status(), Status::NoContent);
+    assert_eq!(
+        response.headers().get_one("Allow"),
+        Some("GET, HEAD, OPTIONS")
+    );
+    assert_eq!(
+        response.headers().get_one("Access-Control-Allow-Origin"),
+        Some("*")
+    );
+    assert_eq!(response.headers().get_one("X-Other"), None);
+    assert_eq!(response.body_string().unwrap_or_default(), "");
+
+    let response = client.options("/static/app.js").dispatch();
+    assert_eq!(response.status(), Status::NoContent);
+    assert_eq!(
+        response.headers().get_one("Access-Control-Allow-Origin"),
+        None
+    );
+
+    let client = testing::client(StaticFiles::etag_only(), dir.path(), "");
+    let response = client.options("/static/fonts/icons.woff2").dispatch();
+    assert_eq!(response.status(), Status::NotFound);
+}
+
+#[test]
+fn not_modified_carries_only_the_rfc_7232_headers() {
+    static MAP: SortedMap = SortedMap::new(&[
+        (
+            "_headers",
+            Asset {
+                version: "v0",
+                ..ASSET
+            },
+        ),
+        (
+            "site.css",
+            Asset {
+                version: "v1",
+                gzip: Some(3),
+                ..ASSET
+            },
+        ),
+    ]);
+
+    let dir = TempDir::new();
+    dir.write(
+        "_headers",
+        "/site.css\n  \
+         Expires: Thu, 01 Jan 2037 00:00:00 GMT\n  \
+         Content-Location: /static/site.css\n  \
+         Content-Language: en\n  \
+         X-Frame-Options: DENY\n",
+    );
+    dir.write("site.css", "body {}");
+    dir.write("site.css.gz", "gz");
+    let client = testing::client(
+        StaticFiles::fairing(&MAP),
+        dir.path(),
+        "sidecar_headers = true",
+    );
+
+    let response = client
+        .get("/static/site.css?v=v1")
+        .header(Header::new("If-None-Match", "\"v1\""))
+        .dispatch();
+    assert_eq!(response.status(), Status::NotModified);
+
+    let headers = response.headers();
+    assert_eq!(headers.get_one("ETag"), Some("\"v1\""));
+    assert!(headers.get_one("Cache-Control").is_some());
+    assert_eq!(headers.get_one("Vary"), Some("Accept-Encoding"));
+    assert_eq!(
+        headers.get_one("Expires"),
+        Some("Thu, 01 Jan 2037 00:00:00 GMT")
+    );
+    assert_eq!(
+        headers.get_one("Content-Location"),
+        Some("/static/site.css")
+    );
+
+    for name in &["Content-Type", "Content-Language", "X-Frame-Options"] {
+        assert_eq!(headers.get_one(name), None, "{}", name);
+    }
+}
+
+#[test]
+fn rewrites_links_in_html_responses() {
+    static MAP: SortedMap = SortedMap::new(&[(
+        "app.js",
+        Asset {
+            version: "v1",
+            ..ASSET
+        },
+    )]);
+
+    let dir = TempDir::new();
+    let client = |extra: &str| {
+        let rocket = testing::rocket(dir.path(), extra)
+            .attach(StaticFiles::fairing(&MAP))
+            .mount("/", routes![page, text]);
+        Client::new(rocket).unwrap()
+    };
+
+    let client_rewriting = client("rewrite_html = true");
+    let mut response = client_rewriting.get("/page").dispatch();
+    assert_eq!(
+        response.body_string().as_deref(),
+        Some(
+            "<script src=\"/static/app.js?v=v1\"></script>\n\
+             <link href='/static/other.css'>\n\
+             <a href=\"/static/app.js#top\">/static/app.js</a>"
+        )
+    );
+
+    // Only HTML is touched.
+    let mut response = client_rewriting.get("/text").dispatch();
+    assert_eq!(response.body_string().as_deref(), Some(PAGE));
+
+    let mut response = client("").get("/page").dispatch();
+    assert_eq!(response.body_string().as_deref(), Some(PAGE));
+
+    let mut response = client("rewrite_html = true\nrewrite_html_max_bytes = 16")
+        .get("/page")
+        .dispatch();
+    assert_eq!(response.body_string().as_deref(), Some(PAGE));
+}
+
+#[test]
+fn sends_content_language_by_directory() {
+    let (_dir, client) = testing::serve(
+        &[("fr/index.html", "bonjour"), ("index.html", "hello")],
+        StaticFiles::etag_only(),
+        "content_language_map = { fr = \"fr\" }",
+    );
+
+    let response = client.get("/static/fr/index.html").dispatch();
+    assert_eq!(response.status(), Status::Ok);
+    assert_eq!(response.headers().get_one("Content-Language"), Some("fr"));
+
+    let response = client.get("/static/index.html").dispatch();
+    assert_eq!(response.headers().get_one("Content-Language"), None);
+}
+
+#[test]
+fn no_token_dirs_are_linked_bare_and_cached_immutably() {
+    static MAP: SortedMap = SortedMap::new(&[
+        (
+            "app.js",
+            Asset {
+                version: "v1",
+                ..ASSET
+            },
+        ),
+        (
+            "fonts/icons.woff2",
+            Asset {
+                version: "v1",
+                ..ASSET
+            },
+        ),
+    ]);
+
+    let (_dir, client) = testing::serve(
+        &[("app.js", "app"), ("fonts/icons.woff2", "font")],
+        StaticFiles::fairing(&MAP),
+        "no_token_dirs = [\"/fonts/\"]",
+    );
+
+    let static_files = client.rocket().state::<StaticFiles>().unwrap();
+    assert_eq!(
+        static_files.to("fonts/icons.woff2"),
+        "/static/fonts/icons.woff2"
+    );
+    assert_eq!(static_files.to("app.js"), "/static/app.js?v=v1");
+
+    for url in &[
+        "/static/fonts/icons.woff2",
+        "/static/fonts/icons.woff2?v=v0",
+    ] {
+        let response = client.get(*url).dispatch();
+        assert_eq!(response.status(), Status::Ok, "{}", url);
+        assert_eq!(
+            response.headers().get_one("Cache-Control"),
+            Some("max-age=31536000, immutable"),
+            "{}",
+            url
+        );
+    }
+
+    // Versioned files keep the plain policy.
+    let response = client.get("/static/app.js?v=v1").dispatch();
+    assert_eq!(
+        response.headers().get_one("Cache-Control"),
+        Some("max-age=31536000")
+    );
+}
+
+#[test]
+fn respond_serves_a_file_from_any_handler() {
+    static MAP: SortedMap = SortedMap::new(&[(
+        "releases/app-1.2.zip",
+        Asset {
+            version: "v1",
+            ..ASSET
+        },
+    )]);
+
+    let dir = TempDir::new();
+    dir.write("releases/app-1.2.zip", "zip");
+    let rocket = testing::rocket(dir.path(), "")
+        .attach(StaticFiles::fairing(&MAP))
+        .mount("/", routes![latest]);
+    let client = Client::new(rocket).unwrap();
+
+    let mut response = client.get("/download/latest").dispatch();
+    assert_eq!(response.status(), Status::Ok);
+    assert_eq!(
+        response.headers().get_one("Content-Type"),
+        Some("application/zip")
+    );
+    assert_eq!(
+        response.headers().get_one("Cache-Control"),
+        Some("no-cache")
+    );
+    assert_eq!(response.headers().get_one("ETag"), Some("\"v1\""));
+    assert_eq!(response.body_string().as_deref(), Some("zip"));
+
+    let response = client
+        .get("/download/latest")
+        .header(Header::new("If-None-Match", "\"v1\""))
+        .dispatch();
+    assert_eq!(response.status(), Status::NotModified);
+
+    let static_files = client.rocket().state::<StaticFiles>().unwrap();
+    assert!(static_files.respond("releases/app-1.3.zip").is_err());
+    assert!(static_files.respond("releases").is_err());
+}
+
+#[test]
+fn version_redirect_off_never_redirects_to_the_token() {
+    static MAP: SortedMap = SortedMap::new(&[(
+        "app.js",
+        Asset {
+            version: "v2",
+            ..ASSET
+        },
+    )]);
+
+    let (_dir, client) = testing::serve(
+        &[("app.js", "app")],
+        StaticFiles::fairing(&MAP),
+        "version_redirect = false",
+    );
+
+    for url in &["/static/app.js", "/static/app.js?v=v1", "/static/app.js?v="] {
+        let mut response = client.get(*url).dispatch();
+        assert_eq!(response.status(), Status::Ok, "{}", url);
+        assert_eq!(response.headers().get_one("Location"), None, "{}", url);
+        assert_eq!(
+            response.headers().get_one("Cache-Control"),
+            Some("no-cache"),
+            "{}",
+            url
+        );
+        assert_eq!(response.body_string().as_deref(), Some("app"), "{}", url);
+
+        let response = client.head(*url).dispatch();
+        assert_eq!(response.status(), Status::Ok, "HEAD {}", url);
+    }
+
+    let response = client.get("/static/app.js?v=v2").dispatch();
+    assert_eq!(
+        response.headers().get_one("Cache-Control"),
+        Some("max-age=31536000")
+    );
+
+    let response = client.get("/static/missing.js?v=v2").dispatch();
+    assert_eq!(response.status(), Status::NotFound);
+}
+
+#[test]
+fn source_maps_are_served_as_json() {
+    let dir = TempDir::new();
+    dir.write("app.js.map", "{}");
+    dir.write("site.css.map", "{}");
+    dir.write("bundle.map", "{}");
+
+    let content_type = |extra: &str, url: &str| {
+        let client = testing::client(StaticFiles::etag_only(), dir.path(), extra);
+        let response = client.get(url).dispatch();
+        assert_eq!(response.status(), Status::Ok, "{}", url);
+        response
+            .headers()
+            .get_one("Content-Type")
+            .unwrap()
+            .to_owned()
+    };
+
+    for url in &[
+        "/static/app.js.map",
+        "/static/site.css.map",
+        "/static/bundle.map",
+    ] {
+        assert!(
+            content_type("", url).starts_with(&"application/json"[..]),
+            "{}",
+            url
+        );
+    }
+
+    let overridden = content_type(
+        "source_map_content_type = \"application/octet-stream\"",
+        "/static/app.js.map",
+    );
+    assert_eq!(overridden, "application/octet-stream");
+}
+
+#[test]
+fn bundle_paths_round_trip() {
+    static MAP: SortedMap = SortedMap::new(&[
+        (
+            "css/a b#1.css",
+            Asset {
+                version: "v0",
+                ..ASSET
+            },
+        ),
+        (
+            "css/site.css",
+            Asset {
+                version: "v1",
+                ..ASSET
+            },
+        ),
+        (
+            "js/app.js",
+            Asset {
+                version: "v2",
+                ..ASSET
+            },
+        ),
+    ]);
+
+    let (dir, client) = testing::serve(
+        &[("css/site.css", "body {}"), ("js/app.js", "app")],
+        StaticFiles::fairing(&MAP),
+        "bundle_path = true",
+    );
+
+    let static_files = client.rocket().state::<StaticFiles>().unwrap();
+    let bundle = static_files.bundle_hash();
+    let url = static_files.to("js/app.js");
+    assert_eq!(url, format!("/static/{}/js/app.js", bundle));
+
+    let mut response = client.get(url.clone()).dispatch();
+    assert_eq!(response.status(), Status::Ok);
+    assert_eq!(
+        response.headers().get_one("Cache-Control"),
+        Some("max-age=31536000")
+    );
+    assert_eq!(response.body_string().as_deref(), Some("app"));
+
+    // An old bundle, or none at all, is sent to the current one.
+    for stale in &["/static/0123456789abcdef/js/app.js", "/static/js/app.js"] {
+        let response = client.get(*stale).dispatch();
+        assert_eq!(response.status(), Status::SeeOther, "{}", stale);
+        assert_eq!(
+            response.headers().get_one("Location"),
+            Some(&url[..]),
+            "{}",
+            stale
+        );
+    }
+
+    dir.write("css/a b#1.css", "a {}");
+    let url = static_files.to("css/a b#1.css");
+    assert_eq!(url, format!("/static/{}/css/a%20b%231.css", bundle));
+    let mut response = client.get(url).dispatch();
+    assert_eq!(response.status(), Status::Ok);
+    assert_eq!(response.body_string().as_deref(), Some("a {}"));
+}
+
+#[test]
+fn expires_is_counted_from_the_clock() {
+    use std::time::{Duration, UNIX_EPOCH};
+
+    static MAP: SortedMap = SortedMap::new(&[(
+        "app.js",
+        Asset {
+            version: "v1",
+            ..ASSET
+        },
+    )]);
+
+    let dir = TempDir::new();
+    dir.write("app.js", "app");
+    dir.write("untracked.js", "untracked");
+
+    // Sun, 09 Sep 2001 01:46:40 GMT.
+    let now = UNIX_EPOCH + Duration::from_secs(1_000_000_000);
+    let fairing = StaticFiles::fairing(&MAP).clock(move || now);
+    let client = testing::client(fairing, dir.path(), "expires = true");
+
+    let response = client.get("/static/app.js?v=v1").dispatch();
+    assert_eq!(
+        response.headers().get_one("Expires"),
+        Some("Mon, 09 Sep 2002 01:46:40 GMT")
+    );
+
+    let response = client
+        .get("/static/app.js?v=v1")
+        .header(Header::new("If-None-Match", "\"v1\""))
+        .dispatch();
+    assert_eq!(response.status(), Status::NotModified);
+    assert_eq!(
+        response.headers().get_one("Expires"),
+        Some("Mon, 09 Sep 2002 01:46:40 GMT")
+    );
+
+    // Nothing to count for files that revalidate.
+    let response = client.get("/static/untracked.js").dispatch();
+    assert_eq!(response.headers().get_one("Expires"), None);
+
+    let client = testing::client(StaticFiles::fairing(&MAP), dir.path(), "");
+    let response = client.get("/static/app.js?v=v1").dispatch();
+    assert_eq!(response.headers().get_one("Expires"), None);
+}
+
+#[test]
+fn wasm_is_served_for_streaming_compilation() {
+    static MAP: SortedMap = SortedMap::new(&[
+        (
+            "app.js",
+            Asset {
+                version: "v1",
+                brotli: Some(2),
+                ..ASSET
+            },
+        ),
+        (
+            "app.wasm",
+            Asset {
+                version: "v1",
+                brotli: Some(2),
+                ..ASSET
+            },
+        ),
+    ]);
+
+    let dir = TempDir::new();
+    for name in &["app.js", "app.wasm"] {
+        dir.write(name, "\0asm");
+        dir.write(format!("{}.br", name), "br");
+    }
+    let client = testing::client(StaticFiles::fairing(&MAP), dir.path(), "");
+
+    let get = |url: &str, accept: &str| {
+        client
+            .get(url.to_owned())
+            .header(Header::new("Accept-Encoding", accept.to_owned()))
+            .dispatch()
+    };
+
+    let response = get("/static/app.wasm?v=v1", "gzip, br");
+    assert_eq!(
+        response.headers().get_one("Content-Type"),
+        Some("application/wasm")
+    );
+    assert_eq!(response.headers().get_one("Content-Encoding"), Some("br"));
+
+    // `*` is enough for anything else, but not for streaming compilation.
+    let mut response = get("/static/app.wasm?v=v1", "*");
+    assert_eq!(response.headers().get_one("Content-Encoding"), None);
+    assert_eq!(response.body_string().as_deref(), Some("\0asm"));
+
+    let response = get("/static/app.js?v=v1", "*");
+    assert_eq!(response.headers().get_one("Content-Encoding"), Some("br"));
+}
+
+#[test]
+fn to_absolute_puts_links_under_the_base_url() {
+    static MAP: SortedMap = SortedMap::new(&[(
+        "og.png",
+        Asset {
+            version: "v1",
+            ..ASSET
+        },
+    )]);
+
+    let dir = TempDir::new();
+    let absolute = |extra: &str, base_url: &str| {
+        let client = testing::client(StaticFiles::fairing(&MAP), dir.path(), extra);
+        let static_files = client.rocket().state::<StaticFiles>().unwrap();
+        static_files.to_absolute(base_url, "og.png")
+    };
+
+    let expected = "https://example.com/static/og.png?v=v1";
+    assert_eq!(absolute("", "https://example.com"), expected);
+    assert_eq!(absolute("", "https://example.com/"), expected);
+
+    // Links that are already absolute keep their own host.
+    assert_eq!(
+        absolute(
+            "base_href = \"https://cdn.example.net\"",
+            "https://example.com"
+        ),
+        "https://cdn.example.net/static/og.png?v=v1"
+    );
+    assert_eq!(
+        absolute("base_href = \"//cdn.example.net\"", "http://example.com"),
+        "http://cdn.example.net/static/og.png?v=v1"
+    );
+}
+
+#[test]
+fn never_cache_types_revalidate_even_when_versioned() {
+    static MAP: SortedMap = SortedMap::new(&[
+        (
+            "app.js",
+            Asset {
+                version: "v1",
+                ..ASSET
+            },
+        ),
+        (
+            "index.html",
+            Asset {
+                version: "v1",
+                ..ASSET
+            },
+        ),
+        (
+            "logo.png",
+            Asset {
+                version: "v1",
+                ..ASSET
+            },
+        ),
+    ]);
+
+    let dir = TempDir::new();
+    for name in &["app.js", "index.html", "logo.png"] {
+        dir.write(name, "contents");
+    }
+    let client = testing::client(
+        StaticFiles::fairing(&MAP),
+        dir.path(),
+        "never_cache_types = [\"text/html\", \"image/*\"]\n\
+         [cache_directives]\n\
+         versioned = [\"public\", \"max-age=31536000\", \"immutable\"]\n",
+    );
+
+    for url in &["/static/index.html?v=v1", "/static/logo.png?v=v1"] {
+        let response = client.get(*url).dispatch();
+        assert_eq!(response.status(), Status::Ok, "{}", url);
+        assert_eq!(
+            response.headers().get_one("Cache-Control"),
+            Some("no-cache"),
+            "{}",
+            url
+        );
+    }
+
+    let response = client.get("/static/app.js?v=v1").dispatch();
+    assert_eq!(
+        response.headers().get_one("Cache-Control"),
+        Some("public, max-age=31536000, immutable")
+    );
+}
+
+#[test]
+fn empty_maps_serve_everything_untracked() {
+    // What the generator writes for an empty static root.
+    static EMPTY: phf::Map<&'static str, Asset> = phf::Map {
+        key: 12913932095322966823,
+        disps: phf::Slice::Static(&[]),
+        entries: phf::Slice::Static(&[]),
+    };
+
+    let (_dir, client) = testing::serve(&[("app.js", "app")], StaticFiles::fairing(&EMPTY), "");
+
+    let static_files = client.rocket().state::<StaticFiles>().unwrap();
+    assert_eq!(static_files.to("app.js"), "/static/app.js");
+
+    let mut response = client.get("/static/app.js").dispatch();
+    assert_eq!(response.status(), Status::Ok);
+    assert_eq!(
+        response.headers().get_one("Cache-Control"),
+        Some("no-cache")
+    );
+    assert_eq!(response.body_string().as_deref(), Some("app"));
+}
+
+#[test]
+fn dpr_variants_follow_the_client_hint() {
+    let (_dir, client) = testing::serve(
+        &[("img.png", "1x"), ("img@2x.png", "2x")],
+        StaticFiles::etag_only(),
+        "dpr_variants = { \"1\" = \"\", \"2\" = \"@2x\" }",
+    );
+
+    for (hint, ratio, body) in &[
+        ("Sec-CH-DPR", "2", "2x"),
+        ("Sec-CH-DPR", "3.5", "2x"),
+        ("DPR", "2", "2x"),
+        ("Sec-CH-DPR", "1.5", "1x"),
+    ] {
+        let mut response = client
+            .get("/static/img.png")
+            .header(Header::new(*hint, *ratio))
+            .dispatch();
+        assert_eq!(response.status(), Status::Ok, "{}: {}", hint, ratio);
+        assert_eq!(
+            response.headers().get_one("Vary"),
+            Some("Sec-CH-DPR, DPR"),
+            "{}: {}",
+            hint,
+            ratio
+        );
+        assert_eq!(
+            response.body_string().as_deref(),
+            Some(*body),
+            "{}: {}",
+            hint,
+            ratio
+        );
+    }
+
+    let mut response = client.get("/static/img.png").dispatch();
+    assert_eq!(response.headers().get_one("Vary"), Some("Sec-CH-DPR, DPR"));
+    assert_eq!(response.body_string().as_deref(), Some("1x"));
+}
+
+#[test]
+fn reject_unknown_query_refuses_other_parameters() {
+    static MAP: SortedMap = SortedMap::new(&[(
+        "app.js",
+        Asset {
+            version: "v1",
+            ..ASSET
+        },
+    )]);
+
+    let (dir, client) = testing::serve(
+        &[("app.js", "app")],
+        StaticFiles::fairing(&MAP),
+        "reject_unknown_query = true",
+    );
+
+    for url in &[
+        "/static/app.js?foo=bar",
+        "/static/app.js?v=v1&foo=bar",
+        "/static/app.js?v=v1&cachebust",
+    ] {
+        let response = client.get(*url).dispatch();
+        assert_eq!(response.status(), Status::BadRequest, "{}", url);
+    }
+
+    for url in &[
+        "/static/app.js?v=v1",
+        "/static/app.js?%76=v1",
+        "/static/app.js?v=v1&",
+    ] {
+        let response = client.get(*url).dispatch();
+        assert_eq!(response.status(), Status::Ok, "{}", url);
+    }
+
+    let client = testing::client(StaticFiles::fairing(&MAP), dir.path(), "");
+    let response = client.get("/static/app.js?v=v1&foo=bar").dispatch();
+    assert_eq!(response.status(), Status::Ok);
+}
+
+#[test]
+fn refuses_to_redirect_back_to_the_requested_url() {
+    static MAP: SortedMap = SortedMap::new(&[(
+        "app.js",
+        Asset {
+            version: "v1",
+            ..ASSET
+        },
+    )]);
+
+    let (_dir, client) = testing::serve(&[("app.js", "app")], StaticFiles::fairing(&MAP), "");
+    let static_files = client.rocket().state::<StaticFiles>().unwrap();
+
+    let serve_from = |request_uri: &str| {
+        let options = Options {
+            expected_revision: Some("v0"),
+            request_uri: Some(request_uri),
+            ..Options::default()
+        };
+
+        serve(
+            static_files,
+            "app.js",
+            options,
+            AcceptEncoding::default(),
+            &Conditional::default(),
+        )
+    };
+
+    // As if something between the client and here had mangled the token,
+    // so the client asked for the very URL it would be sent to.
+    match serve_from("/static/app.js?v=v1") {
+        Err(error @ Error::RedirectLoop) => {
+            assert_eq!(error.status(), Status::InternalServerError)
+        }
+        other => panic!("expected RedirectLoop, got {:?}", other),
+    }
+
+    match serve_from("/static/app.js?v=v0") {
+        Ok(StaticResponse::Redirect(_)) => (),
+        other => panic!("expected a redirect, got {:?}", other),
+    }
+}
+
+/// A `tracing` subscriber keeping every span's fields, and every event's
+/// message, for tests to look at.
+#[cfg(feature = "tracing")]
+mod trace {
+    use std::fmt;
+    use std::sync::{Arc, Mutex};
+
+    use tracing::field::{Field, Visit};
+    use tracing::span::{Attributes, Id, Record};
+    use tracing::{Event, Metadata, Subscriber};
+
+    type Fields = Vec<(String, String)>;
+
+    #[derive(Clone, Default)]
+    pub(super) struct Capture {
+        spans: Arc<Mutex<Vec<(&'static str, Fields)>>>,
+        events: Arc<Mutex<Vec<String>>>,
+    }
+
+    impl Capture {
+        /// The fields of every span called `name`, in the order they opened.
+        pub(super) fn spans(&self, name: &str) -> Vec<Fields> {
+            let spans = self.spans.lock().unwrap();
+            spans
+                .iter()
+                .filter(|(span, _)| *span == name)
+                .map(|(_, fields)| fields.clone())
+                .collect()
+        }
+
+        pub(super) fn events(&self) -> Vec<String> {
+            self.events.lock().unwrap().clone()
+        }
+    }
+
+    struct Visitor<'a>(&'a mut Fields);
+
+    impl Visit for Visitor<'_> {
+        fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+            self.0
+                .push((field.name().to_owned(), format!("{:?}", value)));
+        }
+    }
+
+    impl Subscriber for Capture {
+        fn enabled(&self, _: &Metadata) -> bool {
+            true
+        }
+
+        fn new_span(&self, span: &Attributes) -> Id {
+            let mut fields = Vec::new();
+            span.record(&mut Visitor(&mut fields));
+
+            let mut spans = self.spans.lock().unwrap();
+            spans.push((span.metadata().name(), fields));
+            Id::from_u64(spans.len() as u64)
+        }
+
+        fn record(&self, span: &Id, values: &Record) {
+            let mut spans = self.spans.lock().unwrap();
+            let (_, fields) = &mut spans[span.into_u64() as usize - 1];
+            values.record(&mut Visitor(fields));
+        }
+
+        fn record_follows_from(&self, _: &Id, _: &Id) {}
+
+        fn event(&self, event: &Event) {
+            let mut fields = Vec::new();
+            event.record(&mut Visitor(&mut fields));
+
+            let messages = fields.into_iter().filter(|(name, _)| name == "message");
+            let mut events = self.events.lock().unwrap();
+            events.extend(messages.map(|(_, message)| message));
+        }
+
+        fn enter(&self, _: &Id) {}
+
+        fn exit(&self, _: &Id) {}
+    }
+}
+
+#[test]
+#[cfg(feature = "tracing")]
+fn traces_each_request_in_a_span() {
+    static MAP: SortedMap = SortedMap::new(&[(
+        "app.js",
+        Asset {
+            version: "v1",
+            ..ASSET
+        },
+    )]);
+
+    let (_dir, client) = testing::serve(&[("app.js", "app")], StaticFiles::fairing(&MAP), "");
+
+    let capture = trace::Capture::default();
+    tracing::subscriber::with_default(capture.clone(), || {
+        client.get("/static/app.js?v=v1").dispatch();
+        client
+            .get("/static/app.js?v=v1")
+            .header(Header::new("If-None-Match", "\"v1\""))
+            .dispatch();
+        client.get("/static/app.js?v=v0").dispatch();
+        client.get("/static/missing.js").dispatch();
+    });
+
+    let fields = |fields: &[(&str, &str)]| -> Vec<(String, String)> {
+        let owned = fields.iter().map(|(k, v)| (k.to_string(), v.to_string()));
+        owned.collect()
+    };
+
+    assert_eq!(
+        capture.spans("serve_static"),
+        [
+            fields(&[
+                ("path", "app.js"),
+                ("bytes", "3"),
+                ("outcome", "\"file\""),
+                ("status", "200"),
+            ]),
+            fields(&[
+                ("path", "app.js"),
+                ("outcome", "\"not_modified\""),
+                ("status", "304"),
+            ]),
+            fields(&[("path", "app.js"), ("outcome", "\"redirect\"")]),
+            fields(&[
+                ("path", "missing.js"),
+                ("outcome", "\"error\""),
+                ("status", "404"),
+            ]),
+        ]
+    );
+    assert_eq!(capture.events(), ["cache miss", "cache hit"]);
+}
+
+#[test]
+fn serve_from_searches_each_directory_in_order() {
+    static MAP: SortedMap = SortedMap::new(&[
+        (
+            "app.js",
+            Asset {
+                version: "v1",
+                ..ASSET
+            },
+        ),
+        (
+            "site.css",
+            Asset {
+                version: "v1",
+                ..ASSET
+            },
+        ),
+    ]);
+
+    let theme = TempDir::new();
+    theme.write("site.css", "theme");
+    let base = TempDir::new();
+    base.write("site.css", "base");
+    base.write("app.js", "app");
+
+    let toml = format!(
+        "serve_from = [{:?}, {:?}]\npath_prefix = \"/static\"",
+        theme.path().to_str().unwrap(),
+        base.path().to_str().unwrap()
+    );
+    let rocket = testing::rocket_toml(&toml).attach(StaticFiles::fairing(&MAP));
+    let client = Client::new(rocket).unwrap();
+
+    let mut response = client.get("/static/site.css?v=v1").dispatch();
+    assert_eq!(response.status(), Status::Ok);
+    assert_eq!(response.body_string().as_deref(), Some("theme"));
+
+    let mut response = client.get("/static/app.js?v=v1").dispatch();
+    assert_eq!(response.status(), Status::Ok);
+    assert_eq!(response.body_string().as_deref(), Some("app"));
+}
+
+#[test]
+fn charset_by_type_overrides_the_default_charset() {
+    let (_dir, client) = testing::serve(
+        &[("notes.txt", "caf\u{e9}"), ("site.css", "body {}")],
+        StaticFiles::fairing(&EMPTY),
+        "default_charset = \"utf-8\"\n\
+         [charset_by_type]\n\
+         \"text/plain\" = \"iso-8859-1\"\n",
+    );
+
+    let response = client.get("/static/notes.txt").dispatch();
+    assert_eq!(response.status(), Status::Ok);
+    assert_eq!(
+        response.headers().get_one("Content-Type"),
+        Some("text/plain; charset=iso-8859-1")
+    );
+
+    let response = client.get("/static/site.css").dispatch();
+    assert_eq!(
+        response.headers().get_one("Content-Type"),
+        Some("text/css; charset=utf-8")
+    );
+}
+
+#[test]
+fn warmed_files_are_served_as_they_were_read() {
+    static MAP: SortedMap = SortedMap::new(&[(
+        "app.js",
+        Asset {
+            version: "v1",
+            gzip: Some(6),
+            ..ASSET
+        },
+    )]);
+
+    let (dir, client) = testing::serve(
+        &[
+            ("app.js", "app"),
+            ("app.js.gz", "zipped"),
+            ("other.js", "other"),
+        ],
+        StaticFiles::fairing(&MAP),
+        "",
+    );
+    let static_files = client.rocket().state::<StaticFiles>().unwrap();
+
+    static_files.warm(&["app.js", "/other.js"]).unwrap();
+    dir.write("app.js", "changed");
+    dir.write("app.js.gz", "changed");
+    dir.write("other.js", "changed");
+
+    let mut response = client.get("/static/app.js?v=v1").dispatch();
+    assert_eq!(response.status(), Status::Ok);
+    assert_eq!(response.body_string().as_deref(), Some("app"));
+
+    let mut response = client
+        .get("/static/app.js?v=v1")
+        .header(Header::new("Accept-Encoding", "gzip"))
+        .dispatch();
+    assert_eq!(response.headers().get_one("Content-Encoding"), Some("gzip"));
+    assert_eq!(response.body_string().as_deref(), Some("zipped"));
+
+    let mut response = client.get("/static/other.js").dispatch();
+    assert_eq!(response.body_string().as_deref(), Some("other"));
+
+    assert!(static_files.warm(&["missing.js"]).is_err());
+}
+
+#[test]
+fn paths_are_looked_up_with_or_without_a_leading_slash() {
+    static MAP: SortedMap = SortedMap::new(&[(
+        "css/app.css",
+        Asset {
+            version: "v1",
+            integrity: Some("sha384-abc"),
+            ..ASSET
+        },
+    )]);
+
+    let (_dir, client) = testing::serve(
+        &[("css/app.css", "body {}")],
+        StaticFiles::fairing(&MAP),
+        "",
+    );
+    let static_files = client.rocket().state::<StaticFiles>().unwrap();
+
+    assert_eq!(static_files.to("css/app.css"), "/static/css/app.css?v=v1");
+    assert_eq!(static_files.to("/css/app.css"), "/static/css/app.css?v=v1");
+    assert_eq!(static_files.to("//css/app.css"), "/static/css/app.css?v=v1");
+    assert_eq!(static_files.to("untracked.js"), "/static/untracked.js");
+    assert_eq!(static_files.to("/untracked.js"), "/static/untracked.js");
+
+    assert_eq!(static_files.integrity("css/app.css"), Some("sha384-abc"));
+    assert_eq!(static_files.integrity("/css/app.css"), Some("sha384-abc"));
+}
+
+#[cfg(feature = "gen")]
+#[test]
+fn serves_virtual_files_without_them_on_disk() {
+    use crate::gen::{Generator, MemorySource};
+
+    let (files, _) = Generator::with_source(MemorySource::new())
+        .virtual_file("css/generated.css", b"body {}".to_vec())
+        .scan()
+        .unwrap();
+    let map = testing::leak_map(
+        files
+            .iter()
+            .map(|(path, entry)| {
+                let embedded = entry.embedded.clone().unwrap();
+                let asset = Asset {
+                    version: testing::leak(&entry.token),
+                    embedded: Some(Box::leak(embedded.into_boxed_slice())),
+                    ..ASSET
+                };
+
+                (testing::leak(path), asset)
+            })
+            .collect(),
+    );
+
+    let (_dir, client) = testing::serve(&[], StaticFiles::fairing(map), "");
+    let static_files = client.rocket().state::<StaticFiles>().unwrap();
+
+    let url = static_files.to("css/generated.css");
+    assert_eq!(
+        url,
+        format!(
+            "/static/css/generated.css?v={}",
+            files["css/generated.css"].token
+        )
+    );
+
+    let mut response = client.get(url).dispatch();
+    assert_eq!(response.status(), Status::Ok);
+    assert_eq!(response.headers().get_one("Content-Type"), Some("text/css"));
+    assert_eq!(response.body_string().as_deref(), Some("body {}"));
+}
+
+#[cfg(feature = "gen")]
+#[test]
+fn serves_gzipped_virtual_files_without_them_on_disk() {
+    use crate::gen::{Generator, MemorySource};
+
+    let css = "body { color: red; }\n".repeat(100);
+    let (files, _) = Generator::with_source(MemorySource::new())
+        .embed_gzip(true)
+        .virtual_file("css/generated.css", css.clone().into_bytes())
+        .scan()
+        .unwrap();
+    let entry = &files["css/generated.css"];
+    let compressed: &'static [u8] = Box::leak(entry.embedded.clone().unwrap().into_boxed_slice());
+    let map = testing::leak_map(vec![(
+        "css/generated.css",
+        Asset {
+            version: testing::leak(&entry.token),
+            gzip: entry.gzip,
+            embedded: Some(compressed),
+            embedded_size: entry.embedded_size,
+            ..ASSET
+        },
+    )]);
+    assert_eq!(entry.embedded_size, Some(css.len() as u64));
+
+    let (_dir, client) = testing::serve(&[], StaticFiles::fairing(map), "");
+    let url = client
+        .rocket()
+        .state::<StaticFiles>()
+        .unwrap()
+        .to("css/generated.css");
+
+    let mut response = client
+        .get(url.clone())
+        .header(Header::new("Accept-Encoding", "gzip, br"))
+        .dispatch();
+    assert_eq!(response.status(), Status::Ok);
+    assert_eq!(response.headers().get_one("Content-Encoding"), Some("gzip"));
+    assert_eq!(response.body_bytes().as_deref(), Some(compressed));
+
+    let mut response = client.get(url).dispatch();
+    assert_eq!(response.status(), Status::Ok);
+    assert_eq!(response.headers().get_one("Content-Encoding"), None);
+    assert_eq!(response.headers().get_one("Content-Type"), Some("text/css"));
+    assert_eq!(response.body_string(), Some(css));
+}
+
+#[test]
+fn stale_etags_get_the_whole_file_and_the_current_one() {
+    static MAP: SortedMap = SortedMap::new(&[(
+        "app.js",
+        Asset {
+            version: "v2",
+            ..ASSET
+        },
+    )]);
+
+    let (dir, client) = testing::serve(&[("app.js", "app")], StaticFiles::fairing(&MAP), "");
+
+    // If-Modified-Since would call it current, but If-None-Match wins.
+    let mut response = client
+        .get("/static/app.js?v=v2")
+        .header(Header::new("If-None-Match", "\"v1\""))
+        .header(Header::new(
+            "If-Modified-Since",
+            "Fri, 31 Dec 9999 23:59:59 GMT",
+        ))
+        .dispatch();
+    assert_eq!(response.status(), Status::Ok);
+    assert_eq!(response.headers().get_one("ETag"), Some("\"v2\""));
+    assert_eq!(response.body_string().as_deref(), Some("app"));
+
+    let response = client
+        .get("/static/app.js?v=v2")
+        .header(Header::new("If-None-Match", "\"v1\", W/\"v2\""))
+        .dispatch();
+    assert_eq!(response.status(), Status::NotModified);
+
+    // Untracked files compare against their own tag the same way.
+    let client = testing::client(StaticFiles::etag_only(), dir.path(), "");
+    let etag = client.get("/static/app.js").dispatch();
+    let etag = etag.headers().get_one("ETag").unwrap().to_owned();
+
+    dir.write("app.js", "changed");
+    let mut response = client
+        .get("/static/app.js")
+        .header(Header::new("If-None-Match", etag.clone()))
+        .dispatch();
+    assert_eq!(response.status(), Status::Ok);
+    let current = response.headers().get_one("ETag").unwrap().to_owned();
+    assert_ne!(current, etag);
+    assert_eq!(response.body_string().as_deref(), Some("changed"));
+
+    let response = client
+        .get("/static/app.js")
+        .header(Header::new("If-None-Match", current))
+        .dispatch();
+    assert_eq!(response.status(), Status::NotModified);
+}
+
+#[test]
+fn glob_lists_matching_paths_with_their_tokens() {
+    static MAP: SortedMap = SortedMap::new(&[
+        (
+            "css/app.css",
+            Asset {
+                version: "v0",
+                ..ASSET
+            },
+        ),
+        (
+            "fonts/.hidden.woff2",
+            Asset {
+                version: "v1",
+                ..ASSET
+            },
+        ),
+        (
+            "fonts/a.woff",
+            Asset {
+                version: "v2",
+                ..ASSET
+            },
+        ),
+        (
+            "fonts/a.woff2",
+            Asset {
+                version: "v3",
+                ..ASSET
+            },
+        ),
+        (
+            "fonts/b.woff2",
+            Asset {
+                version: "v4",
+                ..ASSET
+            },
+        ),
+        (
+            "fonts/sub/c.woff2",
+            Asset {
+                version: "v5",
+                ..ASSET
+            },
+        ),
+    ]);
+
+    let (_dir, client) = testing::serve(&[], StaticFiles::fairing(&MAP), "");
+    let static_files = client.rocket().state::<StaticFiles>().unwrap();
+
+    let owned = |entries: &[(&str, &'static str)]| -> Vec<(String, &'static str)> {
+        entries.iter().map(|(p, v)| (p.to_string(), *v)).collect()
+    };
+
+    assert_eq!(
+        static_files.glob("fonts/*.woff2"),
+        owned(&[("fonts/a.woff2", "v3"), ("fonts/b.woff2", "v4")])
+    );
+    assert_eq!(
+        static_files.glob("/fonts/**/*.woff2"),
+        owned(&[
+            ("fonts/a.woff2", "v3"),
+            ("fonts/b.woff2", "v4"),
+            ("fonts/sub/c.woff2", "v5"),
+        ])
+    );
+    assert_eq!(static_files.glob("images/*"), owned(&[]));
+}
+
+#[test]
+fn vary_is_one_header_naming_each_field_once() {
+    static MAP: SortedMap = SortedMap::new(&[
+        (
+            "img.png",
+            Asset {
+                version: "v1",
+                gzip: Some(2),
+                ..ASSET
+            },
+        ),
+        (
+            "img@2x.png",
+            Asset {
+                version: "v2",
+                gzip: Some(2),
+                ..ASSET
+            },
+        ),
+    ]);
+
+    let (_dir, client) = testing::serve(
+        &[("img.png", "1x"), ("img@2x.png", "2x")],
+        StaticFiles::fairing(&MAP),
+        "dpr_variants = { \"1\" = \"\", \"2\" = \"@2x\" }",
+    );
+
+    let response = client
+        .get("/static/img.png")
+        .header(Header::new("Sec-CH-DPR", "2"))
+        .dispatch();
+    assert_eq!(response.status(), Status::Ok);
+    let vary: Vec<_> = response.headers().get("Vary").collect();
+    assert_eq!(vary, ["Accept-Encoding, Sec-CH-DPR, DPR"]);
+    let etag = response.headers().get_one("ETag").unwrap().to_owned();
+
+    let response = client
+        .get("/static/img.png")
+        .header(Header::new("Sec-CH-DPR", "2"))
+        .header(Header::new("If-None-Match", etag))
+        .dispatch();
+    assert_eq!(response.status(), Status::NotModified);
+    let vary: Vec<_> = response.headers().get("Vary").collect();
+    assert_eq!(vary, ["Accept-Encoding, Sec-CH-DPR, DPR"]);
+}
+
+#[cfg(feature = "dictionary")]
+#[test]
+fn dictionary_siblings_go_to_clients_with_the_dictionary() {
+    static MAP: SortedMap = SortedMap::new(&[(
+        "app.js",
+        Asset {
+            version: "v1",
+            brotli: Some(2),
+            ..ASSET
+        },
+    )]);
+
+    let dictionary = [7; 32];
+    let dcb = |hash: &[u8; 32], body: &str| {
+        let mut contents = vec![0xff, 0x44, 0x43, 0x42];
+        contents.extend_from_slice(hash);
+        contents.extend_from_slice(body.as_bytes());
+        contents
+    };
+
+    let dir = TempDir::new();
+    dir.write("app.js", "app");
+    dir.write("app.js.br", "br");
+    dir.write("app.js.dcb", dcb(&dictionary, "dcb"));
+    let client = testing::client(StaticFiles::fairing(&MAP), dir.path(), "");
+
+    let request = |available: &[u8; 32]| {
+        client
+            .get("/static/app.js?v=v1")
+            .header(Header::new("Accept-Encoding", "br, dcb"))
+            .header(Header::new(
+                "Available-Dictionary",
+                format!(":{}:", base64::encode(available)),
+            ))
+    };
+
+    let response = request(&dictionary).dispatch();
+    assert_eq!(response.status(), Status::Ok);
+    assert_eq!(response.headers().get_one("Content-Encoding"), Some("dcb"));
+    let vary: Vec<_> = response.headers().get("Vary").collect();
+    assert_eq!(vary, ["Accept-Encoding, Available-Dictionary"]);
+
+    // Some other dictionary, so the plain Brotli sibling.
+    let mut response = request(&[8; 32]).dispatch();
+    assert_eq!(response.headers().get_one("Content-Encoding"), Some("br"));
+    let vary: Vec<_> = response.headers().get("Vary").collect();
+    assert_eq!(vary, ["Accept-Encoding, Available-Dictionary"]);
+    assert_eq!(response.body_string().as_deref(), Some("br"));
+
+    // Recompressed against another dictionary: the cached hash is dropped.
+    dir.write("app.js.dcb", dcb(&[8; 32], "newer dcb"));
+    let response = request(&dictionary).dispatch();
+    assert_eq!(response.headers().get_one("Content-Encoding"), Some("br"));
+    let response = request(&[8; 32]).dispatch();
+    assert_eq!(response.headers().get_one("Content-Encoding"), Some("dcb"));
+}
+
+#[cfg(feature = "reload")]
+#[test]
+fn requests_during_a_reload_see_one_whole_map() {
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::{Arc, RwLock};
+    use std::thread;
+
+    const NAMES: [&str; 2] = ["app", "lib"];
+
+    // Every file changes each generation, so no token is shared by two.
+    fn write_generation(root: &Path, generation: usize) {
+        for name in &NAMES {
+            let path = root.join(format!("{}.js", name));
+            std::fs::write(path, format!("{} {}", name, generation)).unwrap();
+        }
+    }
+
+    let (dir, client) = testing::serve(&[], StaticFiles::fairing(&EMPTY), "");
+    let static_files = client.rocket().state::<StaticFiles>().unwrap();
+
+    let mut links = Vec::new();
+    let mut listings = Vec::new();
+    for generation in 0..2 {
+        write_generation(dir.path(), generation);
+        static_files.reload(dir.path()).unwrap();
+        let urls: Vec<_> = NAMES
+            .iter()
+            .map(|name| static_files.to(format!("{}.js", name)))
+            .collect();
+        links.push(urls);
+        listings.push(static_files.glob("*.js"));
+    }
+    assert_eq!(listings[0].len(), 2);
+    assert_ne!(listings[0][0], listings[1][0]);
+    assert_ne!(listings[0][1], listings[1][1]);
+
+    // Held for writing while the files and map are out of step, so a request
+    // made under it can check its link and body are from one generation.
+    let settled = Arc::new(RwLock::new(()));
+    let done = Arc::new(AtomicBool::new(false));
+    let reloads = {
+        let static_files = static_files.clone();
+        let root = dir.path().to_owned();
+        let settled = settled.clone();
+        let done = done.clone();
+
+        thread::spawn(move || {
+            for i in 0..50 {
+                let _settling = settled.write().unwrap();
+                write_generation(&root, i % 2);
+                static_files.reload(&root).unwrap();
+            }
+
+            done.store(true, Ordering::SeqCst);
+        })
+    };
+
+    let mut requests = 0;
+    while requests < 100 || !done.load(Ordering::SeqCst) {
+        // Racing the reloads: a listing is all of one generation or the other.
+        let listed = static_files.glob("*.js");
+        assert!(listings.contains(&listed), "{:?}", listed);
+
+        let _settled = settled.read().unwrap();
+        for (i, name) in NAMES.iter().enumerate() {
+            let url = static_files.to(format!("{}.js", name));
+            let generation = links.iter().position(|l| l[i] == url).expect(&url);
+
+            let mut response = client.get(url.as_str()).dispatch();
+            assert_eq!(response.status(), Status::Ok, "{}", url);
+            assert_eq!(
+                response.body_string(),
+                Some(format!("{} {}", name, generation)),
+                "{}",
+                url
+            );
+        }
+
+        requests += 1;
+    }
+
+    reloads.join().unwrap();
+}
+
+#[cfg(unix)]
+#[test]
+fn server_error_page_is_the_body_of_internal_errors() {
+    let dir = TempDir::new();
+    dir.write("500.html", "<h1>Sorry</h1>");
+    // Can't be resolved, and not for being missing.
+    std::os::unix::fs::symlink("loop.js", dir.path().join("loop.js")).unwrap();
+    let client = testing::client(
+        StaticFiles::etag_only(),
+        dir.path(),
+        "server_error_page = \"500.html\"",
+    );
+
+    let mut response = client.get("/static/loop.js").dispatch();
+    assert_eq!(response.status(), Status::InternalServerError);
+    assert_eq!(
+        response.headers().get_one("Content-Type"),
+        Some("text/html")
+    );
+    assert_eq!(
+        response.headers().get_one("Cache-Control"),
+        Some("no-store")
+    );
+    assert_eq!(response.headers().get_one("ETag"), None);
+    assert_eq!(response.body_string().as_deref(), Some("<h1>Sorry</h1>"));
+
+    // Only internal errors get it.
+    let response = client.get("/static/missing.js").dispatch();
+    assert_eq!(response.status(), Status::NotFound);
+
+    // A page that fails too is given up on, not retried.
+    let client = testing::client(
+        StaticFiles::etag_only(),
+        dir.path(),
+        "server_error_page = \"loop.js\"",
+    );
+    let mut response = client.get("/static/loop.js").dispatch();
+    assert_eq!(response.status(), Status::InternalServerError);
+    // Rocket's own catcher instead.
+    let body = response.body_string().unwrap();
+    assert!(body.contains("500: Internal Server Error"), "{}", body);
+    assert!(!body.contains("Sorry"), "{}", body);
+}
+
+#[test]
+fn cache_status_header_labels_each_outcome() {
+    static MAP: SortedMap = SortedMap::new(&[
+        (
+            "app.js",
+            Asset {
+                version: "v1",
+                ..ASSET
+            },
+        ),
+        (
+            "icons/favicon.ico",
+            Asset {
+                version: "v1",
+                ..ASSET
+            },
+        ),
+        (
+            "releases/app-1.2.zip",
+            Asset {
+                version: "v1",
+                ..ASSET
+            },
+        ),
+    ]);
+
+    let dir = TempDir::new();
+    dir.write("app.js", "app");
+    dir.write("untracked.js", "untracked");
+    dir.write("icons/favicon.ico", "icon");
+    dir.write("releases/app-1.2.zip", "zip");
+    let rocket = testing::rocket(
+        dir.path(),
+        "cache_status_header = true\n\
+         root_aliases = { \"favicon.ico\" = \"icons/favicon.ico\" }",
+    )
+    .attach(StaticFiles::fairing(&MAP))
+    .mount("/", routes![latest]);
+    let client = Client::new(rocket).unwrap();
+
+    let cache_status = |response: &rocket::local::LocalResponse| {
+        response
+            .headers()
+            .get_one("X-Cache-Status")
+            .map(str::to_owned)
+    };
+
+    let response = client.get("/static/app.js?v=v1").dispatch();
+    assert_eq!(response.status(), Status::Ok);
+    assert_eq!(cache_status(&response).as_deref(), Some("immutable"));
+
+    let response = client.get("/static/app.js?v=v0").dispatch();
+    assert_eq!(response.status(), Status::SeeOther);
+    assert_eq!(cache_status(&response).as_deref(), Some("redirect"));
+
+    let response = client
+        .get("/static/app.js?v=v1")
+        .header(Header::new("If-None-Match", "\"v1\""))
+        .dispatch();
+    assert_eq!(response.status(), Status::NotModified);
+    assert_eq!(cache_status(&response).as_deref(), Some("revalidated"));
+
+    let response = client.get("/static/untracked.js").dispatch();
+    assert_eq!(response.status(), Status::Ok);
+    assert_eq!(cache_status(&response).as_deref(), Some("miss"));
+
+    let response = client.get("/static/missing.js").dispatch();
+    assert_eq!(response.status(), Status::NotFound);
+    assert_eq!(cache_status(&response), None);
+
+    // Outside path_prefix too.
+    let response = client.get("/favicon.ico").dispatch();
+    assert_eq!(response.status(), Status::Ok);
+    assert_eq!(cache_status(&response).as_deref(), Some("miss"));
+
+    let response = client
+        .get("/download/latest")
+        .header(Header::new("If-None-Match", "\"v1\""))
+        .dispatch();
+    assert_eq!(response.status(), Status::NotModified);
+    assert_eq!(cache_status(&response).as_deref(), Some("revalidated"));
+
+    // Off by default.
+    let client = testing::client(StaticFiles::fairing(&MAP), dir.path(), "");
+    let response = client.get("/static/app.js?v=v1").dispatch();
+    assert_eq!(cache_status(&response), None);
+}
+
+#[test]
+fn timing_allow_origin_is_sent_when_configured() {
+    static MAP: SortedMap = SortedMap::new(&[(
+        "app.js",
+        Asset {
+            version: "v1",
+            ..ASSET
+        },
+    )]);
+
+    let (dir, client) = testing::serve(
+        &[("app.js", "app"), ("untracked.js", "untracked")],
+        StaticFiles::fairing(&MAP),
+        "timing_allow_origin = \"https://rum.example.com\"",
+    );
+
+    for url in &["/static/app.js?v=v1", "/static/untracked.js"] {
+        let response = client.get(*url).dispatch();
+        assert_eq!(response.status(), Status::Ok, "{}", url);
+        assert_eq!(
+            response.headers().get_one("Timing-Allow-Origin"),
+            Some("https://rum.example.com"),
+            "{}",
+            url
+        );
+    }
+
+    let client = testing::client(StaticFiles::fairing(&MAP), dir.path(), "");
+    let response = client.get("/static/app.js?v=v1").dispatch();
+    assert_eq!(response.headers().get_one("Timing-Allow-Origin"), None);
+
+    let rocket = testing::rocket(dir.path(), "timing_allow_origin = \"\"")
+        .attach(StaticFiles::fairing(&MAP));
+    assert!(testing::fails_to_launch(rocket));
+}
@@ -3,20 +3,36 @@
  * file, You can obtain one at https://mozilla.org/MPL/2.0/. */
 
 pub use crate::gen::error::Error;
+#[cfg(feature = "zip")]
+pub use crate::gen::source::ZipSource;
+pub use crate::gen::source::{FileSource, Filesystem, MemorySource};
+
+use crate::asset::{self, Encoding};
+use crate::glob::Glob;
+use crate::lexical;
+use crate::map;
 
 use phf_codegen::Map;
 
+use serde::Serialize;
+
+use sha2::{Digest, Sha256};
+
 use siphasher::sip::SipHasher;
 
-use snafu::{OptionExt, ResultExt, Snafu};
+use snafu::{ensure, OptionExt, ResultExt, Snafu};
 
-use std::collections::HashMap;
+use std::borrow::Cow;
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::fs::File;
 use std::hash::Hasher;
-use std::io::{Read, Write};
+use std::io::Write;
 use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
 
-use walkdir::WalkDir;
+mod source;
+#[cfg(test)]
+mod tests;
 
 mod error {
     use super::*;
@@ -27,73 +43,1069 @@ mod error {
         WalkDir { source: walkdir::Error },
         Io { source: std::io::Error },
         Unprintable { path: PathBuf },
+        Collision { first: PathBuf, second: PathBuf },
+        Json { source: serde_json::Error },
+        MissingReferences { paths: Vec<String> },
+        ZstdLevel { level: i32 },
     }
 }
 
-fn hash(path: &Path) -> Result<u64, Error> {
-    let mut file = File::open(path).context(error::Io)?;
-    let mut hasher = SipHasher::new();
+/// Which precompressed siblings [`Generator`] should write.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Precompress {
+    /// Write `<file>.gz` next to each compressible file.
+    Gzip,
+
+    /// Write `<file>.br` next to each compressible file.
+    Brotli,
+
+    /// Write `<file>.zst` next to each compressible file.
+    Zstd,
+
+    /// Write both `<file>.gz` and `<file>.br`.
+    Both,
+
+    /// Write `<file>.gz`, `<file>.br` and `<file>.zst`.
+    All,
+}
+
+impl Precompress {
+    fn encodings(self) -> &'static [Encoding] {
+        match self {
+            Precompress::Gzip => &[Encoding::Gzip],
+            Precompress::Brotli => &[Encoding::Brotli],
+            Precompress::Zstd => &[Encoding::Zstd],
+            Precompress::Both => &[Encoding::Gzip, Encoding::Brotli],
+            Precompress::All => &[Encoding::Gzip, Encoding::Brotli, Encoding::Zstd],
+        }
+    }
+}
+
+/// How [`Generator`] spells version tokens.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TokenEncoding {
+    /// URL-safe base64 without padding, e.g. `H8y4bzqH6Mg`. The default.
+    Base64Url,
+
+    /// Standard base64 with padding, e.g. `H8y4bzqH6Mg=`. Escaped in links.
+    Base64,
+
+    /// Lowercase hexadecimal, e.g. `1fccb86f3a87e8c8`.
+    Hex,
+}
+
+impl TokenEncoding {
+    pub(crate) const ALL: [TokenEncoding; 3] = [
+        TokenEncoding::Base64Url,
+        TokenEncoding::Base64,
+        TokenEncoding::Hex,
+    ];
 
-    let mut buffer = [0u8; 1024];
+    pub(crate) fn encode(self, hash: u64) -> String {
+        let bytes = hash.to_le_bytes();
 
-    while let Ok(read) = file.read(&mut buffer) {
-        if read == 0 {
-            break;
+        match self {
+            TokenEncoding::Base64Url => base64::encode_config(bytes, base64::URL_SAFE_NO_PAD),
+            TokenEncoding::Base64 => base64::encode_config(bytes, base64::STANDARD),
+            TokenEncoding::Hex => bytes.iter().map(|b| format!("{:02x}", b)).collect(),
         }
+    }
+}
 
-        hasher.write(&buffer[0..read]);
+impl Default for TokenEncoding {
+    fn default() -> Self {
+        TokenEncoding::Base64Url
     }
+}
+
+/// Summary of a generation run, for checking asset budgets and the like.
+#[derive(Debug, Default, Clone)]
+#[non_exhaustive]
+pub struct GenStats {
+    /// Number of files included in the map.
+    pub files: usize,
+
+    /// Combined size in bytes of every included file (before compression).
+    pub total_bytes: u64,
+
+    /// Combined size in bytes of included files, keyed by lowercased file
+    /// extension. Files without an extension are counted under `""`.
+    pub bytes_by_extension: HashMap<String, u64>,
 
-    Ok(hasher.finish())
+    /// Work done compressing, keyed by encoding (`"gzip"`, `"br"`, `"zstd"`).
+    pub compression: HashMap<&'static str, CompressionStats>,
 }
 
-fn rerun(path: &Path) -> Result<(), Error> {
-    let txt = path.to_str().with_context(|| error::Unprintable {
-        path: path.to_owned(),
-    })?;
+/// How much compressing with one encoding saved, and what it cost.
+#[derive(Debug, Default, Clone)]
+#[non_exhaustive]
+pub struct CompressionStats {
+    /// Number of files compressed, including ones whose compressed form
+    /// wasn't smaller and was dropped.
+    pub files: usize,
 
-    println!("cargo:rerun-if-changed={}", txt);
-    Ok(())
+    /// Combined size in bytes of those files before compression.
+    pub bytes_in: u64,
+
+    /// Combined size in bytes of those files after compression.
+    pub bytes_out: u64,
+
+    /// Time spent compressing them.
+    pub time: Duration,
+}
+
+impl GenStats {
+    fn record(&mut self, path: &Path, bytes: u64) {
+        let ext = path
+            .extension()
+            .and_then(|e| e.to_str())
+            .unwrap_or_default()
+            .to_ascii_lowercase();
+
+        self.files += 1;
+        self.total_bytes += bytes;
+        *self.bytes_by_extension.entry(ext).or_default() += bytes;
+    }
+
+    /// Compress `contents`, counting the work under `encoding`.
+    fn compress(
+        &mut self,
+        encoding: Encoding,
+        contents: &[u8],
+        zstd_level: i32,
+    ) -> Result<Vec<u8>, Error> {
+        let started = Instant::now();
+        let compressed = compress(encoding, contents, zstd_level)?;
+
+        let stats = self.compression.entry(encoding.name()).or_default();
+        stats.files += 1;
+        stats.bytes_in += contents.len() as u64;
+        stats.bytes_out += compressed.len() as u64;
+        stats.time += started.elapsed();
+
+        Ok(compressed)
+    }
+}
+
+#[derive(Debug, Default)]
+pub(crate) struct Entry {
+    pub(crate) hash: u64,
+
+    /// `hash`, spelled as configured with [`Generator::token_encoding`].
+    pub(crate) token: String,
+
+    pub(crate) size: u64,
+    pub(crate) mtime: Option<u64>,
+    pub(crate) gzip: Option<u64>,
+    pub(crate) brotli: Option<u64>,
+    pub(crate) zstd: Option<u64>,
+    pub(crate) integrity: Option<String>,
+    pub(crate) embedded: Option<Vec<u8>>,
+    pub(crate) embedded_size: Option<u64>,
+}
+
+/// One line of the audit log written by [`Generator::audit_log`].
+#[derive(Debug, Serialize)]
+struct AuditRecord<'a> {
+    path: &'a str,
+    algorithm: &'static str,
+    hash: String,
+    size: u64,
+    mtime: Option<u64>,
+}
+
+/// Scans a static files directory and writes out the map consumed by
+/// `StaticFiles::fairing`.
+///
+/// [`generate`] covers the common case; use `Generator` to turn on extras:
+///
+/// ```nocompile
+/// rocket_static_files::Generator::new(&static_root)
+///     .precompress(rocket_static_files::Precompress::Both)
+///     .generate(&out_path)
+///     .unwrap();
+/// ```
+#[derive(Debug, Clone)]
+pub struct Generator<S = Filesystem> {
+    source: S,
+    precompress: Option<Precompress>,
+    precompress_min_size: u64,
+    zstd_level: i32,
+    audit_log: Option<PathBuf>,
+    token_encoding: TokenEncoding,
+    url_prefix: String,
+    max_url_length: usize,
+    sip_keys: (u64, u64),
+    integrity: bool,
+    normalize_line_endings: bool,
+    embed_max_size: Option<u64>,
+    embed_gzip: bool,
+    manifest_dir: Option<PathBuf>,
+    manifest_hashed: bool,
+    sorted_map: bool,
+    warn_if_empty: bool,
+    virtual_files: Vec<(String, Virtual)>,
+    dotfiles_allowlist: Vec<Glob>,
+}
+
+/// An entry added to the map by hand rather than found under the static root.
+#[derive(Debug, Clone)]
+enum Virtual {
+    /// Hashed like any other file, and embedded.
+    Contents(Vec<u8>),
+
+    /// Used as the token as-is; the file is served from `serve_from`.
+    Token(String),
 }
 
-pub fn generate(out_path: &Path, static_root: &Path) -> Result<(), Error> {
-    let mut files = HashMap::new();
+impl Generator {
+    /// Create a generator for the files under `static_root`.
+    pub fn new<P: AsRef<Path>>(static_root: P) -> Self {
+        Self::with_source(Filesystem::new(static_root))
+    }
+
+    /// Only hash files up to `depth` levels below the static root, as with
+    /// [`Filesystem::max_depth`].
+    pub fn max_depth(mut self, depth: usize) -> Self {
+        self.source = self.source.max_depth(depth);
+        self
+    }
+}
+
+impl<S: FileSource> Generator<S> {
+    /// Create a generator reading files from `source` instead of the
+    /// filesystem.
+    pub fn with_source(source: S) -> Self {
+        Self {
+            source,
+            precompress: None,
+            precompress_min_size: 1024,
+            zstd_level: 19,
+            audit_log: None,
+            token_encoding: TokenEncoding::default(),
+            url_prefix: String::new(),
+            max_url_length: 2000,
+            sip_keys: (0, 0),
+            integrity: false,
+            normalize_line_endings: false,
+            embed_max_size: None,
+            embed_gzip: false,
+            manifest_dir: None,
+            manifest_hashed: false,
+            sorted_map: false,
+            warn_if_empty: true,
+            virtual_files: Vec::new(),
+            dotfiles_allowlist: Vec::new(),
+        }
+    }
+
+    /// Write precompressed siblings alongside compressible files (text,
+    /// scripts, stylesheets, json, svg, ...), so they can be served as-is to
+    /// clients that send a matching `Accept-Encoding`.
+    ///
+    /// Siblings that would not be smaller than the original are skipped. The
+    /// siblings written are left out of the map, while any other file with a
+    /// compressed extension (like an `archive.tar.gz` next to `archive.tar`)
+    /// gets an entry of its own.
+    pub fn precompress(mut self, precompress: Precompress) -> Self {
+        self.precompress = Some(precompress);
+        self
+    }
+
+    /// Files smaller than this many bytes are never precompressed. Defaults
+    /// to 1024.
+    pub fn precompress_min_size(mut self, bytes: u64) -> Self {
+        self.precompress_min_size = bytes;
+        self
+    }
+
+    /// Compression level for `.zst` siblings: higher is smaller but slower.
+    /// Defaults to 19. Generation fails with [`Error::ZstdLevel`] for a level
+    /// outside `zstd::compression_level_range()`.
+    pub fn zstd_level(mut self, level: i32) -> Self {
+        self.zstd_level = level;
+        self
+    }
+
+    /// Also write a JSON Lines record of every file (path, hash algorithm,
+    /// token, size and modification time) to `path`, for auditing exactly
+    /// what a build was made from.
+    pub fn audit_log<P: AsRef<Path>>(mut self, path: P) -> Self {
+        self.audit_log = Some(path.as_ref().to_owned());
+        self
+    }
+
+    /// Spell version tokens using `encoding` instead of URL-safe base64.
+    pub fn token_encoding(mut self, encoding: TokenEncoding) -> Self {
+        self.token_encoding = encoding;
+        self
+    }
+
+    /// Key the SipHash used for tokens, e.g. with a project secret from the
+    /// environment, so tokens can't be predicted from file contents.
+    ///
+    /// The same keys always give the same tokens. Without this, both keys are
+    /// zero. The keys end up in the generated file, as `STATIC_FILE_SIP_KEYS`,
+    /// for [`StaticFilesFairing::sip_keys`](crate::StaticFilesFairing) to pass
+    /// on to `StaticFiles::reload` and `StaticFiles::verify`.
+    pub fn sip_keys(mut self, k0: u64, k1: u64) -> Self {
+        self.sip_keys = (k0, k1);
+        self
+    }
+
+    /// Also compute a Subresource Integrity digest (`sha256-` and the standard
+    /// base64 of the file's SHA-256) for every file, for
+    /// [`StaticFiles::integrity`](crate::StaticFiles::integrity) and the tag
+    /// helpers.
+    ///
+    /// The digest covers the file exactly as it is on disk, so it matches
+    /// `openssl dgst -sha256 -binary <file> | base64`.
+    pub fn integrity(mut self, integrity: bool) -> Self {
+        self.integrity = integrity;
+        self
+    }
+
+    /// Hash text files as if every CRLF were a plain LF, so a checkout with
+    /// Windows line endings gets the same tokens as one without.
+    ///
+    /// Only the tokens change; files are still served (and precompressed, and
+    /// given integrity digests) exactly as they are.
+    pub fn normalize_line_endings(mut self, normalize: bool) -> Self {
+        self.normalize_line_endings = normalize;
+        self
+    }
+
+    /// Embed the contents of every file up to `max_size` bytes in the
+    /// generated map, so they're served from memory. Larger files are still
+    /// read from `serve_from`, as are precompressed siblings.
+    ///
+    /// [`Asset::embedded`](crate::Asset::embedded) records which is which.
+    pub fn embed(mut self, max_size: u64) -> Self {
+        self.embed_max_size = Some(max_size);
+        self
+    }
+
+    /// Embed compressible files gzipped (when that's smaller), keeping only
+    /// the compressed bytes. They're sent as-is to clients accepting gzip and
+    /// decompressed on the fly for the rest, so nothing needs to be on disk.
+    pub fn embed_gzip(mut self, embed_gzip: bool) -> Self {
+        self.embed_gzip = embed_gzip;
+        self
+    }
+
+    /// Also write a JSON manifest of every file's token, size and integrity
+    /// digest into `dir`, for asset pipelines that expect one.
+    ///
+    /// It's named `manifest.json`, or with `hashed`, `manifest-<digest>.json`
+    /// (after the SHA-256 of its contents) like Sprockets and Propshaft do.
+    /// Either way the name is in `STATIC_FILE_MANIFEST` in the generated file.
+    pub fn manifest<P: AsRef<Path>>(mut self, dir: P, hashed: bool) -> Self {
+        self.manifest_dir = Some(dir.as_ref().to_owned());
+        self.manifest_hashed = hashed;
+        self
+    }
+
+    /// Write `STATIC_FILE_HASHES` as a [`SortedMap`](crate::SortedMap)
+    /// instead of a `phf::Map`. Lookups take a binary search rather than a
+    /// hash, but the generated file compiles much faster for very large sets
+    /// of files.
+    pub fn sorted_map(mut self, sorted: bool) -> Self {
+        self.sorted_map = sorted;
+        self
+    }
+
+    /// Print a `cargo:warning` when there are no files at all, which usually
+    /// means the static root is wrong. On by default. An empty map still works;
+    /// every link just goes without a token.
+    pub fn warn_if_empty(mut self, warn: bool) -> Self {
+        self.warn_if_empty = warn;
+        self
+    }
+
+    /// Add `contents` to the map under `path` (relative to `serve_from`, like
+    /// `"css/generated.css"`), for files produced by the build into
+    /// `OUT_DIR` rather than kept under the static root. They're hashed like
+    /// any other file and always embedded, whatever [`Generator::embed`] is
+    /// set to. A file of the same name under the static root is replaced.
+    pub fn virtual_file<P: Into<String>>(mut self, path: P, contents: Vec<u8>) -> Self {
+        let path = path.into().trim_start_matches('/').to_owned();
+        self.virtual_files.push((path, Virtual::Contents(contents)));
+        self
+    }
+
+    /// Add `path` to the map with a version token worked out elsewhere, for
+    /// files that will be in `serve_from` at runtime but aren't under the
+    /// static root at build time. With no contents to go on, the entry has no
+    /// size, integrity digest or precompressed siblings.
+    pub fn virtual_token<P: Into<String>, T: Into<String>>(mut self, path: P, token: T) -> Self {
+        let path = path.into().trim_start_matches('/').to_owned();
+        self.virtual_files
+            .push((path, Virtual::Token(token.into())));
+        self
+    }
+
+    /// What links will start with (`base_href` plus `path_prefix`), so
+    /// [`Generator::max_url_length`] can account for it.
+    pub fn url_prefix<P: Into<String>>(mut self, prefix: P) -> Self {
+        self.url_prefix = prefix.into();
+        self
+    }
+
+    /// Print a cargo warning for any file whose versioned link would be
+    /// longer than this many bytes, since some proxies reject long URLs.
+    /// Defaults to 2000.
+    pub fn max_url_length(mut self, bytes: usize) -> Self {
+        self.max_url_length = bytes;
+        self
+    }
+
+    /// Also hash the dotfiles (and files in dot-directories) matching `globs`,
+    /// like `.well-known/**`. Pass the same list as `serve_dotfiles_allowlist`;
+    /// every other dotfile is skipped, since it would never be served.
+    pub fn dotfiles_allowlist<I, G>(mut self, globs: I) -> Self
+    where
+        I: IntoIterator<Item = G>,
+        G: AsRef<str>,
+    {
+        let globs = globs
+            .into_iter()
+            .map(|g| Glob::new(g.as_ref().trim_start_matches('/')));
+        self.dotfiles_allowlist.extend(globs);
+        self
+    }
+
+    /// Walk the static root and write the generated map to `out_path`.
+    pub fn generate<P: AsRef<Path>>(&self, out_path: P) -> Result<GenStats, Error> {
+        let (files, stats) = self.scan()?;
+
+        if files.is_empty() && self.warn_if_empty {
+            println!("cargo:warning=no static files found; is the static root right?");
+        }
+
+        // Harmless until served from a case-insensitive filesystem, or with
+        // `case_insensitive_lookup`, where only one of them is reachable.
+        for (first, second) in case_collisions(&files) {
+            println!("cargo:warning={} and {} differ only by case", first, second);
+        }
+
+        for (path, len) in self.long_urls(&files) {
+            println!(
+                "cargo:warning=link to {} is {} bytes long (limit {})",
+                path, len, self.max_url_length
+            );
+        }
+
+        let manifest = match &self.manifest_dir {
+            Some(dir) => Some(write_manifest(dir, self.manifest_hashed, &files)?),
+            None => None,
+        };
+
+        write_map(
+            out_path.as_ref(),
+            &files,
+            manifest.as_deref(),
+            self.sorted_map,
+            self.sip_keys,
+        )?;
+
+        if let Some(audit_log) = &self.audit_log {
+            write_audit_log(audit_log, &files)?;
+        }
+
+        Ok(stats)
+    }
+
+    /// The files whose versioned links are longer than `max_url_length`, by
+    /// path, with the length of each link.
+    fn long_urls<'f>(&self, files: &'f HashMap<String, Entry>) -> Vec<(&'f str, usize)> {
+        let mut long: Vec<_> = files
+            .iter()
+            .map(|(path, entry)| {
+                // `<prefix>/<path>?v=<token>`, assuming the default `version_param`.
+                let len = self.url_prefix.len() + 1 + path.len() + 3 + entry.token.len();
+                (path.as_str(), len)
+            })
+            .filter(|(_, len)| *len > self.max_url_length)
+            .collect();
+
+        long.sort();
+        long
+    }
+
+    /// Walk the static root and hash (and compress, if configured) every file
+    /// as [`Generator::generate`] would, but write nothing: no map, manifest,
+    /// audit log or siblings. For checking in CI that generation will succeed.
+    pub fn dry_run(&self) -> Result<GenStats, Error> {
+        self.scan_to(false).map(|(_, stats)| stats)
+    }
+
+    /// Walk the static root, hashing (and possibly precompressing) every file.
+    pub(crate) fn scan(&self) -> Result<(HashMap<String, Entry>, GenStats), Error> {
+        self.scan_to(true)
+    }
+
+    /// [`Generator::scan`], writing precompressed siblings only if `write`.
+    fn scan_to(&self, write: bool) -> Result<(HashMap<String, Entry>, GenStats), Error> {
+        ensure!(
+            zstd::compression_level_range().contains(&self.zstd_level),
+            error::ZstdLevel {
+                level: self.zstd_level
+            }
+        );
+
+        let mut files = HashMap::new();
+        let mut stats = GenStats::default();
+        let mut tokens: HashMap<u64, PathBuf> = HashMap::new();
+
+        let walked = self.source.walk()?;
+        let all: HashSet<_> = walked.iter().cloned().collect();
+
+        // Whatever only looks like a sibling waits for the rest to write
+        // theirs, to see whether it's one of them.
+        let (maybe_siblings, walked): (Vec<_>, Vec<_>) = walked
+            .into_iter()
+            .partition(|path| looks_like_sibling(path, &all));
+        let mut written = HashSet::new();
+
+        for rel_path in walked.iter().chain(&maybe_siblings) {
+            if written.contains(rel_path) || self.is_virtual(rel_path) {
+                continue;
+            }
+
+            let rel_str = rel_path.to_str().with_context(|| error::Unprintable {
+                path: rel_path.to_owned(),
+            })?;
+
+            if lexical::is_dotted(rel_path) && !self.allows_dotfile(rel_str) {
+                continue;
+            }
+
+            let contents = self.source.read(rel_path)?;
+
+            let hash = hash(self.sip_keys, &self.hashed(rel_path, &contents));
+
+            let mut file = Entry {
+                hash,
+                token: self.token_encoding.encode(hash),
+                size: contents.len() as u64,
+                mtime: self.source.modified(rel_path)?,
+                integrity: if self.integrity {
+                    Some(sri(&contents))
+                } else {
+                    None
+                },
+                ..Default::default()
+            };
+
+            self.embed_contents(rel_path, &contents, &mut file, &mut stats)?;
 
-    for entry_res in WalkDir::new(static_root).into_iter() {
-        let entry = entry_res.context(error::WalkDir)?;
-        rerun(entry.path())?;
+            self.check_collision(&mut tokens, file.hash, rel_path, &contents)?;
 
-        if !entry.file_type().is_file() {
-            continue;
+            if let Some(precompress) = self.precompress {
+                let siblings = Siblings { precompress, write };
+                written.extend(
+                    self.write_siblings(rel_path, &contents, siblings, &mut file, &mut stats)?,
+                );
+            }
+
+            stats.record(rel_path, contents.len() as u64);
+
+            files.insert(rel_str.to_owned(), file);
+        }
+
+        for (path, entry) in &self.virtual_files {
+            let file = self.virtual_entry(Path::new(path), entry, &mut stats)?;
+            files.insert(path.clone(), file);
+        }
+
+        Ok((files, stats))
+    }
+
+    /// The map entry for a file added with [`Generator::virtual_file`] or
+    /// [`Generator::virtual_token`].
+    fn virtual_entry(
+        &self,
+        path: &Path,
+        entry: &Virtual,
+        stats: &mut GenStats,
+    ) -> Result<Entry, Error> {
+        let contents = match entry {
+            Virtual::Contents(contents) => contents,
+            Virtual::Token(token) => {
+                stats.record(path, 0);
+                return Ok(Entry {
+                    token: token.clone(),
+                    ..Default::default()
+                });
+            }
+        };
+
+        let hash = hash(self.sip_keys, &self.hashed(path, contents));
+
+        let mut file = Entry {
+            hash,
+            token: self.token_encoding.encode(hash),
+            size: contents.len() as u64,
+            integrity: if self.integrity {
+                Some(sri(contents))
+            } else {
+                None
+            },
+            ..Default::default()
+        };
+
+        self.embed_into(path, contents, &mut file, stats)?;
+
+        stats.record(path, contents.len() as u64);
+        Ok(file)
+    }
+
+    /// Whether the dotfile `path` matches [`Generator::dotfiles_allowlist`].
+    fn allows_dotfile(&self, path: &str) -> bool {
+        self.dotfiles_allowlist.iter().any(|g| g.matches(path))
+    }
+
+    /// Whether `path` was added with [`Generator::virtual_file`] or
+    /// [`Generator::virtual_token`], replacing any file found there.
+    fn is_virtual(&self, path: &Path) -> bool {
+        self.virtual_files.iter().any(|(p, _)| Path::new(p) == path)
+    }
+
+    /// Two files sharing a token is fine when their contents are identical
+    /// (e.g. empty files), but otherwise one of them would silently stop
+    /// busting caches.
+    fn check_collision(
+        &self,
+        tokens: &mut HashMap<u64, PathBuf>,
+        hash: u64,
+        path: &Path,
+        contents: &[u8],
+    ) -> Result<(), Error> {
+        let first = match tokens.get(&hash) {
+            Some(first) => first,
+            None => {
+                tokens.insert(hash, path.to_owned());
+                return Ok(());
+            }
+        };
+
+        let first_contents = self.source.read(first)?;
+
+        if self.hashed(first, &first_contents) != self.hashed(path, contents) {
+            return error::Collision {
+                first: first.clone(),
+                second: path.to_owned(),
+            }
+            .fail();
+        }
+
+        Ok(())
+    }
+
+    /// The bytes the token for `path` is computed over.
+    fn hashed<'c>(&self, path: &Path, contents: &'c [u8]) -> Cow<'c, [u8]> {
+        let text = asset::is_text(&mime_guess::from_path(path).first_or_octet_stream());
+
+        if !self.normalize_line_endings || !text || !contents.contains(&b'\r') {
+            return Cow::Borrowed(contents);
+        }
+
+        let mut normalized = Vec::with_capacity(contents.len());
+        for (i, byte) in contents.iter().enumerate() {
+            if *byte != b'\r' || contents.get(i + 1) != Some(&b'\n') {
+                normalized.push(*byte);
+            }
+        }
+
+        Cow::Owned(normalized)
+    }
+
+    /// Fill in `entry.embedded`, if [`Generator::embed`] covers the file.
+    fn embed_contents(
+        &self,
+        path: &Path,
+        contents: &[u8],
+        entry: &mut Entry,
+        stats: &mut GenStats,
+    ) -> Result<(), Error> {
+        let fits = self
+            .embed_max_size
+            .map(|max| contents.len() as u64 <= max)
+            .unwrap_or(false);
+
+        if !fits {
+            return Ok(());
+        }
+
+        self.embed_into(path, contents, entry, stats)
+    }
+
+    /// Embed `contents` in `entry`, gzipped if [`Generator::embed_gzip`] is
+    /// set and that's smaller.
+    fn embed_into(
+        &self,
+        path: &Path,
+        contents: &[u8],
+        entry: &mut Entry,
+        stats: &mut GenStats,
+    ) -> Result<(), Error> {
+        if self.embed_gzip && compressible(path) {
+            let compressed = stats.compress(Encoding::Gzip, contents, self.zstd_level)?;
+
+            if compressed.len() < contents.len() {
+                entry.gzip = Some(compressed.len() as u64);
+                entry.embedded_size = Some(contents.len() as u64);
+                entry.embedded = Some(compressed);
+                return Ok(());
+            }
         }
 
-        let file_hash = hash(entry.path())?;
-        let rel_path = entry.path().strip_prefix(static_root).unwrap();
-        let rel_str = rel_path.to_str().with_context(|| error::Unprintable {
-            path: rel_path.to_owned(),
-        })?;
+        entry.embedded = Some(contents.to_vec());
+        Ok(())
+    }
+
+    /// Compress `contents` for each of `siblings.precompress`'s encodings,
+    /// returning the paths of the siblings written (or, for a dry run, that
+    /// would have been).
+    fn write_siblings(
+        &self,
+        path: &Path,
+        contents: &[u8],
+        siblings: Siblings,
+        entry: &mut Entry,
+        stats: &mut GenStats,
+    ) -> Result<Vec<PathBuf>, Error> {
+        let mut written = Vec::new();
+
+        if !compressible(path) || (contents.len() as u64) < self.precompress_min_size {
+            return Ok(written);
+        }
+
+        for encoding in siblings.precompress.encodings() {
+            let compressed = stats.compress(*encoding, contents, self.zstd_level)?;
+            if compressed.len() >= contents.len() {
+                continue;
+            }
+
+            let sibling = encoding.sibling(path);
+            if siblings.write {
+                self.source.write(&sibling, &compressed)?;
+            }
+            written.push(sibling);
+
+            let size = Some(compressed.len() as u64);
+            match encoding {
+                Encoding::Gzip => entry.gzip = size,
+                Encoding::Brotli => entry.brotli = size,
+                Encoding::Zstd => entry.zstd = size,
+                Encoding::DictionaryBrotli => (),
+            }
+        }
 
-        files.insert(rel_str.to_owned(), file_hash);
+        Ok(written)
     }
+}
+
+/// Which siblings [`Generator::write_siblings`] compresses, and whether it
+/// writes them out or (for a dry run) only measures them.
+#[derive(Debug, Clone, Copy)]
+struct Siblings {
+    precompress: Precompress,
+    write: bool,
+}
+
+/// Pairs of paths that are equal once lowercased.
+fn case_collisions(files: &HashMap<String, Entry>) -> Vec<(&str, &str)> {
+    let mut sorted: Vec<_> = files.keys().map(String::as_str).collect();
+    sorted.sort();
 
-    let refs: HashMap<_, _> = files.iter().map(|(k, v)| (k.as_str(), v)).collect();
+    let mut seen: HashMap<String, &str> = HashMap::new();
+    let mut collisions = Vec::new();
 
-    let mut map = Map::new();
-    map.phf_path("::rocket_static_files::phf");
-    for (key, value) in refs {
-        let hashed = base64::encode_config(value.to_le_bytes(), base64::URL_SAFE_NO_PAD);
-        map.entry(key, &format!("\"{}\"", hashed));
+    for path in sorted {
+        match seen.get(&path.to_lowercase()) {
+            Some(first) => collisions.push((*first, path)),
+            None => {
+                seen.insert(path.to_lowercase(), path);
+            }
+        }
     }
 
-    let output = map.build();
+    collisions
+}
+
+fn hash((k0, k1): (u64, u64), contents: &[u8]) -> u64 {
+    let mut hasher = SipHasher::new_with_keys(k0, k1);
+    hasher.write(contents);
+    hasher.finish()
+}
+
+fn sri(contents: &[u8]) -> String {
+    let digest = Sha256::digest(contents);
+    format!("sha256-{}", base64::encode(digest))
+}
+
+fn compressible(path: &Path) -> bool {
+    let mime = mime_guess::from_path(path).first_or_octet_stream();
+    asset::is_text(&mime) || mime.essence_str() == "application/wasm"
+}
+
+/// Whether `path` looks like a sibling written by [`Generator::precompress`],
+/// given every file in the source: it's named for a compressible file plus a
+/// compressed extension. Whether it really is one depends on the generator's
+/// settings, so the scan checks again.
+pub(crate) fn looks_like_sibling(path: &Path, files: &HashSet<PathBuf>) -> bool {
+    let ext = match path.extension().and_then(|e| e.to_str()) {
+        Some(e) => e,
+        None => return false,
+    };
+
+    let original = path.with_extension("");
+    let is_compressed = Encoding::PREFERENCE.iter().any(|e| e.extension() == ext);
+    is_compressed && compressible(&original) && files.contains(&original)
+}
 
+/// Drop from a rescan of `serve_from` the siblings an earlier build wrote
+/// there, which the rescan can't tell apart without that build's
+/// [`Generator::precompress`] settings: whatever looks like one, unless
+/// `tracked` says the build hashed it as a file of its own.
+#[cfg(any(feature = "reload", feature = "verify"))]
+pub(crate) fn drop_built_siblings<F>(files: &mut HashMap<String, Entry>, tracked: F)
+where
+    F: Fn(&str) -> bool,
+{
+    let all: HashSet<_> = files.keys().map(PathBuf::from).collect();
+    files.retain(|path, _| tracked(path) || !looks_like_sibling(Path::new(path), &all));
+}
+
+fn compress(encoding: Encoding, contents: &[u8], zstd_level: i32) -> Result<Vec<u8>, Error> {
+    match encoding {
+        Encoding::Gzip => {
+            let compression = flate2::Compression::best();
+            let mut encoder = flate2::write::GzEncoder::new(Vec::new(), compression);
+            encoder.write_all(contents).context(error::Io)?;
+            encoder.finish().context(error::Io)
+        }
+        Encoding::Brotli => {
+            let mut encoder = brotli::CompressorWriter::new(Vec::new(), 4096, 11, 22);
+            encoder.write_all(contents).context(error::Io)?;
+            Ok(encoder.into_inner())
+        }
+        Encoding::Zstd => zstd::stream::encode_all(contents, zstd_level).context(error::Io),
+        // Needs a dictionary, which is for the asset pipeline to pick.
+        Encoding::DictionaryBrotli => unreachable!("no Precompress writes dcb siblings"),
+    }
+}
+
+fn write_map(
+    out_path: &Path,
+    files: &HashMap<String, Entry>,
+    manifest: Option<&str>,
+    sorted: bool,
+    (k0, k1): (u64, u64),
+) -> Result<(), Error> {
     let mut out_file = File::create(out_path).context(error::Io)?;
-    write!(
+
+    writeln!(
+        out_file,
+        "const _: () = ::rocket_static_files::check_schema({});",
+        map::MAP_SCHEMA,
+    )
+    .context(error::Io)?;
+
+    if sorted {
+        let sorted: BTreeMap<_, _> = files.iter().collect();
+
+        let entries: Vec<_> = sorted
+            .into_iter()
+            .map(|(key, value)| format!("({:?}, {})", key, asset_literal(value)))
+            .collect();
+
+        writeln!(
+            out_file,
+            "static STATIC_FILE_HASHES: ::rocket_static_files::SortedMap = ::rocket_static_files::SortedMap::new(&[{}]);",
+            entries.join(", "),
+        )
+        .context(error::Io)?;
+    } else {
+        let mut map = Map::new();
+        map.phf_path("::rocket_static_files::phf");
+        for (key, value) in files {
+            map.entry(key.as_str(), &asset_literal(value));
+        }
+
+        writeln!(
+            out_file,
+            "static STATIC_FILE_HASHES: ::rocket_static_files::phf::Map<&'static str, ::rocket_static_files::Asset> = {};",
+            map.build(),
+        )
+        .context(error::Io)?;
+    }
+
+    let bundle = asset::bundle_hash(files.iter().map(|(k, v)| (k.as_str(), v.token.as_str())));
+    writeln!(
+        out_file,
+        "#[allow(dead_code)]\nstatic STATIC_FILE_BUNDLE_HASH: &str = \"{}\";",
+        bundle,
+    )
+    .context(error::Io)?;
+
+    writeln!(
         out_file,
-        "static STATIC_FILE_HASHES: ::rocket_static_files::phf::Map<&'static str, &'static str> = {};",
-        output,
+        "#[allow(dead_code)]\nstatic STATIC_FILE_SIP_KEYS: (u64, u64) = ({}, {});",
+        k0, k1,
     )
     .context(error::Io)?;
 
+    if let Some(name) = manifest {
+        writeln!(
+            out_file,
+            "#[allow(dead_code)]\nstatic STATIC_FILE_MANIFEST: &str = {:?};",
+            name,
+        )
+        .context(error::Io)?;
+    }
+
     Ok(())
 }
+
+/// The `Asset` expression for `value` in the generated file.
+fn asset_literal(value: &Entry) -> String {
+    format!(
+        "::rocket_static_files::Asset {{ version: \"{}\", gzip: {:?}, brotli: {:?}, zstd: {:?}, integrity: {:?}, embedded: {}, embedded_size: {:?} }}",
+        value.token, value.gzip, value.brotli, value.zstd, value.integrity,
+        match &value.embedded {
+            Some(bytes) => format!("Some({})", byte_literal(bytes)),
+            None => "None".into(),
+        },
+        value.embedded_size,
+    )
+}
+
+/// One file's entry in the manifest written by [`Generator::manifest`].
+#[derive(Debug, Serialize)]
+struct ManifestRecord<'a> {
+    digest: &'a str,
+    size: u64,
+    integrity: Option<&'a str>,
+}
+
+/// Write the manifest into `dir`, returning its file name.
+fn write_manifest(
+    dir: &Path,
+    hashed: bool,
+    files: &HashMap<String, Entry>,
+) -> Result<String, Error> {
+    let records: BTreeMap<_, _> = files
+        .iter()
+        .map(|(path, entry)| {
+            let record = ManifestRecord {
+                digest: &entry.token,
+                size: entry.size,
+                integrity: entry.integrity.as_deref(),
+            };
+
+            (path.as_str(), record)
+        })
+        .collect();
+
+    let contents = serde_json::to_vec_pretty(&records).context(error::Json)?;
+
+    let name = if hashed {
+        let digest = Sha256::digest(&contents);
+        let hex: String = digest.iter().map(|b| format!("{:02x}", b)).collect();
+        format!("manifest-{}.json", hex)
+    } else {
+        "manifest.json".to_owned()
+    };
+
+    std::fs::write(dir.join(&name), contents).context(error::Io)?;
+    Ok(name)
+}
+
+/// `bytes` as a Rust byte string literal.
+fn byte_literal(bytes: &[u8]) -> String {
+    let mut literal = String::with_capacity(bytes.len() + 3);
+    literal.push_str("b\"");
+
+    for byte in bytes {
+        literal.extend(std::ascii::escape_default(*byte).map(char::from));
+    }
+
+    literal.push('"');
+    literal
+}
+
+fn write_audit_log(path: &Path, files: &HashMap<String, Entry>) -> Result<(), Error> {
+    let mut sorted: Vec<_> = files.iter().collect();
+    sorted.sort_by(|a, b| a.0.cmp(b.0));
+
+    let mut out = String::new();
+    for (path, entry) in sorted {
+        let record = AuditRecord {
+            path,
+            algorithm: "siphash-2-4",
+            hash: entry.token.clone(),
+            size: entry.size,
+            mtime: entry.mtime,
+        };
+
+        out.push_str(&serde_json::to_string(&record).context(error::Json)?);
+        out.push('\n');
+    }
+
+    std::fs::write(path, out).context(error::Io)
+}
+
+/// Walk `static_root` and write the generated map to `out_path`.
+///
+/// Shorthand for `Generator::new(static_root).generate(out_path)`.
+pub fn generate(out_path: &Path, static_root: &Path) -> Result<GenStats, Error> {
+    Generator::new(static_root).generate(out_path)
+}
+
+/// Check that every path in `references` (relative to `static_root`, like
+/// `"css/site.css"`) names a file there, so a typo fails the build instead of
+/// a request. Every missing path is listed in the error.
+pub fn verify_references(static_root: &Path, references: &[&str]) -> Result<(), Error> {
+    let walked: HashSet<_> = Filesystem::new(static_root).walk()?.into_iter().collect();
+
+    let missing: Vec<_> = references
+        .iter()
+        .filter(|r| !walked.contains(Path::new(r.trim_start_matches('/'))))
+        .map(|r| r.to_string())
+        .collect();
+
+    if missing.is_empty() {
+        Ok(())
+    } else {
+        error::MissingReferences { paths: missing }.fail()
+    }
+}
+
+/// Walk `static_root` and hash every file, without writing anything.
+///
+/// Shorthand for [`Generator::dry_run`], minus the `cargo:rerun-if-changed`
+/// lines, since this is meant for CI rather than build scripts.
+pub fn generate_dry_run(static_root: &Path) -> Result<GenStats, Error> {
+    Generator::with_source(Filesystem::quiet(static_root)).dry_run()
+}
+
+/// [`generate`], run on Tokio's blocking thread pool so it can be awaited
+/// from async code (say, an admin "rescan" endpoint) without stalling the
+/// executor. Like [`generate_dry_run`], it prints no
+/// `cargo:rerun-if-changed` lines, since it isn't run from a build script.
+#[cfg(feature = "tokio")]
+pub async fn generate_async(out_path: PathBuf, static_root: PathBuf) -> Result<GenStats, Error> {
+    let task = tokio_rt::task::spawn_blocking(move || {
+        Generator::with_source(Filesystem::quiet(&static_root)).generate(&out_path)
+    });
+
+    match task.await {
+        Ok(result) => result,
+        Err(e) => match e.try_into_panic() {
+            Ok(panic) => std::panic::resume_unwind(panic),
+            Err(e) => Err(std::io::Error::new(std::io::ErrorKind::Other, e)).context(error::Io),
+        },
+    }
+}
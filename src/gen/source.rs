@@ -0,0 +1,354 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/. */
+
+use crate::gen::{error, Error};
+
+use snafu::{OptionExt, ResultExt};
+
+use std::cell::RefCell;
+use std::collections::{BTreeMap, HashSet};
+use std::fmt::Debug;
+use std::fs;
+use std::io::{self, Cursor, Read};
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+
+use walkdir::WalkDir;
+
+#[cfg(feature = "zip")]
+use crate::archive;
+#[cfg(feature = "zip")]
+use zip::ZipArchive;
+
+/// Where [`Generator`](crate::Generator) finds the files to hash.
+///
+/// All paths are relative to the root of the source.
+pub trait FileSource: Debug {
+    /// List every file, in any order.
+    fn walk(&self) -> Result<Vec<PathBuf>, Error>;
+
+    /// Open a file returned by [`FileSource::walk`] for reading.
+    fn open(&self, path: &Path) -> Result<Box<dyn Read + '_>, Error>;
+
+    /// Create or replace a file (used for precompressed siblings).
+    fn write(&self, path: &Path, contents: &[u8]) -> Result<(), Error>;
+
+    /// Modification time of a file in seconds since the Unix epoch, if the
+    /// source keeps track of one.
+    fn modified(&self, _path: &Path) -> Result<Option<u64>, Error> {
+        Ok(None)
+    }
+
+    /// Read a whole file into memory.
+    fn read(&self, path: &Path) -> Result<Vec<u8>, Error> {
+        let mut contents = Vec::new();
+        self.open(path)?
+            .read_to_end(&mut contents)
+            .context(error::Io)?;
+        Ok(contents)
+    }
+}
+
+/// The default [`FileSource`]: a directory on disk, optionally overlaid on
+/// others (see [`Filesystem::fallback`]).
+///
+/// Walking also tells cargo to rerun the build script when anything in the
+/// directory changes.
+#[derive(Debug, Clone)]
+pub struct Filesystem {
+    /// `root`, then each fallback, in the order they're searched.
+    roots: Vec<PathBuf>,
+    rerun: bool,
+    max_depth: usize,
+}
+
+impl Filesystem {
+    /// Read files from under `root`.
+    pub fn new<P: AsRef<Path>>(root: P) -> Self {
+        Self {
+            roots: vec![root.as_ref().to_owned()],
+            rerun: true,
+            max_depth: usize::MAX,
+        }
+    }
+
+    /// Only walk `depth` levels below each root: with `1`, just the files
+    /// directly in it; with `2`, those and the ones a directory down; and so
+    /// on. Anything deeper is left out of the map (and doesn't trigger a
+    /// rebuild). Unlimited by default.
+    pub fn max_depth(mut self, depth: usize) -> Self {
+        self.max_depth = depth;
+        self
+    }
+
+    /// Also read files from under `dir`, where they aren't found in the
+    /// directories before it; a theme can override a base this way, matching
+    /// a `serve_from` list with the theme first.
+    pub fn fallback<P: AsRef<Path>>(mut self, dir: P) -> Self {
+        self.roots.push(dir.as_ref().to_owned());
+        self
+    }
+
+    /// Where `path` lives: under the first root that has it, or the first
+    /// root if none do (yet).
+    fn locate(&self, path: &Path) -> PathBuf {
+        self.roots
+            .iter()
+            .map(|root| root.join(path))
+            .find(|full| full.exists())
+            .unwrap_or_else(|| self.roots[0].join(path))
+    }
+
+    /// Read files from under `root` without printing anything for cargo, for
+    /// use outside of build scripts.
+    pub(crate) fn quiet<P: AsRef<Path>>(root: P) -> Self {
+        Self {
+            rerun: false,
+            ..Self::new(root)
+        }
+    }
+}
+
+impl FileSource for Filesystem {
+    fn walk(&self) -> Result<Vec<PathBuf>, Error> {
+        let mut entries = Vec::new();
+        let mut files = Vec::new();
+        let mut seen = HashSet::new();
+
+        for root in &self.roots {
+            for entry_res in WalkDir::new(root).max_depth(self.max_depth) {
+                let entry = entry_res.context(error::WalkDir)?;
+                let rel_path = entry.path().strip_prefix(root).unwrap().to_owned();
+
+                // Overridden by an earlier root.
+                if entry.file_type().is_file() && seen.insert(rel_path.clone()) {
+                    files.push(rel_path.clone());
+                }
+
+                entries.push((entry.into_path(), rel_path));
+            }
+        }
+
+        if !self.rerun {
+            return Ok(files);
+        }
+
+        // Siblings we wrote ourselves must not trigger another build. That
+        // goes for a hand-made `site.css.gz` too, though not `archive.tar.gz`.
+        for (entry, rel_path) in entries {
+            if !super::looks_like_sibling(&rel_path, &seen) {
+                rerun(&entry)?;
+            }
+        }
+
+        Ok(files)
+    }
+
+    fn open(&self, path: &Path) -> Result<Box<dyn Read + '_>, Error> {
+        let file = fs::File::open(self.locate(path)).context(error::Io)?;
+        Ok(Box::new(file))
+    }
+
+    fn modified(&self, path: &Path) -> Result<Option<u64>, Error> {
+        let metadata = fs::metadata(self.locate(path)).context(error::Io)?;
+
+        let secs = metadata
+            .modified()
+            .ok()
+            .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+            .map(|d| d.as_secs());
+
+        Ok(secs)
+    }
+
+    fn write(&self, path: &Path, contents: &[u8]) -> Result<(), Error> {
+        // Siblings go next to their original, in whichever root that is.
+        let original = path.with_extension("");
+        let root = self
+            .roots
+            .iter()
+            .find(|root| root.join(path).exists() || root.join(&original).exists())
+            .unwrap_or(&self.roots[0]);
+        let full = root.join(path);
+
+        // Only touch the file when it changed, so cargo doesn't see a fresh
+        // mtime on every build.
+        if fs::read(&full).ok().as_deref() != Some(contents) {
+            fs::write(&full, contents).context(error::Io)?;
+        }
+
+        Ok(())
+    }
+}
+
+fn rerun(path: &Path) -> Result<(), Error> {
+    let txt = path.to_str().with_context(|| error::Unprintable {
+        path: path.to_owned(),
+    })?;
+
+    println!("cargo:rerun-if-changed={}", txt);
+    Ok(())
+}
+
+/// An in-memory [`FileSource`], mostly useful for testing.
+#[derive(Debug, Default, Clone)]
+pub struct MemorySource {
+    files: RefCell<BTreeMap<PathBuf, Vec<u8>>>,
+}
+
+impl MemorySource {
+    /// Create an empty source.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add (or replace) a file.
+    pub fn insert<P: Into<PathBuf>, C: Into<Vec<u8>>>(&self, path: P, contents: C) {
+        self.files.borrow_mut().insert(path.into(), contents.into());
+    }
+
+    /// The current contents of a file, including any written by the
+    /// generator.
+    pub fn get<P: AsRef<Path>>(&self, path: P) -> Option<Vec<u8>> {
+        self.files.borrow().get(path.as_ref()).cloned()
+    }
+}
+
+impl FileSource for MemorySource {
+    fn walk(&self) -> Result<Vec<PathBuf>, Error> {
+        Ok(self.files.borrow().keys().cloned().collect())
+    }
+
+    fn open(&self, path: &Path) -> Result<Box<dyn Read + '_>, Error> {
+        let contents = self
+            .get(path)
+            .ok_or_else(|| io::Error::from(io::ErrorKind::NotFound))
+            .context(error::Io)?;
+
+        Ok(Box::new(Cursor::new(contents)))
+    }
+
+    fn write(&self, path: &Path, contents: &[u8]) -> Result<(), Error> {
+        self.insert(path, contents);
+        Ok(())
+    }
+}
+
+/// A [`FileSource`] reading the members of a zip archive, for generating the
+/// map when `serve_from` points at the same archive.
+///
+/// The archive is read-only, so [`Generator::precompress`](crate::Generator)
+/// can't be used; put any `.gz`/`.br` siblings in the archive yourself.
+#[cfg(feature = "zip")]
+pub struct ZipSource {
+    archive: RefCell<ZipArchive<fs::File>>,
+}
+
+#[cfg(feature = "zip")]
+impl Debug for ZipSource {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("ZipSource")
+    }
+}
+
+#[cfg(feature = "zip")]
+impl ZipSource {
+    /// Read files from the archive at `path`.
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self, Error> {
+        let file = fs::File::open(path).context(error::Io)?;
+        let archive = ZipArchive::new(file)
+            .map_err(archive::to_io)
+            .context(error::Io)?;
+
+        Ok(Self {
+            archive: RefCell::new(archive),
+        })
+    }
+}
+
+#[cfg(feature = "zip")]
+impl FileSource for ZipSource {
+    fn walk(&self) -> Result<Vec<PathBuf>, Error> {
+        let mut archive = self.archive.borrow_mut();
+        let mut files = Vec::new();
+
+        for index in 0..archive.len() {
+            let member = archive
+                .by_index(index)
+                .map_err(archive::to_io)
+                .context(error::Io)?;
+
+            if !member.name().ends_with('/') {
+                files.push(PathBuf::from(member.name()));
+            }
+        }
+
+        Ok(files)
+    }
+
+    fn open(&self, path: &Path) -> Result<Box<dyn Read + '_>, Error> {
+        let name = path.to_str().with_context(|| error::Unprintable {
+            path: path.to_owned(),
+        })?;
+
+        let mut archive = self.archive.borrow_mut();
+        let mut member = archive
+            .by_name(name)
+            .map_err(archive::to_io)
+            .context(error::Io)?;
+
+        let mut contents = Vec::new();
+        member.read_to_end(&mut contents).context(error::Io)?;
+        Ok(Box::new(Cursor::new(contents)))
+    }
+
+    fn write(&self, _path: &Path, _contents: &[u8]) -> Result<(), Error> {
+        let read_only = io::Error::new(io::ErrorKind::Other, "zip archives are read-only");
+        Err(read_only).context(error::Io)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::Generator;
+
+    #[test]
+    fn memory_source_lists_reads_and_writes_files() {
+        let source = MemorySource::new();
+        source.insert("b.txt", "bee");
+        source.insert("a/c.txt", "sea");
+
+        assert_eq!(
+            source.walk().unwrap(),
+            [PathBuf::from("a/c.txt"), PathBuf::from("b.txt")]
+        );
+        assert_eq!(source.read(Path::new("b.txt")).unwrap(), b"bee");
+        assert!(source.open(Path::new("missing.txt")).is_err());
+        assert_eq!(source.modified(Path::new("b.txt")).unwrap(), None);
+
+        source.write(Path::new("b.txt"), b"buzz").unwrap();
+        assert_eq!(source.get("b.txt").as_deref(), Some(&b"buzz"[..]));
+    }
+
+    #[test]
+    fn generator_hashes_files_from_memory_like_files_on_disk() {
+        let dir = crate::testing::TempDir::new();
+        dir.write("css/site.css", "body {}");
+
+        let source = MemorySource::new();
+        source.insert("css/site.css", "body {}");
+
+        let (on_disk, _) = Generator::with_source(Filesystem::quiet(dir.path()))
+            .scan()
+            .unwrap();
+        let (in_memory, _) = Generator::with_source(source).scan().unwrap();
+
+        assert_eq!(
+            in_memory["css/site.css"].token,
+            on_disk["css/site.css"].token
+        );
+    }
+}
@@ -0,0 +1,707 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/. */
+
+use super::*;
+
+use std::io::Read;
+
+fn stylesheet() -> String {
+    "body { color: red; }\n".repeat(200)
+}
+
+#[test]
+fn precompressed_siblings_decompress_to_the_originals() {
+    let source = MemorySource::new();
+    source.insert("site.css", stylesheet());
+    source.insert("tiny.css", "a {}");
+
+    let generator = Generator::with_source(source).precompress(Precompress::Both);
+    let (files, _) = generator.scan().unwrap();
+
+    let gzip = generator.source.get("site.css.gz").unwrap();
+    let mut gunzipped = String::new();
+    flate2::read::GzDecoder::new(&gzip[..])
+        .read_to_string(&mut gunzipped)
+        .unwrap();
+    assert_eq!(gunzipped, stylesheet());
+
+    let brotli = generator.source.get("site.css.br").unwrap();
+    let mut unbrotlied = String::new();
+    brotli::Decompressor::new(&brotli[..], 4096)
+        .read_to_string(&mut unbrotlied)
+        .unwrap();
+    assert_eq!(unbrotlied, stylesheet());
+
+    let entry = &files["site.css"];
+    assert_eq!(entry.gzip, Some(gzip.len() as u64));
+    assert_eq!(entry.brotli, Some(brotli.len() as u64));
+    assert_eq!(entry.zstd, None);
+
+    // Under `precompress_min_size`.
+    assert_eq!(generator.source.get("tiny.css.gz"), None);
+    assert_eq!(files["tiny.css"].gzip, None);
+}
+
+#[test]
+fn zstd_siblings_use_the_configured_level() {
+    let source = MemorySource::new();
+    source.insert("site.css", stylesheet());
+
+    let generator = Generator::with_source(source)
+        .precompress(Precompress::Zstd)
+        .zstd_level(3);
+    let (files, _) = generator.scan().unwrap();
+
+    let zstd = generator.source.get("site.css.zst").unwrap();
+    assert_eq!(
+        zstd::decode_all(&zstd[..]).unwrap(),
+        stylesheet().as_bytes()
+    );
+    assert_eq!(files["site.css"].zstd, Some(zstd.len() as u64));
+
+    let generator = generator.zstd_level(1000);
+    match generator.dry_run() {
+        Err(Error::ZstdLevel { level }) => assert_eq!(level, 1000),
+        other => panic!("expected a bad level, got {:?}", other),
+    }
+}
+
+#[test]
+fn siblings_are_not_hashed_as_files_of_their_own() {
+    let source = MemorySource::new();
+    source.insert("site.css", stylesheet());
+
+    let generator = Generator::with_source(source).precompress(Precompress::Gzip);
+    generator.scan().unwrap();
+
+    // A second run sees `site.css.gz` on the source, and skips it.
+    let (files, _) = generator.scan().unwrap();
+    assert_eq!(files.keys().collect::<Vec<_>>(), ["site.css"]);
+}
+
+#[test]
+fn files_that_only_look_like_siblings_are_hashed() {
+    let source = MemorySource::new();
+    source.insert("archive.tar", "tar");
+    source.insert("archive.tar.gz", "tar.gz");
+    source.insert("site.css", stylesheet());
+    source.insert("site.css.br", "by hand");
+
+    // Without precompression, nothing is a sibling.
+    let generator = Generator::with_source(source);
+    let (files, _) = generator.scan().unwrap();
+    let mut paths: Vec<_> = files.keys().map(String::as_str).collect();
+    paths.sort();
+    assert_eq!(
+        paths,
+        ["archive.tar", "archive.tar.gz", "site.css", "site.css.br"]
+    );
+
+    // With it, only the ones written are.
+    let generator = generator.precompress(Precompress::Gzip);
+    let (files, _) = generator.scan().unwrap();
+    let mut paths: Vec<_> = files.keys().map(String::as_str).collect();
+    paths.sort();
+    assert_eq!(
+        paths,
+        ["archive.tar", "archive.tar.gz", "site.css", "site.css.br"]
+    );
+    assert!(generator.source.get("site.css.gz").is_some());
+    assert_ne!(files["archive.tar.gz"].token, files["archive.tar"].token);
+}
+
+#[test]
+fn stats_break_sizes_down_by_extension() {
+    let source = MemorySource::new();
+    source.insert("app.js", "0123456789");
+    source.insert("vendor/lib.JS", "01234");
+    source.insert("site.css", "0123456");
+    source.insert("LICENSE", "012");
+
+    let stats = Generator::with_source(source).dry_run().unwrap();
+
+    assert_eq!(stats.files, 4);
+    assert_eq!(stats.total_bytes, 25);
+    assert_eq!(stats.bytes_by_extension.len(), 3);
+    assert_eq!(stats.bytes_by_extension["js"], 15);
+    assert_eq!(stats.bytes_by_extension["css"], 7);
+    assert_eq!(stats.bytes_by_extension[""], 3);
+}
+
+#[test]
+fn a_shared_hash_over_different_contents_is_an_error() {
+    let source = MemorySource::new();
+    source.insert("a.txt", "one");
+    source.insert("b.txt", "two");
+    source.insert("c.txt", "one");
+    let generator = Generator::with_source(source);
+
+    // Real collisions are too rare to find, so file all three under one
+    // hash by hand.
+    let mut tokens = HashMap::new();
+    generator
+        .check_collision(&mut tokens, 42, Path::new("a.txt"), b"one")
+        .unwrap();
+
+    // The same bytes at a second path is fine.
+    generator
+        .check_collision(&mut tokens, 42, Path::new("c.txt"), b"one")
+        .unwrap();
+
+    match generator.check_collision(&mut tokens, 42, Path::new("b.txt"), b"two") {
+        Err(Error::Collision { first, second }) => {
+            assert_eq!(first, Path::new("a.txt"));
+            assert_eq!(second, Path::new("b.txt"));
+        }
+        other => panic!("expected a collision, got {:?}", other),
+    }
+}
+
+#[test]
+fn audit_log_has_a_line_per_file() {
+    let dir = crate::testing::TempDir::new();
+    let source = MemorySource::new();
+    source.insert("b.css", "bee");
+    source.insert("a.js", "ay");
+
+    let audit_log = dir.path().join("audit.jsonl");
+    let generator = Generator::with_source(source).audit_log(&audit_log);
+    generator.generate(dir.path().join("map.rs")).unwrap();
+    let (files, _) = generator.scan().unwrap();
+
+    let log = std::fs::read_to_string(&audit_log).unwrap();
+    let lines: Vec<serde_json::Value> = log
+        .lines()
+        .map(|line| serde_json::from_str(line).unwrap())
+        .collect();
+
+    assert_eq!(lines.len(), 2);
+    assert_eq!(lines[0]["path"], "a.js");
+    assert_eq!(lines[0]["algorithm"], "siphash-2-4");
+    assert_eq!(lines[0]["hash"], files["a.js"].token.as_str());
+    assert_eq!(lines[0]["size"], 2);
+    assert_eq!(lines[0]["mtime"], serde_json::Value::Null);
+    assert_eq!(lines[1]["path"], "b.css");
+    assert_eq!(lines[1]["size"], 3);
+}
+
+#[test]
+fn token_encodings_spell_the_same_hash() {
+    assert_eq!(TokenEncoding::Base64Url.encode(1), "AQAAAAAAAAA");
+    assert_eq!(TokenEncoding::Base64.encode(1), "AQAAAAAAAAA=");
+    assert_eq!(TokenEncoding::Hex.encode(1), "0100000000000000");
+}
+
+#[test]
+fn hex_tokens_end_up_in_the_map() {
+    let dir = crate::testing::TempDir::new();
+    let source = MemorySource::new();
+    source.insert("app.js", "app");
+
+    let generator = Generator::with_source(source).token_encoding(TokenEncoding::Hex);
+    let (files, _) = generator.scan().unwrap();
+    let token = &files["app.js"].token;
+    assert_eq!(token.len(), 16);
+    assert!(token.bytes().all(|b| b.is_ascii_hexdigit()), "{}", token);
+    assert_eq!(*token, TokenEncoding::Hex.encode(files["app.js"].hash));
+
+    let out = dir.path().join("map.rs");
+    generator.generate(&out).unwrap();
+    let map = std::fs::read_to_string(&out).unwrap();
+    assert!(map.contains(&format!("version: \"{}\"", token)), "{}", map);
+}
+
+#[test]
+fn warns_about_links_over_the_url_length_limit() {
+    let source = MemorySource::new();
+    source.insert("short.js", "a");
+    source.insert(format!("{}.js", "long".repeat(10)), "b");
+
+    let generator = Generator::with_source(source)
+        .url_prefix("/static")
+        .max_url_length(40);
+    let (files, _) = generator.scan().unwrap();
+
+    // "/static" + "/" + 43 + "?v=" + 11
+    let long_name = format!("{}.js", "long".repeat(10));
+    assert_eq!(generator.long_urls(&files), [(long_name.as_str(), 65)]);
+}
+
+#[test]
+fn sip_keys_change_tokens_reproducibly() {
+    let source = MemorySource::new();
+    source.insert("app.js", "app");
+
+    let token = |keys: (u64, u64)| {
+        let (files, _) = Generator::with_source(source.clone())
+            .sip_keys(keys.0, keys.1)
+            .scan()
+            .unwrap();
+        files["app.js"].token.clone()
+    };
+
+    assert_eq!(token((1, 2)), token((1, 2)));
+    assert_ne!(token((1, 2)), token((0, 0)));
+    assert_ne!(token((1, 2)), token((2, 1)));
+}
+
+#[test]
+fn the_generated_file_records_the_sip_keys() {
+    let dir = crate::testing::TempDir::new();
+    let source = MemorySource::new();
+    source.insert("app.js", "app");
+
+    let out = dir.path().join("map.rs");
+    Generator::with_source(source)
+        .sip_keys(7, 11)
+        .generate(&out)
+        .unwrap();
+
+    let map = std::fs::read_to_string(&out).unwrap();
+    assert!(map.contains("static STATIC_FILE_SIP_KEYS: (u64, u64) = (7, 11);\n"));
+}
+
+#[test]
+fn integrity_digests_match_sha256sum() {
+    let source = MemorySource::new();
+    source.insert("app.js", "alert(1);\n");
+
+    let generator = Generator::with_source(source);
+    let (files, _) = generator.scan().unwrap();
+    assert_eq!(files["app.js"].integrity, None);
+
+    let (files, _) = generator.integrity(true).scan().unwrap();
+
+    // `sha256sum app.js` gives bf25b19d...ac06fb; these are the same 32
+    // bytes, from `openssl dgst -sha256 -binary app.js | base64`.
+    assert_eq!(
+        files["app.js"].integrity.as_deref(),
+        Some("sha256-vyWxnR5/SfkpBXTjK7tyUvoeEZCT+fK2ayxsse+sBvs=")
+    );
+}
+
+#[test]
+fn normalized_line_endings_give_crlf_and_lf_the_same_token() {
+    let source = MemorySource::new();
+    source.insert("unix.txt", "one\ntwo\n");
+    source.insert("windows.txt", "one\r\ntwo\r\n");
+    source.insert("unix.png", "one\ntwo\n");
+    source.insert("windows.png", "one\r\ntwo\r\n");
+
+    let generator = Generator::with_source(source);
+    let (files, _) = generator.scan().unwrap();
+    assert_ne!(files["unix.txt"].token, files["windows.txt"].token);
+
+    let (files, _) = generator.normalize_line_endings(true).scan().unwrap();
+    assert_eq!(files["unix.txt"].token, files["windows.txt"].token);
+
+    // Binary files are hashed byte for byte.
+    assert_ne!(files["unix.png"].token, files["windows.png"].token);
+}
+
+#[cfg(feature = "tokio")]
+#[test]
+fn generate_async_writes_the_map_from_a_runtime() {
+    let dir = crate::testing::TempDir::new();
+    dir.write("static/app.js", "app");
+    let out = dir.path().join("static_file_hashes.rs");
+
+    let runtime = tokio_rt::runtime::Builder::new_current_thread()
+        .build()
+        .unwrap();
+    let stats = runtime
+        .block_on(generate_async(out.clone(), dir.path().join("static")))
+        .unwrap();
+    assert_eq!(stats.files, 1);
+
+    let generated = std::fs::read_to_string(&out).unwrap();
+    assert!(generated.contains("\"app.js\""), "{}", generated);
+}
+
+#[test]
+fn dotfiles_are_skipped_unless_allowed() {
+    let source = MemorySource::new();
+    source.insert(".env", "SECRET=1");
+    source.insert(".git/config", "[core]");
+    source.insert(".well-known/security.txt", "Contact: me");
+    source.insert("app.js", "app");
+
+    let generator = Generator::with_source(source);
+    let (files, _) = generator.scan().unwrap();
+    assert_eq!(files.keys().collect::<Vec<_>>(), ["app.js"]);
+
+    let (files, _) = generator
+        .dotfiles_allowlist(&["/.well-known/**"])
+        .scan()
+        .unwrap();
+    let mut paths: Vec<_> = files.keys().collect();
+    paths.sort();
+    assert_eq!(paths, [".well-known/security.txt", "app.js"]);
+}
+
+#[test]
+fn the_bundle_hash_changes_with_any_file() {
+    let dir = crate::testing::TempDir::new();
+    let out = dir.path().join("static_file_hashes.rs");
+
+    let bundle_hash = |files: &[(&str, &str)]| {
+        let source = MemorySource::new();
+        for (path, contents) in files {
+            source.insert(*path, *contents);
+        }
+
+        Generator::with_source(source).generate(&out).unwrap();
+        let generated = std::fs::read_to_string(&out).unwrap();
+        let line = generated
+            .lines()
+            .find(|l| l.starts_with("static STATIC_FILE_BUNDLE_HASH: &str = \""))
+            .map(str::to_owned);
+        line.unwrap_or_else(|| panic!("no bundle hash in {}", generated))
+    };
+
+    let original = bundle_hash(&[("a.css", "a"), ("b.js", "b")]);
+    assert_eq!(bundle_hash(&[("b.js", "b"), ("a.css", "a")]), original);
+    assert_ne!(bundle_hash(&[("a.css", "a"), ("b.js", "c")]), original);
+    assert_ne!(bundle_hash(&[("a.css", "a"), ("c.js", "b")]), original);
+    assert_ne!(
+        bundle_hash(&[("a.css", "a"), ("b.js", "b"), ("c.txt", "")]),
+        original
+    );
+}
+
+#[test]
+fn hashed_manifests_are_named_after_their_contents() {
+    let dir = crate::testing::TempDir::new();
+    let out = dir.path().join("static_file_hashes.rs");
+
+    let source = MemorySource::new();
+    source.insert("app.js", "alert(1);\n");
+    Generator::with_source(source)
+        .manifest(dir.path(), true)
+        .generate(&out)
+        .unwrap();
+
+    let names: Vec<_> = std::fs::read_dir(dir.path())
+        .unwrap()
+        .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+        .filter(|name| name.starts_with("manifest"))
+        .collect();
+    assert_eq!(names.len(), 1, "{:?}", names);
+
+    let contents = std::fs::read(dir.path().join(&names[0])).unwrap();
+    let hex: String = Sha256::digest(&contents)
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect();
+    assert_eq!(names[0], format!("manifest-{}.json", hex));
+
+    let records: serde_json::Value = serde_json::from_slice(&contents).unwrap();
+    assert_eq!(records["app.js"]["size"], 10);
+
+    let generated = std::fs::read_to_string(&out).unwrap();
+    let line = format!("static STATIC_FILE_MANIFEST: &str = {:?};\n", names[0]);
+    assert!(generated.ends_with(&line), "{}", generated);
+}
+
+#[test]
+fn unhashed_manifests_keep_their_name() {
+    let dir = crate::testing::TempDir::new();
+    let out = dir.path().join("static_file_hashes.rs");
+
+    let source = MemorySource::new();
+    source.insert("app.js", "alert(1);\n");
+    Generator::with_source(source)
+        .manifest(dir.path(), false)
+        .generate(&out)
+        .unwrap();
+
+    assert!(dir.path().join("manifest.json").is_file());
+    let generated = std::fs::read_to_string(&out).unwrap();
+    assert!(generated.ends_with("static STATIC_FILE_MANIFEST: &str = \"manifest.json\";\n"));
+}
+
+#[test]
+fn embeds_files_up_to_the_threshold() {
+    let source = MemorySource::new();
+    source.insert("small.txt", "tiny");
+    source.insert("exact.txt", "8 bytes!");
+    source.insert("large.txt", "nine byte");
+
+    let (files, _) = Generator::with_source(source).embed(8).scan().unwrap();
+    assert_eq!(files["small.txt"].embedded.as_deref(), Some(&b"tiny"[..]));
+    assert_eq!(
+        files["exact.txt"].embedded.as_deref(),
+        Some(&b"8 bytes!"[..])
+    );
+    assert_eq!(files["large.txt"].embedded, None);
+
+    // Nothing is embedded without a threshold.
+    let source = MemorySource::new();
+    source.insert("small.txt", "tiny");
+    let (files, _) = Generator::with_source(source).scan().unwrap();
+    assert_eq!(files["small.txt"].embedded, None);
+}
+
+#[test]
+fn embedded_bytes_are_escaped_in_the_generated_file() {
+    assert_eq!(byte_literal(b"a\"\\\n\xff"), r#"b"a\"\\\n\xff""#);
+}
+
+#[test]
+fn embed_gzip_keeps_only_the_compressed_bytes() {
+    let source = MemorySource::new();
+    source.insert("site.css", stylesheet());
+    source.insert("tiny.css", "a{}");
+    source.insert("logo.png", stylesheet());
+
+    let (files, _) = Generator::with_source(source)
+        .embed(1 << 20)
+        .embed_gzip(true)
+        .scan()
+        .unwrap();
+
+    let site = &files["site.css"];
+    let embedded = site.embedded.as_ref().unwrap();
+    assert_eq!(site.embedded_size, Some(stylesheet().len() as u64));
+    assert_eq!(site.gzip, Some(embedded.len() as u64));
+    let mut gunzipped = String::new();
+    flate2::read::GzDecoder::new(&embedded[..])
+        .read_to_string(&mut gunzipped)
+        .unwrap();
+    assert_eq!(gunzipped, stylesheet());
+
+    // Kept as they are when gzip wouldn't help, or the type doesn't compress.
+    assert_eq!(files["tiny.css"].embedded.as_deref(), Some(&b"a{}"[..]));
+    assert_eq!(files["tiny.css"].embedded_size, None);
+    assert_eq!(files["logo.png"].embedded_size, None);
+    assert_eq!(
+        files["logo.png"].embedded.as_deref(),
+        Some(stylesheet().as_bytes())
+    );
+}
+
+#[test]
+fn stats_count_the_work_done_compressing() {
+    let source = MemorySource::new();
+    source.insert("site.css", stylesheet());
+    source.insert("tiny.css", "a {}");
+    source.insert("logo.png", stylesheet());
+
+    let generator = Generator::with_source(source)
+        .precompress(Precompress::Both)
+        .precompress_min_size(0);
+    let (files, stats) = generator.scan().unwrap();
+
+    let mut encodings: Vec<_> = stats.compression.keys().copied().collect();
+    encodings.sort();
+    assert_eq!(encodings, ["br", "gzip"]);
+
+    let total_in = (stylesheet().len() + "a {}".len()) as u64;
+    for (encoding, sibling) in &[
+        ("gzip", files["site.css"].gzip),
+        ("br", files["site.css"].brotli),
+    ] {
+        let counted = &stats.compression[encoding];
+
+        // Counted even when the result was dropped for not being smaller, but
+        // never for types that aren't compressed at all.
+        assert_eq!(counted.files, 2, "{}", encoding);
+        assert_eq!(counted.bytes_in, total_in, "{}", encoding);
+        assert!(counted.bytes_out > sibling.unwrap(), "{}", encoding);
+        assert!(counted.bytes_out < counted.bytes_in, "{}", encoding);
+    }
+}
+
+#[test]
+fn verify_references_lists_every_missing_path() {
+    let dir = crate::testing::TempDir::new();
+    dir.write("css/site.css", "body {}");
+
+    verify_references(dir.path(), &["css/site.css", "/css/site.css"]).unwrap();
+
+    match verify_references(
+        dir.path(),
+        &["css/site.css", "js/app.js", "css", "css/Site.css"],
+    ) {
+        Err(Error::MissingReferences { paths }) => {
+            assert_eq!(paths, ["js/app.js", "css", "css/Site.css"])
+        }
+        other => panic!("expected MissingReferences, got {:?}", other),
+    }
+}
+
+#[test]
+fn sorted_maps_are_written_in_path_order() {
+    let dir = crate::testing::TempDir::new();
+    let out = dir.path().join("static_file_hashes.rs");
+
+    let source = MemorySource::new();
+    source.insert("z.txt", "z");
+    source.insert("a.css", "a");
+    source.insert("js/app.js", "app");
+    Generator::with_source(source)
+        .sorted_map(true)
+        .generate(&out)
+        .unwrap();
+
+    let generated = std::fs::read_to_string(&out).unwrap();
+    assert!(generated.contains("::rocket_static_files::SortedMap::new(&[(\"a.css\", "));
+    let positions: Vec<_> = ["\"a.css\"", "\"js/app.js\"", "\"z.txt\""]
+        .iter()
+        .map(|path| generated.find(path).unwrap())
+        .collect();
+    assert!(positions.windows(2).all(|w| w[0] < w[1]), "{}", generated);
+    assert!(!generated.contains("phf"), "{}", generated);
+}
+
+#[test]
+fn generated_files_start_with_the_schema_check() {
+    let dir = crate::testing::TempDir::new();
+    let out = dir.path().join("static_file_hashes.rs");
+
+    Generator::with_source(MemorySource::new())
+        .warn_if_empty(false)
+        .generate(&out)
+        .unwrap();
+
+    let generated = std::fs::read_to_string(&out).unwrap();
+    let check = format!(
+        "const _: () = ::rocket_static_files::check_schema({});\n",
+        map::MAP_SCHEMA
+    );
+    assert!(generated.starts_with(&check), "{}", generated);
+}
+
+fn listing(dir: &Path) -> Vec<PathBuf> {
+    let mut paths: Vec<_> = walkdir::WalkDir::new(dir)
+        .into_iter()
+        .map(|entry| entry.unwrap().into_path())
+        .collect();
+    paths.sort();
+    paths
+}
+
+#[test]
+fn dry_runs_report_stats_and_write_nothing() {
+    let dir = crate::testing::TempDir::new();
+    dir.write("app.js", "0123456789");
+    dir.write("css/site.css", stylesheet());
+    let before = listing(dir.path());
+
+    let stats = generate_dry_run(dir.path()).unwrap();
+    assert_eq!(stats.files, 2);
+    assert_eq!(stats.total_bytes, 10 + stylesheet().len() as u64);
+
+    let stats = Generator::new(dir.path())
+        .precompress(Precompress::All)
+        .manifest(dir.path(), false)
+        .dry_run()
+        .unwrap();
+    assert_eq!(stats.files, 2);
+    assert!(!stats.compression.is_empty());
+
+    assert_eq!(listing(dir.path()), before);
+}
+
+#[test]
+#[cfg(unix)]
+fn dry_runs_catch_names_that_are_not_utf8() {
+    use std::ffi::OsStr;
+    use std::os::unix::ffi::OsStrExt;
+
+    let dir = crate::testing::TempDir::new();
+    dir.write(OsStr::from_bytes(b"caf\xe9.css"), "body {}");
+
+    match generate_dry_run(dir.path()) {
+        Err(Error::Unprintable { .. }) => (),
+        other => panic!("expected Unprintable, got {:?}", other),
+    }
+}
+
+#[test]
+fn empty_static_roots_give_an_empty_map() {
+    let dir = crate::testing::TempDir::new();
+    let out = dir.path().join("static_file_hashes.rs");
+    std::fs::create_dir(dir.path().join("static")).unwrap();
+
+    let stats = generate(&out, &dir.path().join("static")).unwrap();
+    assert_eq!(stats.files, 0);
+
+    let generated = std::fs::read_to_string(&out).unwrap();
+    assert!(generated.contains("entries: ::rocket_static_files::phf::Slice::Static(&["));
+    assert!(
+        !generated.contains("::rocket_static_files::Asset {"),
+        "{}",
+        generated
+    );
+}
+
+#[test]
+fn fallback_directories_fill_in_what_the_theme_lacks() {
+    let theme = crate::testing::TempDir::new();
+    theme.write("site.css", "theme");
+    let base = crate::testing::TempDir::new();
+    base.write("site.css", "base");
+    base.write("app.js", "app");
+
+    let (files, _) = Generator::with_source(Filesystem::quiet(theme.path()).fallback(base.path()))
+        .scan()
+        .unwrap();
+    let (themed, _) = Generator::with_source(Filesystem::quiet(theme.path()))
+        .scan()
+        .unwrap();
+
+    assert_eq!(files.len(), 2);
+    assert_eq!(files["site.css"].token, themed["site.css"].token);
+    assert_eq!(files["site.css"].size, 5);
+    assert_eq!(files["app.js"].size, 3);
+}
+
+#[test]
+fn virtual_files_join_the_map() {
+    let source = MemorySource::new();
+    source.insert("app.js", "app");
+    source.insert("css/generated.css", "stale");
+    let (files, _) = Generator::with_source(source)
+        .virtual_file("/css/generated.css", b"body {}".to_vec())
+        .virtual_token("runtime.js", "abc123")
+        .scan()
+        .unwrap();
+
+    // Hashed as if it had been under the static root all along.
+    let real = MemorySource::new();
+    real.insert("css/generated.css", "body {}");
+    let (expected, _) = Generator::with_source(real).scan().unwrap();
+
+    let generated = &files["css/generated.css"];
+    assert_eq!(generated.token, expected["css/generated.css"].token);
+    assert_eq!(generated.size, 7);
+    assert_eq!(generated.embedded.as_deref(), Some(&b"body {}"[..]));
+
+    assert_eq!(files["runtime.js"].token, "abc123");
+    assert_eq!(files["runtime.js"].embedded, None);
+    assert_eq!(files["app.js"].embedded, None);
+    assert_eq!(files.len(), 3);
+}
+
+#[test]
+fn max_depth_leaves_deeper_files_out_of_the_map() {
+    let dir = crate::testing::TempDir::new();
+    dir.write("app.css", "top");
+    dir.write("css/site.css", "one down");
+    dir.write("css/vendor/lib.css", "two down");
+
+    let keys = |depth| {
+        let (files, _) = Generator::with_source(Filesystem::quiet(dir.path()).max_depth(depth))
+            .scan()
+            .unwrap();
+        let mut keys: Vec<_> = files.keys().cloned().collect();
+        keys.sort();
+        keys
+    };
+
+    assert_eq!(keys(1), ["app.css"]);
+    assert_eq!(keys(2), ["app.css", "css/site.css"]);
+    assert_eq!(keys(3), ["app.css", "css/site.css", "css/vendor/lib.css"]);
+}
@@ -0,0 +1,81 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/. */
+
+/// A simple path glob.
+///
+/// `*` matches anything except `/`, `**` matches anything at all (so
+/// `fonts/**/*.woff2` also matches `fonts/a.woff2`), and `?` matches any single
+/// character except `/`. Everything else matches itself.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct Glob {
+    pattern: String,
+}
+
+impl Glob {
+    pub(crate) fn new<S: Into<String>>(pattern: S) -> Self {
+        Self {
+            pattern: pattern.into(),
+        }
+    }
+
+    pub(crate) fn matches(&self, text: &str) -> bool {
+        matches(self.pattern.as_bytes(), text.as_bytes())
+    }
+}
+
+fn matches(pattern: &[u8], text: &[u8]) -> bool {
+    match pattern {
+        [] => text.is_empty(),
+        [b'*', b'*', rest @ ..] => {
+            if let [b'/', after @ ..] = rest {
+                if matches(after, text) {
+                    return true;
+                }
+            }
+
+            (0..=text.len()).any(|i| matches(rest, &text[i..]))
+        }
+        [b'*', rest @ ..] => {
+            for i in 0..=text.len() {
+                if matches(rest, &text[i..]) {
+                    return true;
+                }
+
+                if text.get(i) == Some(&b'/') {
+                    break;
+                }
+            }
+
+            false
+        }
+        [b'?', rest @ ..] => match text {
+            [c, tail @ ..] if *c != b'/' => matches(rest, tail),
+            _ => false,
+        },
+        [p, rest @ ..] => match text {
+            [c, tail @ ..] if c == p => matches(rest, tail),
+            _ => false,
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn stars_stop_at_slashes_unless_doubled() {
+        let glob = |pattern: &str, text: &str| Glob::new(pattern).matches(text);
+
+        assert!(glob("fonts/*.woff2", "fonts/a.woff2"));
+        assert!(!glob("fonts/*.woff2", "fonts/sub/a.woff2"));
+        assert!(!glob("fonts/*.woff2", "fonts/a.woff"));
+        assert!(glob("fonts/**/*.woff2", "fonts/a.woff2"));
+        assert!(glob("fonts/**/*.woff2", "fonts/sub/deeper/a.woff2"));
+        assert!(glob("**", "anything/at/all"));
+        assert!(glob("app.?s", "app.js"));
+        assert!(!glob("app?js", "app/js"));
+        assert!(!glob("app.js", "app.jsx"));
+    }
+}
@@ -0,0 +1,116 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/. */
+
+use crate::glob::Glob;
+
+use rocket::http::Header;
+
+/// Name of the sidecar file read from the root of `serve_from`.
+pub(crate) const FILE_NAME: &str = "_headers";
+
+/// Extra response headers from a Netlify-style `_headers` file:
+///
+/// ```text
+/// # Comments and blank lines are ignored.
+/// /fonts/*
+///   Access-Control-Allow-Origin: *
+///
+/// /index.html
+///   Link: </app.css>; rel=preload; as=style
+/// ```
+///
+/// Paths are relative to `path_prefix` and matched as [`Glob`]s. Every
+/// matching rule applies, in file order.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct HeaderRules {
+    rules: Vec<(Glob, Vec<(String, String)>)>,
+}
+
+impl HeaderRules {
+    pub(crate) fn parse(text: &str) -> Result<Self, String> {
+        let mut rules: Vec<(Glob, Vec<_>)> = Vec::new();
+
+        for (number, line) in text.lines().enumerate() {
+            let trimmed = line.trim();
+            if trimmed.is_empty() || trimmed.starts_with('#') {
+                continue;
+            }
+
+            if !line.starts_with(char::is_whitespace) {
+                let pattern = trimmed.trim_start_matches('/');
+                rules.push((Glob::new(pattern), Vec::new()));
+                continue;
+            }
+
+            let headers = match rules.last_mut() {
+                Some((_, headers)) => headers,
+                None => return Err(format!("line {}: header before any path", number + 1)),
+            };
+
+            let mut parts = trimmed.splitn(2, ':');
+            let name = parts.next().unwrap_or_default().trim();
+            let value = match parts.next() {
+                Some(value) if !name.is_empty() => value.trim(),
+                _ => return Err(format!("line {}: expected `Name: value`", number + 1)),
+            };
+
+            headers.push((name.to_owned(), value.to_owned()));
+        }
+
+        Ok(Self { rules })
+    }
+
+    /// Headers for the file at `path`, relative to `serve_from`.
+    pub(crate) fn matching<'a>(
+        &'a self,
+        path: &'a str,
+    ) -> impl Iterator<Item = Header<'static>> + 'a {
+        self.rules
+            .iter()
+            .filter(move |(glob, _)| glob.matches(path))
+            .flat_map(|(_, headers)| headers)
+            .map(|(name, value)| Header::new(name.clone(), value.clone()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RULES: &str = "\
+# Fonts are fetched cross-origin.
+/fonts/*
+  Access-Control-Allow-Origin: *
+
+/fonts/icons.woff2
+  X-Robots-Tag: noindex
+";
+
+    fn matching(rules: &HeaderRules, path: &str) -> Vec<(String, String)> {
+        rules
+            .matching(path)
+            .map(|h| (h.name().to_string(), h.value().to_owned()))
+            .collect()
+    }
+
+    #[test]
+    fn every_matching_rule_applies_in_order() {
+        let rules = HeaderRules::parse(RULES).unwrap();
+
+        assert_eq!(
+            matching(&rules, "fonts/icons.woff2"),
+            [
+                ("Access-Control-Allow-Origin".to_owned(), "*".to_owned()),
+                ("X-Robots-Tag".to_owned(), "noindex".to_owned()),
+            ]
+        );
+        assert!(matching(&rules, "css/site.css").is_empty());
+    }
+
+    #[test]
+    fn rejects_headers_outside_a_rule() {
+        assert!(HeaderRules::parse("  X-Early: yes\n").is_err());
+        assert!(HeaderRules::parse("/a\n  no colon\n").is_err());
+    }
+}
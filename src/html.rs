@@ -0,0 +1,66 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/. */
+
+/// Escape `text` for use inside a double-quoted HTML attribute.
+pub(crate) fn escape_attr(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#x27;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            c => escaped.push(c),
+        }
+    }
+
+    escaped
+}
+
+/// Render ` integrity="..." crossorigin="anonymous"`, or nothing.
+pub(crate) fn integrity_attrs(integrity: Option<&str>) -> String {
+    match integrity {
+        Some(digest) => format!(
+            " integrity=\"{}\" crossorigin=\"anonymous\"",
+            escape_attr(digest)
+        ),
+        None => String::new(),
+    }
+}
+
+/// Replace every quoted attribute value in `html` that links under `prefix`
+/// (without a query string or fragment) with `to` of the rest of the link,
+/// which starts with a `/`.
+pub(crate) fn rewrite_links<F: Fn(&str) -> String>(html: &str, prefix: &str, to: F) -> String {
+    let base = prefix.trim_end_matches('/');
+    let needle = format!("{}/", base);
+
+    let mut out = String::with_capacity(html.len());
+    let mut rest = html;
+
+    while let Some(start) = rest.find(&needle) {
+        let quote = rest[..start]
+            .chars()
+            .last()
+            .filter(|c| *c == '"' || *c == '\'');
+        let end = quote.and_then(|q| rest[start..].find(q));
+
+        match end {
+            Some(end) if !rest[start..start + end].contains(|c: char| "?#&".contains(c)) => {
+                out.push_str(&rest[..start]);
+                out.push_str(&to(&rest[start + base.len()..start + end]));
+                rest = &rest[start + end..];
+            }
+            _ => {
+                out.push_str(&rest[..start + needle.len()]);
+                rest = &rest[start + needle.len()..];
+            }
+        }
+    }
+
+    out.push_str(rest);
+    out
+}
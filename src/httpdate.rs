@@ -0,0 +1,135 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/. */
+
+//! Just enough IMF-fixdate (`Sun, 06 Nov 1994 08:49:37 GMT`) handling for
+//! `Last-Modified` and `If-Modified-Since`, in whole seconds since the epoch.
+
+use std::ops::RangeInclusive;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+const DAYS: [&str; 7] = ["Sun", "Mon", "Tue", "Wed", "Thu", "Fri", "Sat"];
+
+const MONTHS: [&str; 12] = [
+    "Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec",
+];
+
+/// Years `parse` accepts: nothing before the epoch could be newer than a file,
+/// and four digits keeps the arithmetic well clear of overflow.
+const YEARS: RangeInclusive<i64> = 1970..=9999;
+
+pub(crate) fn unix_secs(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
+}
+
+pub(crate) fn format(secs: u64) -> String {
+    let days = (secs / 86400) as i64;
+    let rem = secs % 86400;
+    let (year, month, day) = civil_from_days(days);
+
+    format!(
+        "{}, {:02} {} {} {:02}:{:02}:{:02} GMT",
+        DAYS[((days + 4) % 7) as usize],
+        day,
+        MONTHS[(month - 1) as usize],
+        year,
+        rem / 3600,
+        (rem / 60) % 60,
+        rem % 60,
+    )
+}
+
+pub(crate) fn parse(text: &str) -> Option<u64> {
+    let mut parts = text.split_whitespace();
+
+    let _weekday = parts.next()?;
+    let day: i64 = parts.next()?.parse().ok()?;
+    let month = parts.next()?;
+    let year: i64 = parts.next()?.parse().ok()?;
+    let time = parts.next()?;
+
+    if !YEARS.contains(&year) {
+        return None;
+    }
+
+    if parts.next()? != "GMT" || parts.next().is_some() {
+        return None;
+    }
+
+    let month = MONTHS.iter().position(|m| *m == month)? as i64 + 1;
+
+    let mut hms = time.split(':').map(|p| p.parse::<u64>().ok());
+    let (hour, minute, second) = (hms.next()??, hms.next()??, hms.next()??);
+
+    if hms.next().is_some() || day < 1 || day > 31 || hour > 23 || minute > 59 || second > 60 {
+        return None;
+    }
+
+    let days = days_from_civil(year, month, day);
+    if days < 0 {
+        return None;
+    }
+
+    Some(days as u64 * 86400 + hour * 3600 + minute * 60 + second)
+}
+
+// Both conversions are Howard Hinnant's `civil_from_days`/`days_from_civil`.
+
+fn civil_from_days(days: i64) -> (i64, i64, i64) {
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+
+    (year, month, day)
+}
+
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let yoe = year.rem_euclid(400);
+    let mp = if month > 2 { month - 3 } else { month + 9 };
+    let doy = (153 * mp + 2) / 5 + day - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+
+    era * 146_097 + doe - 719_468
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trips_imf_fixdates() {
+        assert_eq!(parse("Sun, 06 Nov 1994 08:49:37 GMT"), Some(784_111_777));
+        assert_eq!(format(784_111_777), "Sun, 06 Nov 1994 08:49:37 GMT");
+        assert_eq!(format(0), "Thu, 01 Jan 1970 00:00:00 GMT");
+    }
+
+    #[test]
+    fn rejects_years_out_of_range() {
+        assert_eq!(parse("Sat, 31 Dec 1969 23:59:59 GMT"), None);
+        assert_eq!(
+            parse("Fri, 31 Dec 9999 23:59:59 GMT"),
+            Some(253_402_300_799)
+        );
+        assert_eq!(parse("Sat, 01 Jan 10000 00:00:00 GMT"), None);
+        assert_eq!(parse("Mon, 01 Jan 9223372036854775807 00:00:00 GMT"), None);
+    }
+
+    #[test]
+    fn rejects_malformed_dates() {
+        assert_eq!(parse("Sun, 06 Nov 1994 08:49:37"), None);
+        assert_eq!(parse("Sun, 06 Nov 1994 08:49:37 GMT extra"), None);
+        assert_eq!(parse("Sun, 32 Nov 1994 08:49:37 GMT"), None);
+        assert_eq!(parse("Sun, 06 Foo 1994 08:49:37 GMT"), None);
+        assert_eq!(parse("Sun, 06 Nov 1994 24:00:00 GMT"), None);
+    }
+}
@@ -0,0 +1,61 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/. */
+
+use std::path::{Component, Path, PathBuf};
+
+/// Whether any component of `path` starts with a `.`, like `.env` or
+/// `.well-known/security.txt`.
+pub(crate) fn is_dotted(path: &Path) -> bool {
+    path.components().any(|component| match component {
+        Component::Normal(part) => part.to_string_lossy().starts_with('.'),
+        _ => false,
+    })
+}
+
+/// Normalize `relative` without touching the filesystem, resolving `.` and
+/// `..` components.
+///
+/// Returns `None` if the path is absolute or climbs above its starting point,
+/// so the result can always be safely joined onto a root directory. Symbolic
+/// links are *not* resolved: a link inside the root pointing elsewhere is
+/// followed as-is.
+pub(crate) fn normalize(relative: &Path) -> Option<PathBuf> {
+    let mut normal = PathBuf::new();
+
+    for component in relative.components() {
+        match component {
+            Component::Normal(part) => normal.push(part),
+            Component::CurDir => (),
+            Component::ParentDir => {
+                if !normal.pop() {
+                    return None;
+                }
+            }
+            Component::RootDir | Component::Prefix(_) => return None,
+        }
+    }
+
+    Some(normal)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn resolves_dots_that_stay_inside() {
+        assert_eq!(
+            normalize(Path::new("css/./vendor/../site.css")),
+            Some(PathBuf::from("css/site.css"))
+        );
+    }
+
+    #[test]
+    fn blocks_climbing_out() {
+        assert_eq!(normalize(Path::new("..")), None);
+        assert_eq!(normalize(Path::new("../secret")), None);
+        assert_eq!(normalize(Path::new("css/../../secret")), None);
+        assert_eq!(normalize(Path::new("/etc/passwd")), None);
+    }
+}
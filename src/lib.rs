@@ -23,16 +23,45 @@
 #![deny(missing_docs)]
 #![deny(missing_debug_implementations)]
 #![feature(decl_macro)]
+#![feature(const_panic)]
 
 #[macro_use]
 extern crate rocket;
 #[doc(hidden)]
 pub extern crate phf;
 
+#[cfg(feature = "zip")]
+mod archive;
+mod asset;
+mod config;
+mod error;
 mod fairing;
 #[cfg(feature = "gen")]
 mod gen;
+mod glob;
+mod headers;
+mod html;
+mod httpdate;
+mod lexical;
+mod manifest;
+mod map;
+mod resolve;
+mod response;
+#[cfg(test)]
+mod testing;
+#[cfg(feature = "verify")]
+mod verify;
 
+pub use crate::asset::Asset;
+pub use crate::config::ConfigView;
+pub use crate::error::Error as ServeError;
 pub use crate::fairing::*;
 #[cfg(feature = "gen")]
 pub use crate::gen::*;
+#[doc(hidden)]
+pub use crate::map::check_schema;
+pub use crate::map::{AssetMap, SortedMap, MAP_SCHEMA};
+pub use crate::resolve::resolve_within;
+pub use crate::response::ServedFile;
+#[cfg(feature = "verify")]
+pub use crate::verify::Mismatch;
@@ -0,0 +1,95 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/. */
+
+//! The JSON index of every asset, served at `manifest_path`:
+//!
+//! ```json
+//! {"app.js":{"version":"H8y4bzqH6Mg","url":"/static/app.js?v=H8y4bzqH6Mg","integrity":null}}
+//! ```
+
+use crate::asset::Asset;
+use crate::config::Config;
+
+use rocket::http::hyper::header::{CacheControl, CacheDirective};
+use rocket::http::ContentType;
+use rocket::response::{Responder, Result as ResponseResult};
+use rocket::{Request, Response};
+
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::io::Cursor;
+
+/// Seconds clients may cache the manifest. It changes with every deploy, so
+/// this is kept short.
+const MAX_AGE: u32 = 60;
+
+/// The rendered manifest, ready to send.
+#[derive(Debug)]
+pub(crate) struct Manifest(String);
+
+impl Manifest {
+    pub(crate) fn render(config: &Config, assets: &BTreeMap<String, Asset>) -> Self {
+        let mut out = String::from("{");
+
+        let listed = assets.iter().filter(|(path, _)| !config.is_hidden(path));
+
+        for (i, (path, asset)) in listed.enumerate() {
+            let url = if config.is_pinned(path) {
+                config.file_url(path)
+            } else {
+                config.versioned_url(path, asset.version)
+            };
+
+            let integrity = match asset.integrity {
+                Some(digest) => string(digest),
+                None => "null".into(),
+            };
+
+            if i > 0 {
+                out.push(',');
+            }
+
+            write!(
+                out,
+                "{}:{{\"version\":{},\"url\":{},\"integrity\":{}}}",
+                string(path),
+                string(asset.version),
+                string(&url),
+                integrity,
+            )
+            .unwrap();
+        }
+
+        out.push('}');
+        Manifest(out)
+    }
+}
+
+impl<'r> Responder<'r> for Manifest {
+    fn respond_to(self, _: &Request) -> ResponseResult<'r> {
+        Response::build()
+            .header(ContentType::JSON)
+            .header(CacheControl(vec![CacheDirective::MaxAge(MAX_AGE)]))
+            .sized_body(Cursor::new(self.0))
+            .ok()
+    }
+}
+
+/// `text` as a JSON string literal.
+fn string(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len() + 2);
+    escaped.push('"');
+
+    for c in text.chars() {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            c if c < ' ' => write!(escaped, "\\u{:04x}", c as u32).unwrap(),
+            c => escaped.push(c),
+        }
+    }
+
+    escaped.push('"');
+    escaped
+}
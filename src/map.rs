@@ -0,0 +1,152 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/. */
+
+use crate::asset::Asset;
+
+use std::fmt::Debug;
+
+/// Version of the generated file's layout (the fields of [`Asset`], the
+/// statics defined, and so on). Bumped whenever a file written by one release
+/// of the generator wouldn't compile against another.
+pub const MAP_SCHEMA: u32 = 1;
+
+/// Called from the generated file, so that a map written by a different
+/// release of the generator (say, when `[build-dependencies]` and
+/// `[dependencies]` disagree) fails with this message rather than a type
+/// error about `Asset`'s fields.
+///
+/// The generated file starts with a line like this one:
+///
+/// ```
+/// const _: () = rocket_static_files::check_schema(rocket_static_files::MAP_SCHEMA);
+/// ```
+///
+/// which stops the build for a map of any other schema:
+///
+/// ```compile_fail
+/// const _: () = rocket_static_files::check_schema(0);
+/// ```
+#[doc(hidden)]
+pub const fn check_schema(schema: u32) {
+    if schema != MAP_SCHEMA {
+        panic!(
+            "static_file_hashes.rs was generated for a different version of \
+             rocket-static-files; use the same version in [build-dependencies] \
+             and [dependencies]"
+        );
+    }
+}
+
+/// A generated table of [`Asset`]s by path (relative to `serve_from`), as
+/// passed to [`StaticFiles::fairing`](crate::StaticFiles::fairing).
+///
+/// The generator writes a `phf::Map` by default, or a [`SortedMap`] with
+/// [`Generator::sorted_map`](crate::Generator::sorted_map).
+pub trait AssetMap: Debug + Sync {
+    /// The asset stored under `path`, if any.
+    fn get(&self, path: &str) -> Option<&Asset>;
+
+    /// Every path and its asset, in no particular order.
+    fn entries(&self) -> Box<dyn Iterator<Item = (&str, &Asset)> + '_>;
+}
+
+impl AssetMap for phf::Map<&'static str, Asset> {
+    fn get(&self, path: &str) -> Option<&Asset> {
+        phf::Map::get(self, path)
+    }
+
+    fn entries(&self) -> Box<dyn Iterator<Item = (&str, &Asset)> + '_> {
+        Box::new(phf::Map::entries(self).map(|(path, asset)| (*path, asset)))
+    }
+}
+
+/// Assets in a slice sorted by path, found by binary search.
+///
+/// Slower to look up than a `phf::Map`, but much quicker to compile when
+/// there are tens of thousands of files.
+#[derive(Debug)]
+pub struct SortedMap(&'static [(&'static str, Asset)]);
+
+impl SortedMap {
+    /// Wrap `entries`, which must be sorted by path.
+    pub const fn new(entries: &'static [(&'static str, Asset)]) -> Self {
+        SortedMap(entries)
+    }
+}
+
+impl AssetMap for SortedMap {
+    fn get(&self, path: &str) -> Option<&Asset> {
+        self.0
+            .binary_search_by(|(key, _)| (*key).cmp(path))
+            .ok()
+            .map(|i| &self.0[i].1)
+    }
+
+    fn entries(&self) -> Box<dyn Iterator<Item = (&str, &Asset)> + '_> {
+        Box::new(self.0.iter().map(|(path, asset)| (*path, asset)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::testing::ASSET;
+
+    static MAP: SortedMap = SortedMap::new(&[
+        (
+            "a.css",
+            Asset {
+                version: "a",
+                ..ASSET
+            },
+        ),
+        (
+            "js/app.js",
+            Asset {
+                version: "app",
+                ..ASSET
+            },
+        ),
+        (
+            "js/vendor.js",
+            Asset {
+                version: "vendor",
+                ..ASSET
+            },
+        ),
+        (
+            "z.txt",
+            Asset {
+                version: "z",
+                ..ASSET
+            },
+        ),
+    ]);
+
+    #[test]
+    fn sorted_maps_find_every_entry() {
+        for (path, asset) in MAP.entries() {
+            assert_eq!(MAP.get(path).map(|a| a.version), Some(asset.version));
+        }
+
+        assert_eq!(MAP.get("js/app.js").map(|a| a.version), Some("app"));
+        assert_eq!(MAP.entries().count(), 4);
+
+        for missing in &["", "a.cs", "b.css", "js", "js/", "zz.txt"] {
+            assert!(MAP.get(missing).is_none(), "{}", missing);
+        }
+    }
+
+    #[test]
+    fn the_current_schema_checks_out() {
+        check_schema(MAP_SCHEMA);
+    }
+
+    #[test]
+    #[should_panic(expected = "generated for a different version of rocket-static-files")]
+    fn maps_of_another_schema_say_so() {
+        check_schema(MAP_SCHEMA - 1);
+    }
+}
@@ -0,0 +1,102 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/. */
+
+use crate::error::{Error, Io, OutOfBounds};
+use crate::lexical;
+
+use snafu::{ensure, OptionExt, ResultExt};
+
+use std::path::{Path, PathBuf};
+
+/// Where `requested` lives under `serve_from`, or an error if it would be
+/// anywhere else.
+///
+/// `serve_from` must already be canonical. Normally `requested` is
+/// canonicalized too, so the file must exist and symbolic links can't lead
+/// outside `serve_from`; with `skip_canonicalize` it's only normalized
+/// lexically (see the option of the same name).
+///
+/// This is the check `StaticFiles` runs on every request path, exposed so it
+/// can be fuzzed on its own.
+pub fn resolve_within(
+    serve_from: &Path,
+    requested: &Path,
+    skip_canonicalize: bool,
+) -> Result<PathBuf, Error> {
+    // Never a valid file name, and `canonicalize` would call it an internal
+    // error rather than a missing file.
+    let bytes = requested.as_os_str().to_string_lossy();
+    ensure!(!bytes.contains('\0'), OutOfBounds);
+
+    let target = if skip_canonicalize {
+        let relative = lexical::normalize(requested).context(OutOfBounds)?;
+        serve_from.join(relative)
+    } else {
+        serve_from.join(requested).canonicalize().context(Io)?
+    };
+
+    ensure!(target.starts_with(serve_from), OutOfBounds);
+    Ok(target)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::testing::TempDir;
+
+    #[test]
+    fn stays_within_serve_from() {
+        let dir = TempDir::new();
+        dir.write("static/css/app.css", "");
+        dir.write("secret/key", "");
+        let root = dir.path().join("static");
+        let absolute = dir.path().join("secret/key");
+        let absolute = absolute.to_str().unwrap();
+
+        for &skip in &[false, true] {
+            let resolve = |path: &str| resolve_within(&root, Path::new(path), skip);
+
+            assert_eq!(
+                resolve("css/app.css").unwrap(),
+                root.join("css/app.css"),
+                "skip_canonicalize = {}",
+                skip
+            );
+
+            for escape in &["../secret/key", "css/../../secret/key", absolute] {
+                match resolve(escape) {
+                    Err(Error::OutOfBounds) => (),
+                    other => panic!("{} gave {:?} ({})", escape, other, skip),
+                }
+            }
+
+            match resolve("css/\0app.css") {
+                Err(Error::OutOfBounds) => (),
+                other => panic!("NUL gave {:?} ({})", other, skip),
+            }
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn symbolic_links_out_are_caught_unless_skipped() {
+        let dir = TempDir::new();
+        dir.write("static/app.js", "");
+        dir.write("secret/key", "");
+        let root = dir.path().join("static");
+        std::os::unix::fs::symlink(dir.path().join("secret"), root.join("linked")).unwrap();
+
+        match resolve_within(&root, Path::new("linked/key"), false) {
+            Err(Error::OutOfBounds) => (),
+            other => panic!("expected OutOfBounds, got {:?}", other),
+        }
+
+        // As documented for `skip_canonicalize`.
+        assert_eq!(
+            resolve_within(&root, Path::new("linked/key"), true).unwrap(),
+            root.join("linked/key")
+        );
+    }
+}
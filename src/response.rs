@@ -0,0 +1,821 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/. */
+
+use crate::asset::{self, Asset, Encoding};
+use crate::config::Config;
+use crate::error::{Error, Io, NotAFile, Replaced};
+use crate::httpdate;
+
+use rocket::http::hyper::header::CacheControl;
+use rocket::http::{ContentType, Header, Status};
+use rocket::request::{FromRequest, Outcome};
+use rocket::response::{Body, Redirect, Responder, Result as ResponseResult};
+use rocket::{Request, Response};
+
+use snafu::{ensure, ResultExt};
+
+use flate2::read::GzDecoder;
+
+use std::fs::{File, Metadata};
+#[cfg(feature = "log")]
+use std::io::BufRead;
+use std::io::{self, BufReader, Cursor, Read, Seek, SeekFrom};
+use std::path::Path;
+use std::str::FromStr;
+use std::sync::Arc;
+
+/// Content codings the client is willing to accept, from `Accept-Encoding`.
+#[derive(Debug, Default, Clone, Copy)]
+pub(crate) struct AcceptEncoding {
+    gzip: bool,
+    brotli: bool,
+    zstd: bool,
+    dcb: bool,
+
+    /// Codings asked for by name rather than through `*`, a bit per
+    /// [`Encoding`].
+    named: u8,
+
+    /// SHA-256 of the dictionary the client has, from `Available-Dictionary`.
+    #[cfg(feature = "dictionary")]
+    dictionary: Option<[u8; 32]>,
+}
+
+impl AcceptEncoding {
+    fn parse(&mut self, header: &str) {
+        for item in header.split(',') {
+            let mut parts = item.split(';').map(str::trim);
+            let coding = parts.next().unwrap_or_default();
+
+            // A quality of zero means "not acceptable".
+            let refused = parts
+                .filter_map(|p| p.strip_prefix("q="))
+                .any(|q| q.parse::<f32>().map(|q| q <= 0.0).unwrap_or(false));
+
+            if refused {
+                continue;
+            }
+
+            for encoding in Encoding::PREFERENCE.iter() {
+                if coding.eq_ignore_ascii_case(encoding.name()) {
+                    self.named |= 1 << *encoding as u8;
+                }
+            }
+
+            if coding.eq_ignore_ascii_case("gzip") || coding == "*" {
+                self.gzip = true;
+            }
+
+            if coding.eq_ignore_ascii_case("br") || coding == "*" {
+                self.brotli = true;
+            }
+
+            if coding.eq_ignore_ascii_case("zstd") || coding == "*" {
+                self.zstd = true;
+            }
+
+            // Only worth it when asked for by name, with a dictionary.
+            if coding.eq_ignore_ascii_case("dcb") {
+                self.dcb = true;
+            }
+        }
+    }
+
+    /// The SHA-256 of the dictionary the client has, if it also accepts
+    /// `dcb`, and so might take a dictionary-compressed sibling.
+    #[cfg(feature = "dictionary")]
+    pub(crate) fn dictionary(&self) -> Option<[u8; 32]> {
+        self.dictionary.filter(|_| self.dcb)
+    }
+
+    fn accepts(&self, encoding: Encoding) -> bool {
+        match encoding {
+            Encoding::Gzip => self.gzip,
+            Encoding::Brotli => self.brotli,
+            Encoding::Zstd => self.zstd,
+            Encoding::DictionaryBrotli => self.dcb,
+        }
+    }
+
+    /// Pick the preferred encoding that both the client and `asset` support,
+    /// only counting ones asked for by name if `named_only`.
+    pub(crate) fn choose(&self, asset: &Asset, named_only: bool) -> Option<Encoding> {
+        Encoding::PREFERENCE
+            .iter()
+            .copied()
+            .filter(|e| !named_only || self.named & (1 << *e as u8) != 0)
+            .find(|e| self.accepts(*e) && asset.has(*e))
+    }
+}
+
+impl<'a, 'r> FromRequest<'a, 'r> for AcceptEncoding {
+    type Error = ();
+
+    fn from_request(request: &'a Request<'r>) -> Outcome<Self, Self::Error> {
+        let mut accept = AcceptEncoding::default();
+
+        for header in request.headers().get("Accept-Encoding") {
+            accept.parse(header);
+        }
+
+        #[cfg(feature = "dictionary")]
+        {
+            accept.dictionary = request
+                .headers()
+                .get_one("Available-Dictionary")
+                .and_then(parse_dictionary_hash);
+        }
+
+        Outcome::Success(accept)
+    }
+}
+
+/// What a `dcb` stream starts with, before the dictionary's SHA-256.
+#[cfg(feature = "dictionary")]
+const DCB_MAGIC: [u8; 4] = [0xff, 0x44, 0x43, 0x42];
+
+/// The SHA-256 of the dictionary the `dcb` file at `path` was compressed
+/// against, from its header; `None` if it can't be read or isn't `dcb`.
+#[cfg(feature = "dictionary")]
+pub(crate) fn dictionary_hash(path: &Path) -> Option<[u8; 32]> {
+    let mut header = [0; 36];
+    File::open(path)
+        .and_then(|mut file| file.read_exact(&mut header))
+        .ok()?;
+
+    if header[..4] != DCB_MAGIC {
+        return None;
+    }
+
+    let mut hash = [0; 32];
+    hash.copy_from_slice(&header[4..]);
+    Some(hash)
+}
+
+/// The SHA-256 in an `Available-Dictionary` header: a structured-field byte
+/// sequence, `:<base64>:`.
+#[cfg(feature = "dictionary")]
+fn parse_dictionary_hash(header: &str) -> Option<[u8; 32]> {
+    let encoded = header.trim().strip_prefix(':')?.strip_suffix(':')?;
+    let decoded = base64::decode(encoded).ok()?;
+
+    let mut hash = [0; 32];
+    if decoded.len() != hash.len() {
+        return None;
+    }
+
+    hash.copy_from_slice(&decoded);
+    Some(hash)
+}
+
+/// Validators sent back by a client revalidating a cached response.
+#[derive(Debug, Default)]
+pub(crate) struct Conditional {
+    if_none_match: Option<String>,
+    if_modified_since: Option<u64>,
+}
+
+impl Conditional {
+    /// Whether the client's copy is still current, so a `304` will do.
+    ///
+    /// `modified` is only called when there's an `If-Modified-Since` to
+    /// compare against, which (per RFC 7232) is ignored whenever
+    /// `If-None-Match` is present.
+    pub(crate) fn is_fresh<F>(&self, etag: Option<&str>, modified: F) -> bool
+    where
+        F: FnOnce() -> Option<u64>,
+    {
+        if let Some(if_none_match) = &self.if_none_match {
+            return match etag {
+                Some(etag) => etag_matches(if_none_match, etag),
+                None => false,
+            };
+        }
+
+        match (self.if_modified_since, modified()) {
+            (Some(since), Some(modified)) => modified <= since,
+            _ => false,
+        }
+    }
+}
+
+impl<'a, 'r> FromRequest<'a, 'r> for Conditional {
+    type Error = ();
+
+    fn from_request(request: &'a Request<'r>) -> Outcome<Self, Self::Error> {
+        let headers = request.headers();
+
+        let if_none_match: Vec<_> = headers.get("If-None-Match").collect();
+        let if_none_match = if if_none_match.is_empty() {
+            None
+        } else {
+            Some(if_none_match.join(","))
+        };
+
+        let if_modified_since = headers
+            .get_one("If-Modified-Since")
+            .and_then(httpdate::parse);
+
+        Outcome::Success(Conditional {
+            if_none_match,
+            if_modified_since,
+        })
+    }
+}
+
+/// Weak comparison of `etag` against an `If-None-Match` list.
+fn etag_matches(if_none_match: &str, etag: &str) -> bool {
+    let etag = etag.trim_start_matches("W/");
+
+    if_none_match
+        .split(',')
+        .map(str::trim)
+        .any(|tag| tag == "*" || tag.trim_start_matches("W/") == etag)
+}
+
+/// The entity tag for a tracked file, distinct for each encoding.
+pub(crate) fn etag(version: &str, encoding: Option<Encoding>) -> String {
+    match encoding {
+        Some(encoding) => format!("\"{}-{}\"", version, encoding.name()),
+        None => format!("\"{}\"", version),
+    }
+}
+
+/// `etag` with `-suffix` added inside the quotes.
+pub(crate) fn etag_suffixed(etag: &str, suffix: &str) -> String {
+    let unquoted = etag.strip_suffix('"').unwrap_or(etag);
+    format!("{}-{}\"", unquoted, suffix)
+}
+
+/// A weak entity tag from the size and modification time of an untracked
+/// file.
+pub(crate) fn weak_etag(metadata: &Metadata) -> Option<String> {
+    Some(format!("W/\"{}\"", stat_token(metadata)?))
+}
+
+/// A cheap stand-in for a version token: the size and modification time.
+pub(crate) fn stat_token(metadata: &Metadata) -> Option<String> {
+    let modified = httpdate::unix_secs(metadata.modified().ok()?);
+    Some(format!("{:x}-{:x}", metadata.len(), modified))
+}
+
+/// A redirect to where a file is really served from, cacheable for a little
+/// while.
+#[derive(Debug)]
+pub(crate) struct FileRedirect {
+    pub(crate) redirect: Redirect,
+    pub(crate) cache_control: CacheControl,
+}
+
+impl<'r> Responder<'r> for FileRedirect {
+    fn respond_to(self, request: &Request) -> ResponseResult<'r> {
+        let mut response = self.redirect.respond_to(request)?;
+        response.set_header(self.cache_control);
+        Ok(response)
+    }
+}
+
+/// `R`, plus an `X-Cache-Status` header if there's one to send.
+#[derive(Debug)]
+pub(crate) struct WithCacheStatus<R>(pub(crate) R, pub(crate) Option<&'static str>);
+
+impl<'r, R: Responder<'r>> Responder<'r> for WithCacheStatus<R> {
+    fn respond_to(self, request: &Request) -> ResponseResult<'r> {
+        let mut response = self.0.respond_to(request)?;
+
+        if let Some(status) = self.1 {
+            response.set_raw_header("X-Cache-Status", status);
+        }
+
+        Ok(response)
+    }
+}
+
+/// Headers a `304` carries if the `200` would have (RFC 7232, section 4.1).
+/// Anything else, entity headers like `Content-Type` especially, is left off.
+pub(crate) const NOT_MODIFIED_HEADERS: [&str; 6] = [
+    "Cache-Control",
+    "Content-Location",
+    "Date",
+    "ETag",
+    "Expires",
+    "Vary",
+];
+
+/// A bodyless `304 Not Modified`.
+#[derive(Debug)]
+pub(crate) struct NotModified {
+    pub(crate) etag: Option<String>,
+    pub(crate) cache_control: CacheControl,
+    pub(crate) vary: bool,
+
+    /// Extra headers for the file, of which only [`NOT_MODIFIED_HEADERS`] are
+    /// sent.
+    pub(crate) headers: Vec<Header<'static>>,
+}
+
+impl<'r> Responder<'r> for NotModified {
+    fn respond_to(self, _: &Request) -> ResponseResult<'r> {
+        let mut response = Response::new();
+
+        response.set_status(Status::NotModified);
+        response.set_header(self.cache_control);
+
+        if let Some(etag) = self.etag {
+            response.set_header(Header::new("ETag", etag));
+        }
+
+        if self.vary {
+            response.set_header(Header::new("Vary", "Accept-Encoding"));
+        }
+
+        let allowed = |h: &Header| {
+            NOT_MODIFIED_HEADERS
+                .iter()
+                .any(|n| n.eq_ignore_ascii_case(h.name()))
+        };
+
+        for header in self.headers.into_iter().filter(allowed) {
+            set_or_vary(&mut response, header);
+        }
+
+        Ok(response)
+    }
+}
+
+/// `405 Method Not Allowed`, with the `Allow` header value.
+#[derive(Debug)]
+pub(crate) struct MethodNotAllowed(pub(crate) &'static str);
+
+impl<'r> Responder<'r> for MethodNotAllowed {
+    fn respond_to(self, _: &Request) -> ResponseResult<'r> {
+        Response::build()
+            .status(Status::MethodNotAllowed)
+            .raw_header("Allow", self.0)
+            .ok()
+    }
+}
+
+/// `204 No Content` for an `OPTIONS` request.
+#[derive(Debug)]
+pub(crate) struct OptionsResponse {
+    pub(crate) allow: &'static str,
+    pub(crate) headers: Vec<Header<'static>>,
+}
+
+impl<'r> Responder<'r> for OptionsResponse {
+    fn respond_to(self, _: &Request) -> ResponseResult<'r> {
+        let mut response = Response::new();
+
+        response.set_status(Status::NoContent);
+        response.set_raw_header("Allow", self.allow);
+
+        for header in self.headers {
+            response.set_header(header);
+        }
+
+        Ok(response)
+    }
+}
+
+/// Left in request-local state (as `Option<ServedFile>`) for every file
+/// `StaticFiles` sends, so later response fairings know what the body is:
+///
+/// ```nocompile
+/// let served = request.local_cache(|| None::<ServedFile>);
+///
+/// if let Some(ServedFile { encoding: Some(_), .. }) = served {
+///     // Already compressed, leave it alone.
+/// }
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct ServedFile {
+    /// The `Content-Encoding` of the body (`"gzip"`, `"br"`), or `None` if the
+    /// file was sent as-is.
+    pub encoding: Option<&'static str>,
+}
+
+/// Bytes of a file held in memory at once while it's streamed out.
+const STREAM_BUFFER: usize = 64 * 1024;
+
+/// A file ready to send.
+///
+/// Files on disk are streamed from the open handle through a buffer of
+/// [`STREAM_BUFFER`] bytes, so a slow client holds up its worker thread rather
+/// than filling memory with the rest of the file. Only archive members (see
+/// [`Contents::Memory`]) are read into memory whole; embedded files are
+/// already there.
+#[derive(Debug)]
+pub(crate) struct FileResponse {
+    body: BufReader<Contents>,
+    len: u64,
+    content_type: ContentType,
+    cache_control: CacheControl,
+    encoding: Option<Encoding>,
+    vary: bool,
+    pub(crate) etag: Option<String>,
+    last_modified: Option<u64>,
+    pub(crate) headers: Vec<Header<'static>>,
+
+    /// Sent as the current version, with the long-lived `Cache-Control`.
+    pub(crate) versioned: bool,
+}
+
+impl<'r> Responder<'r> for FileResponse {
+    fn respond_to(self, request: &Request) -> ResponseResult<'r> {
+        let encoding = self.encoding.map(Encoding::name);
+        request.local_cache(|| Some(ServedFile { encoding }));
+
+        let mut response = Response::new();
+
+        response.set_raw_body(Body::Sized(self.body, self.len));
+        response.set_header(self.content_type);
+        response.set_header(self.cache_control);
+
+        if let Some(encoding) = self.encoding {
+            response.set_header(Header::new("Content-Encoding", encoding.name()));
+        }
+
+        if self.vary {
+            response.set_header(Header::new("Vary", "Accept-Encoding"));
+        }
+
+        if let Some(etag) = self.etag {
+            response.set_header(Header::new("ETag", etag));
+        }
+
+        if let Some(modified) = self.last_modified {
+            response.set_header(Header::new("Last-Modified", httpdate::format(modified)));
+        }
+
+        for header in self.headers {
+            set_or_vary(&mut response, header);
+        }
+
+        Ok(response)
+    }
+}
+
+/// Set `header` on `response`, except that `Vary` is merged into what's
+/// there, so the response carries one `Vary` naming each field once.
+fn set_or_vary(response: &mut Response, header: Header<'static>) {
+    if !header.name().eq_ignore_ascii_case("Vary") {
+        response.set_header(header);
+        return;
+    }
+
+    let mut fields: Vec<String> = response
+        .headers()
+        .get("Vary")
+        .flat_map(|value| value.split(','))
+        .map(str::trim)
+        .filter(|field| !field.is_empty())
+        .map(str::to_owned)
+        .collect();
+
+    for field in header.value().split(',').map(str::trim) {
+        if !field.is_empty() && !fields.iter().any(|f| f.eq_ignore_ascii_case(field)) {
+            fields.push(field.to_owned());
+        }
+    }
+
+    response.set_header(Header::new("Vary", fields.join(", ")));
+}
+
+const UTF8_BOM: &[u8] = b"\xEF\xBB\xBF";
+
+impl FileResponse {
+    /// The body, for reading its first chunk into the buffer it's then sent
+    /// from, so the time that takes can be measured up front.
+    #[cfg(feature = "log")]
+    pub(crate) fn body_mut(&mut self) -> &mut impl BufRead {
+        &mut self.body
+    }
+
+    /// Size in bytes of the body.
+    #[cfg_attr(not(feature = "tracing"), allow(dead_code))]
+    pub(crate) fn body_len(&self) -> u64 {
+        self.len
+    }
+
+    pub(crate) fn new<P: AsRef<Path>>(
+        config: &Config,
+        path: P,
+        cache_control: CacheControl,
+        asset: Option<&Asset>,
+        encoding: Option<Encoding>,
+    ) -> Result<Self, Error> {
+        Self::new_path(config, path.as_ref(), cache_control, asset, encoding)
+    }
+
+    fn new_path(
+        config: &Config,
+        path: &Path,
+        cache_control: CacheControl,
+        asset: Option<&Asset>,
+        encoding: Option<Encoding>,
+    ) -> Result<Self, Error> {
+        let (file, metadata) = match encoding {
+            Some(encoding) => open_checked(&encoding.sibling(path))?,
+            None => open_checked(path)?,
+        };
+
+        let body = Contents::File(file);
+        let mut response = Self::with_body(
+            config,
+            path,
+            body,
+            metadata.len(),
+            cache_control,
+            asset,
+            encoding,
+        )?;
+
+        response.last_modified = metadata.modified().ok().map(httpdate::unix_secs);
+        if asset.is_none() {
+            response.etag = weak_etag(&metadata);
+        }
+
+        Ok(response)
+    }
+
+    /// Respond with `contents`, embedded in the map, as the file at `path`
+    /// (in `encoding`, if set).
+    pub(crate) fn from_static(
+        config: &Config,
+        path: &Path,
+        contents: &'static [u8],
+        cache_control: CacheControl,
+        asset: Option<&Asset>,
+        encoding: Option<Encoding>,
+    ) -> Result<Self, Error> {
+        let len = contents.len() as u64;
+        let body = Contents::Static(Cursor::new(contents));
+        Self::with_body(config, path, body, len, cache_control, asset, encoding)
+    }
+
+    /// Respond with the file at `path`, decompressed on the fly from the
+    /// embedded, gzipped `contents`. `size` is the decompressed size.
+    pub(crate) fn gunzip(
+        config: &Config,
+        path: &Path,
+        contents: &'static [u8],
+        size: u64,
+        cache_control: CacheControl,
+        asset: Option<&Asset>,
+    ) -> Result<Self, Error> {
+        let body = Contents::Gunzip(GzDecoder::new(contents), contents);
+        Self::with_body(config, path, body, size, cache_control, asset, None)
+    }
+
+    /// Respond with a file read in by `StaticFiles::warm`, as the file at
+    /// `path` (or its sibling, if `encoding` is set).
+    pub(crate) fn from_warm(
+        config: &Config,
+        path: &Path,
+        warm: &Warm,
+        cache_control: CacheControl,
+        asset: Option<&Asset>,
+        encoding: Option<Encoding>,
+    ) -> Result<Self, Error> {
+        let len = warm.contents.len() as u64;
+        let body = Contents::Shared(Cursor::new(warm.contents.clone()));
+        let mut response =
+            Self::with_body(config, path, body, len, cache_control, asset, encoding)?;
+
+        response.last_modified = warm.modified;
+        if asset.is_none() {
+            response.etag = warm.etag.clone();
+        }
+
+        Ok(response)
+    }
+
+    /// Respond with `contents`, already read into memory, as the file at
+    /// `path` (or its sibling, if `encoding` is set).
+    #[cfg_attr(not(feature = "zip"), allow(dead_code))]
+    pub(crate) fn from_memory(
+        config: &Config,
+        path: &Path,
+        contents: Vec<u8>,
+        cache_control: CacheControl,
+        asset: Option<&Asset>,
+        encoding: Option<Encoding>,
+    ) -> Result<Self, Error> {
+        let len = contents.len() as u64;
+        let body = Contents::Memory(Cursor::new(contents));
+        Self::with_body(config, path, body, len, cache_control, asset, encoding)
+    }
+
+    fn with_body(
+        config: &Config,
+        path: &Path,
+        mut body: Contents,
+        mut len: u64,
+        cache_control: CacheControl,
+        asset: Option<&Asset>,
+        encoding: Option<Encoding>,
+    ) -> Result<Self, Error> {
+        // The content type always describes the original, not the sibling.
+        let mime = config.mime(path);
+
+        // Compressed siblings are served untouched; the version token is
+        // always computed over the file as it is on disk.
+        if config.strip_bom && encoding.is_none() && asset::is_text(&mime) {
+            len -= Self::skip_bom(&mut body).context(Io)?;
+        }
+
+        let mut mime_text = mime.to_string();
+        if let Some(charset) = config.charset(&mime) {
+            mime_text = format!("{}; charset={}", mime_text, charset);
+        }
+
+        // TODO: Probably a better way to do this conversion
+        let content_type = ContentType::from_str(&mime_text).unwrap();
+
+        let timing_allow_origin = config
+            .timing_allow_origin
+            .clone()
+            .map(|origin| Header::new("Timing-Allow-Origin", origin));
+
+        let headers = config
+            .content_language(path)
+            .map(|language| Header::new("Content-Language", language.to_owned()))
+            .into_iter()
+            .chain(timing_allow_origin)
+            .collect();
+
+        Ok(FileResponse {
+            body: BufReader::with_capacity(STREAM_BUFFER, body),
+            len,
+            content_type,
+            cache_control,
+            encoding,
+            vary: vary(asset),
+            etag: asset.map(|a| etag(a.version, encoding)),
+            last_modified: None,
+            headers,
+            versioned: false,
+        })
+    }
+
+    /// Leave `body` positioned after a leading UTF-8 byte order mark, if it
+    /// has one, returning how many bytes were skipped.
+    fn skip_bom(body: &mut Contents) -> io::Result<u64> {
+        let mut start = Vec::with_capacity(UTF8_BOM.len());
+        (&mut *body)
+            .take(UTF8_BOM.len() as u64)
+            .read_to_end(&mut start)?;
+
+        if start == UTF8_BOM {
+            Ok(UTF8_BOM.len() as u64)
+        } else {
+            body.seek(SeekFrom::Start(0))?;
+            Ok(0)
+        }
+    }
+}
+
+/// Where the body of a [`FileResponse`] comes from.
+#[derive(Debug)]
+enum Contents {
+    File(File),
+    /// Read out of a zip archive, which can't be streamed from without
+    /// holding its lock for the whole response.
+    Memory(Cursor<Vec<u8>>),
+    /// Embedded in the map by the generator.
+    Static(Cursor<&'static [u8]>),
+    /// Read in ahead of time by `StaticFiles::warm`.
+    Shared(Cursor<Arc<[u8]>>),
+    /// Embedded gzipped, and decompressed as it's read. Keeps the compressed
+    /// bytes around to start over from.
+    Gunzip(GzDecoder<&'static [u8]>, &'static [u8]),
+}
+
+impl Read for Contents {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self {
+            Contents::File(file) => file.read(buf),
+            Contents::Memory(cursor) => cursor.read(buf),
+            Contents::Static(cursor) => cursor.read(buf),
+            Contents::Shared(cursor) => cursor.read(buf),
+            Contents::Gunzip(decoder, _) => decoder.read(buf),
+        }
+    }
+}
+
+impl Seek for Contents {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        match self {
+            Contents::File(file) => file.seek(pos),
+            Contents::Memory(cursor) => cursor.seek(pos),
+            Contents::Static(cursor) => cursor.seek(pos),
+            Contents::Shared(cursor) => cursor.seek(pos),
+            // Only rewinding is needed, for `skip_bom`.
+            Contents::Gunzip(decoder, compressed) => match pos {
+                SeekFrom::Start(0) => {
+                    *decoder = GzDecoder::new(*compressed);
+                    Ok(0)
+                }
+                _ => Err(io::Error::new(
+                    io::ErrorKind::Other,
+                    "can only rewind a decompressed body",
+                )),
+            },
+        }
+    }
+}
+
+/// A file held in memory by `StaticFiles::warm`, with what's needed to answer
+/// for it without touching the disk.
+#[derive(Debug, Clone)]
+pub(crate) struct Warm {
+    contents: Arc<[u8]>,
+    modified: Option<u64>,
+    etag: Option<String>,
+}
+
+impl Warm {
+    /// Read in the file at `path`.
+    pub(crate) fn load(path: &Path) -> Result<Self, Error> {
+        let (mut file, metadata) = open_checked(path)?;
+
+        let mut contents = Vec::with_capacity(metadata.len() as usize);
+        file.read_to_end(&mut contents).context(Io)?;
+
+        Ok(Warm {
+            contents: contents.into(),
+            modified: metadata.modified().ok().map(httpdate::unix_secs),
+            etag: weak_etag(&metadata),
+        })
+    }
+}
+
+/// Whether responses for `asset` depend on `Accept-Encoding`.
+pub(crate) fn vary(asset: Option<&Asset>) -> bool {
+    asset.map(Asset::has_variants).unwrap_or(false)
+}
+
+/// Open `path` and make sure the handle refers to a regular file.
+///
+/// Everything after this works from the handle, so the file can't be swapped
+/// out between checking its size and reading it. `path` can still be replaced
+/// between the bounds check in `serve_static` and this open; on Unix that is
+/// detected by comparing the handle with whatever `path` names afterwards,
+/// which narrows (but cannot close) the window.
+fn open_checked(path: &Path) -> Result<(File, Metadata), Error> {
+    let file = File::open(path).context(Io)?;
+    let metadata = file.metadata().context(Io)?;
+
+    ensure!(metadata.is_file(), NotAFile);
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::MetadataExt;
+
+        let current = std::fs::metadata(path).context(Io)?;
+        ensure!(
+            current.dev() == metadata.dev() && current.ino() == metadata.ino(),
+            Replaced,
+        );
+    }
+
+    Ok((file, metadata))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::testing::TempDir;
+
+    #[test]
+    fn reads_through_the_handle_after_the_path_is_replaced() {
+        let dir = TempDir::new();
+        let path = dir.write("app.js", "old");
+
+        let (mut file, metadata) = open_checked(&path).unwrap();
+        assert_eq!(metadata.len(), 3);
+
+        // A deploy renaming a new version into place.
+        let next = dir.write("app.js.next", "newer");
+        std::fs::rename(&next, &path).unwrap();
+
+        let mut contents = String::new();
+        file.read_to_string(&mut contents).unwrap();
+        assert_eq!(contents, "old");
+    }
+
+    #[test]
+    fn refuses_to_open_directories() {
+        let dir = TempDir::new();
+        std::fs::create_dir(dir.path().join("css")).unwrap();
+
+        match open_checked(&dir.path().join("css")) {
+            Err(Error::NotAFile) => (),
+            other => panic!("expected NotAFile, got {:?}", other),
+        }
+    }
+}
@@ -0,0 +1,201 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/. */
+
+//! Fixtures shared by the unit tests.
+
+// Not every helper is used under every set of features.
+#![allow(dead_code)]
+
+use crate::asset::Asset;
+use crate::config::Config;
+use crate::fairing::StaticFilesFairing;
+use crate::map::{AssetMap, SortedMap};
+
+use rocket::config::{Config as RocketConfig, Environment, LoggingLevel, Value};
+use rocket::error::LaunchErrorKind;
+use rocket::local::Client;
+use rocket::Rocket;
+
+#[cfg(feature = "log")]
+use std::cell::RefCell;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+#[cfg(feature = "log")]
+use std::sync::Once;
+
+/// An `Asset` with nothing set, to fill out map entries in tests with
+/// `Asset { version: "...", ..ASSET }`.
+pub(crate) const ASSET: Asset = Asset {
+    version: "",
+    gzip: None,
+    brotli: None,
+    zstd: None,
+    integrity: None,
+    embedded: None,
+    embedded_size: None,
+};
+
+/// A map built at runtime, for tokens that aren't known in advance.
+pub(crate) fn leak_map(mut entries: Vec<(&'static str, Asset)>) -> &'static dyn AssetMap {
+    entries.sort_by_key(|(path, _)| *path);
+    let entries = Box::leak(entries.into_boxed_slice());
+    Box::leak(Box::new(SortedMap::new(entries)))
+}
+
+/// `text`, for as long as the process runs, for map entries built at runtime.
+pub(crate) fn leak(text: &str) -> &'static str {
+    Box::leak(text.to_owned().into_boxed_str())
+}
+
+/// A fresh directory under the system's temporary directory, removed again
+/// when dropped.
+#[derive(Debug)]
+pub(crate) struct TempDir(PathBuf);
+
+impl TempDir {
+    pub(crate) fn new() -> Self {
+        static NEXT: AtomicUsize = AtomicUsize::new(0);
+
+        let name = format!(
+            "rocket-static-files-{}-{}",
+            std::process::id(),
+            NEXT.fetch_add(1, Ordering::SeqCst)
+        );
+
+        let path = std::env::temp_dir().join(name);
+        let _ = fs::remove_dir_all(&path);
+        fs::create_dir_all(&path).unwrap();
+
+        // Canonical, like `serve_from` once attached.
+        TempDir(path.canonicalize().unwrap())
+    }
+
+    pub(crate) fn path(&self) -> &Path {
+        &self.0
+    }
+
+    /// Write `contents` to `path` (relative to the directory), creating any
+    /// directories it's in.
+    pub(crate) fn write<P: AsRef<Path>, C: AsRef<[u8]>>(&self, path: P, contents: C) -> PathBuf {
+        let full = self.0.join(path);
+        fs::create_dir_all(full.parent().unwrap()).unwrap();
+        fs::write(&full, contents).unwrap();
+        full
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.0);
+    }
+}
+
+/// The `static_files` table serving `dir` at `/static`, plus the TOML in
+/// `extra`.
+fn table(dir: &Path, extra: &str) -> Value {
+    let toml = format!(
+        "serve_from = {:?}\npath_prefix = \"/static\"\n{}",
+        dir.to_str().unwrap(),
+        extra
+    );
+
+    toml.parse().unwrap()
+}
+
+/// The config [`rocket`] would attach with.
+pub(crate) fn config(dir: &Path, extra: &str) -> Config {
+    table(dir, extra).try_into().unwrap()
+}
+
+/// A rocket serving `dir` at `/static`, with the TOML in `extra` added to its
+/// `static_files` table, and nothing attached yet.
+pub(crate) fn rocket(dir: &Path, extra: &str) -> Rocket {
+    rocket_from(table(dir, extra))
+}
+
+/// A rocket whose whole `static_files` table is the TOML in `toml`.
+pub(crate) fn rocket_toml(toml: &str) -> Rocket {
+    rocket_from(toml.parse().unwrap())
+}
+
+fn rocket_from(table: Value) -> Rocket {
+    let config = RocketConfig::build(Environment::Development)
+        .log_level(LoggingLevel::Off)
+        .extra("static_files", table)
+        .finalize()
+        .unwrap();
+
+    rocket::custom(config)
+}
+
+/// A client for [`rocket`], with `fairing` attached.
+pub(crate) fn client(fairing: StaticFilesFairing, dir: &Path, extra: &str) -> Client {
+    Client::new(rocket(dir, extra).attach(fairing)).unwrap()
+}
+
+/// A [`TempDir`] holding `files` (each a path and its contents), and a
+/// [`client`] serving it with `fairing` attached. The directory is removed
+/// once dropped, so keep it for as long as the client is used.
+pub(crate) fn serve(
+    files: &[(&str, &str)],
+    fairing: StaticFilesFairing,
+    extra: &str,
+) -> (TempDir, Client) {
+    let dir = TempDir::new();
+    for (path, contents) in files {
+        dir.write(path, contents);
+    }
+
+    let client = client(fairing, dir.path(), extra);
+    (dir, client)
+}
+
+/// Whether launching `rocket` fails because a fairing refused to attach.
+pub(crate) fn fails_to_launch(rocket: Rocket) -> bool {
+    match Client::new(rocket) {
+        // Looked at, so it doesn't panic when dropped.
+        Err(error) => matches!(error.kind(), LaunchErrorKind::FailedFairings(_)),
+        Ok(_) => false,
+    }
+}
+
+#[cfg(feature = "log")]
+thread_local! {
+    static LOGS: RefCell<Vec<String>> = RefCell::new(Vec::new());
+}
+
+/// Keeps each thread's log messages for [`take_logs`]. Requests through a
+/// local `Client` are handled on the calling thread, so tests running in
+/// parallel don't see each other's.
+#[cfg(feature = "log")]
+struct Capture;
+
+#[cfg(feature = "log")]
+impl log::Log for Capture {
+    fn enabled(&self, _: &log::Metadata) -> bool {
+        true
+    }
+
+    fn log(&self, record: &log::Record) {
+        let _ = LOGS.try_with(|logs| logs.borrow_mut().push(record.args().to_string()));
+    }
+
+    fn flush(&self) {}
+}
+
+/// Everything logged on this thread since the last call. Call it once first
+/// to start capturing.
+#[cfg(feature = "log")]
+pub(crate) fn take_logs() -> Vec<String> {
+    static INSTALL: Once = Once::new();
+    static CAPTURE: Capture = Capture;
+
+    INSTALL.call_once(|| {
+        log::set_logger(&CAPTURE).unwrap();
+        log::set_max_level(log::LevelFilter::Trace);
+    });
+
+    LOGS.with(|logs| logs.borrow_mut().split_off(0))
+}
@@ -0,0 +1,243 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/. */
+
+#[cfg(feature = "zip")]
+use crate::gen::ZipSource;
+use crate::gen::{self, Error, Filesystem, Generator, TokenEncoding};
+use crate::map::AssetMap;
+
+use std::collections::HashSet;
+use std::path::PathBuf;
+
+/// A difference between the files on disk and the map compiled into the
+/// binary, as found by [`StaticFiles::verify`](crate::StaticFiles::verify).
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Mismatch {
+    /// The file is in the map, but not on disk.
+    Missing {
+        /// Path relative to `serve_from`.
+        path: String,
+    },
+
+    /// The file is on disk, but not in the map.
+    Untracked {
+        /// Path relative to `serve_from`.
+        path: String,
+    },
+
+    /// The file's contents no longer match its version token.
+    Changed {
+        /// Path relative to `serve_from`.
+        path: String,
+    },
+}
+
+impl Mismatch {
+    fn path(&self) -> &str {
+        match self {
+            Mismatch::Missing { path } => path,
+            Mismatch::Untracked { path } => path,
+            Mismatch::Changed { path } => path,
+        }
+    }
+}
+
+pub(crate) fn verify(
+    dirs: &[PathBuf],
+    hashes: &[&'static dyn AssetMap],
+    (k0, k1): (u64, u64),
+    dotfiles: &[String],
+) -> Result<Vec<Mismatch>, Error> {
+    let filesystem = dirs[1..]
+        .iter()
+        .fold(Filesystem::quiet(&dirs[0]), Filesystem::fallback);
+
+    #[cfg(feature = "zip")]
+    let (mut files, _) = if dirs[0].is_file() {
+        Generator::with_source(ZipSource::open(&dirs[0])?)
+            .sip_keys(k0, k1)
+            .dotfiles_allowlist(dotfiles)
+            .scan()?
+    } else {
+        Generator::with_source(filesystem)
+            .sip_keys(k0, k1)
+            .dotfiles_allowlist(dotfiles)
+            .scan()?
+    };
+
+    #[cfg(not(feature = "zip"))]
+    let (mut files, _) = Generator::with_source(filesystem)
+        .sip_keys(k0, k1)
+        .dotfiles_allowlist(dotfiles)
+        .scan()?;
+    gen::drop_built_siblings(&mut files, |path| {
+        hashes.iter().any(|h| h.get(path).is_some())
+    });
+
+    let mut mismatches = Vec::new();
+
+    for (path, entry) in &files {
+        let asset = hashes.iter().find_map(|h| h.get(path.as_str()));
+
+        // The map could have been generated with any token encoding.
+        let unchanged = asset.map(|a| {
+            TokenEncoding::ALL
+                .iter()
+                .any(|e| e.encode(entry.hash) == a.version)
+        });
+
+        match unchanged {
+            None => mismatches.push(Mismatch::Untracked { path: path.clone() }),
+            Some(false) => mismatches.push(Mismatch::Changed { path: path.clone() }),
+            Some(true) => (),
+        }
+    }
+
+    let mut seen = HashSet::new();
+    for path in hashes.iter().flat_map(|h| h.entries().map(|(k, _)| k)) {
+        if !files.contains_key(path) && seen.insert(path) {
+            mismatches.push(Mismatch::Missing {
+                path: path.to_string(),
+            });
+        }
+    }
+
+    mismatches.sort_by(|a, b| a.path().cmp(b.path()));
+    Ok(mismatches)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::asset::Asset;
+    use crate::testing::{leak_map, TempDir, ASSET};
+
+    #[test]
+    fn flags_changed_missing_and_untracked_files() {
+        let dir = TempDir::new();
+        dir.write("a.txt", "a");
+        dir.write("b.txt", "b");
+
+        let (files, _) = Generator::with_source(Filesystem::quiet(dir.path()))
+            .scan()
+            .unwrap();
+        let token = |path: &str| -> &'static str { Box::leak(files[path].token.clone().into()) };
+
+        let map = leak_map(vec![
+            (
+                "a.txt",
+                Asset {
+                    version: token("a.txt"),
+                    ..ASSET
+                },
+            ),
+            (
+                "b.txt",
+                Asset {
+                    version: token("b.txt"),
+                    ..ASSET
+                },
+            ),
+            (
+                "gone.txt",
+                Asset {
+                    version: "v1",
+                    ..ASSET
+                },
+            ),
+        ]);
+
+        dir.write("b.txt", "changed");
+        dir.write("extra.txt", "extra");
+
+        assert_eq!(
+            verify(&[dir.path().to_owned()], &[map], (0, 0), &[]).unwrap(),
+            [
+                Mismatch::Changed {
+                    path: "b.txt".into()
+                },
+                Mismatch::Untracked {
+                    path: "extra.txt".into()
+                },
+                Mismatch::Missing {
+                    path: "gone.txt".into()
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn rehashes_with_the_keys_the_map_was_generated_with() {
+        let dir = TempDir::new();
+        dir.write("a.txt", "a");
+
+        let (files, _) = Generator::with_source(Filesystem::quiet(dir.path()))
+            .sip_keys(3, 5)
+            .scan()
+            .unwrap();
+        let token: &'static str = Box::leak(files["a.txt"].token.clone().into());
+
+        let map = leak_map(vec![(
+            "a.txt",
+            Asset {
+                version: token,
+                ..ASSET
+            },
+        )]);
+
+        let dirs = [dir.path().to_owned()];
+        assert!(verify(&dirs, &[map], (3, 5), &[]).unwrap().is_empty());
+        assert_eq!(
+            verify(&dirs, &[map], (0, 0), &[]).unwrap(),
+            [Mismatch::Changed {
+                path: "a.txt".into()
+            }]
+        );
+    }
+
+    #[test]
+    fn siblings_are_only_checked_when_the_map_has_them() {
+        let dir = TempDir::new();
+        dir.write("site.css", "body {}");
+        dir.write("site.css.gz", "written by the build");
+        dir.write("site.css.br", "hashed by the build");
+
+        let (files, _) = Generator::with_source(Filesystem::quiet(dir.path()))
+            .scan()
+            .unwrap();
+        let token = |path: &str| -> &'static str { Box::leak(files[path].token.clone().into()) };
+
+        let map = leak_map(vec![
+            (
+                "site.css",
+                Asset {
+                    version: token("site.css"),
+                    gzip: Some(20),
+                    ..ASSET
+                },
+            ),
+            (
+                "site.css.br",
+                Asset {
+                    version: token("site.css.br"),
+                    ..ASSET
+                },
+            ),
+        ]);
+
+        let dirs = [dir.path().to_owned()];
+        assert!(verify(&dirs, &[map], (0, 0), &[]).unwrap().is_empty());
+
+        dir.write("site.css.gz", "written again");
+        dir.write("site.css.br", "changed");
+        assert_eq!(
+            verify(&dirs, &[map], (0, 0), &[]).unwrap(),
+            [Mismatch::Changed {
+                path: "site.css.br".into()
+            }]
+        );
+    }
+}